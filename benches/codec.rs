@@ -0,0 +1,90 @@
+// Compares the codec with RequestBin and parse_response, on the messages an
+// animation sends and receives most: SetColor and State.
+//
+//     cargo bench --bench codec
+
+extern crate rustylifx;
+
+use rustylifx::codec::{self, Header};
+use rustylifx::colour::Hsbk;
+use rustylifx::messages::set_colour_payload;
+use rustylifx::request::{Frame, FrameAddress, ProtocolHeader, Request, RequestBin};
+use rustylifx::response::{parse_response, ResponseData};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200_000;
+
+fn main() {
+    let hsbk = Hsbk::new(21845, 65535, 32768, 3500);
+
+    let request_bin = bench("encode SetColor, RequestBin", || {
+        let msg = Request::new(header(102), set_colour_payload(&hsbk, 250));
+        RequestBin::from(msg).0.len()
+    });
+    let codec = bench("encode SetColor, codec", || {
+        let mut buf = [0u8; 64];
+        codec::encode_with(&header(102), &mut buf, |w| {
+            w.u8(0)?;
+            w.hsbk(hsbk.hue, hsbk.saturation, hsbk.brightness, hsbk.kelvin)?;
+            w.u32(250)
+        })
+        .unwrap()
+    });
+    compare(request_bin, codec);
+
+    let mut state = [0u8; 88];
+    let len = codec::encode_with(&header(107), &mut state, |w| {
+        w.hsbk(hsbk.hue, hsbk.saturation, hsbk.brightness, hsbk.kelvin)?;
+        w.u16(0)?;
+        w.u16(65535)?;
+        w.bytes(b"Desk")?;
+        w.zeros(28 + 8)
+    })
+    .unwrap();
+    let state = &state[..len];
+
+    let parse = bench("decode State, parse_response", || {
+        parse_response(ResponseData(state.to_vec())).sequence_number
+    });
+    let codec = bench("decode State, codec", || {
+        let (header, mut r) = codec::decode(state).unwrap();
+        let hsbk = r.hsbk().unwrap();
+        r.skip(2).unwrap();
+        let power = r.u16().unwrap();
+        let label = r.bytes(32).unwrap();
+        (header.frame_address.sequence, hsbk, power, label.len())
+    });
+    compare(parse, codec);
+}
+
+fn header(message_type: u16) -> Header {
+    Header::new(
+        Frame::new(0, false, true, 1024, 321),
+        FrameAddress::new([0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0], [0; 6], 0, false, true, 7),
+        ProtocolHeader::new(0, message_type, 0),
+    )
+}
+
+// Runs f repeatedly, printing and returning the time per iteration.
+fn bench<T, F: FnMut() -> T>(name: &str, mut f: F) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        black_box(f());
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let each = start.elapsed() / ITERATIONS;
+    println!("{:<32} {:>8} ns", name, each.as_nanos());
+    each
+}
+
+fn compare(before: Duration, after: Duration) {
+    println!(
+        "{:<32} {:>8.1}x\n",
+        "speedup",
+        before.as_secs_f64() / after.as_secs_f64().max(1e-12)
+    );
+}
@@ -3,6 +3,7 @@ extern crate rustylifx;
 use rustylifx::network::Device;
 use rustylifx::{colour, messages, response};
 
+use std::net::Ipv4Addr;
 use std::process;
 use std::thread;
 use std::time::Duration;
@@ -30,7 +31,7 @@ fn main() {
 }
 
 fn find_device() -> Device {
-    let device = match messages::get_service() {
+    let device = match messages::get_service(Ipv4Addr::new(192, 168, 1, 255)) {
         Ok(v) => v,
         Err(e) => {
             println!("failed getting device power state: {:?}", e);
@@ -39,7 +40,7 @@ fn find_device() -> Device {
     };
 
     match device.response {
-        Some(ref resp) => display_response("State service", &resp),
+        Some(ref resp) => display_response("State service", resp),
         None => panic!("no response"),
     };
 
@@ -70,34 +71,31 @@ fn parse_hsvk(device: &Device) {
         _ => None,
     };
 
-    match payload {
-        Some(v) => {
-            println!("current payload body: {:?}", v.body);
-            println!("current hue: {:?}", v.hsbk.hue);
-            println!(
-                "current hue degrees: {:?}º",
-                colour::hue_word_to_degrees(v.hsbk.hue)
-            );
-            println!("current sat: {:?}", v.hsbk.saturation);
-            println!(
-                "current sat percent: {:?}%",
-                colour::saturation_word_to_percent(v.hsbk.saturation as u16)
-            );
-            println!("current bri: {:?}", v.hsbk.brightness);
-            println!(
-                "current bri percent: {:?}%",
-                colour::brightness_word_to_percent(v.hsbk.brightness as u16)
-            );
-            println!("current kel: {:?}", v.hsbk.kelvin);
-        }
-        None => (),
-    };
+    if let Some(v) = payload {
+        println!("current payload body: {:?}", v.body);
+        println!("current hue: {:?}", v.hsbk.hue);
+        println!(
+            "current hue degrees: {:?}º",
+            colour::hue_word_to_degrees(v.hsbk.hue)
+        );
+        println!("current sat: {:?}", v.hsbk.saturation);
+        println!(
+            "current sat percent: {:?}%",
+            colour::saturation_word_to_percent(v.hsbk.saturation)
+        );
+        println!("current bri: {:?}", v.hsbk.brightness);
+        println!(
+            "current bri percent: {:?}%",
+            colour::brightness_word_to_percent(v.hsbk.brightness)
+        );
+        println!("current kel: {:?}", v.hsbk.kelvin);
+    }
     println!("\n");
 }
 
 fn change_colour(device: Device) {
     // Use constants.
-    let cols: Vec<colour::HSB> = vec![
+    let cols: Vec<colour::Hsb> = vec![
         colour::get_colour("red"),
         colour::get_colour("green"),
         colour::get_colour("blue"),
@@ -109,7 +107,7 @@ fn change_colour(device: Device) {
     }
 
     // Use RGB.
-    let rgb_orange = colour::rgb_to_hsv(colour::RGB {
+    let rgb_orange = colour::rgb_to_hsv(colour::Rgb {
         red: 255,
         green: 165,
         blue: 0,
@@ -118,7 +116,7 @@ fn change_colour(device: Device) {
     thread::sleep(Duration::from_millis(1000));
 
     // More constants.
-    let cols: Vec<colour::HSB> = vec![
+    let cols: Vec<colour::Hsb> = vec![
         colour::get_colour("beige"),
         colour::get_colour("chartreuse"),
         colour::get_colour("coral"),
@@ -143,13 +141,9 @@ fn display_response(title: &str, resp: &response::Response) {
     println!("Size: {}", resp.size);
     println!("Source: {:?}", resp.source);
     println!("Mac addr: {:?}", resp.mac_address);
-    println!("Firmware: {:?}", resp.firmware);
-
-    // packed byte
     println!("Sequence num: {:?}", resp.sequence_number);
-    println!("Reserved_1 (timestamp?): {:?}", resp.reserved_1);
     println!("Message type: {:?}", resp.message_type);
-    println!("Reserved_2: {:?}", resp.reserved_2);
+    println!("Header: {:?}", resp.header);
 
     // println!("Service: {:?}", resp.service);
     // println!("Port: {:?}", resp.port);
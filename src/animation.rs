@@ -0,0 +1,536 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use colour::Hsbk;
+use gradient::{self, Interpolation};
+use light::Light;
+use messages::{self, ZoneApply};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The most zones a single frame can set, the most one SetExtendedColorZones holds.
+pub const MAX_FRAME_ZONES: usize = 82;
+
+/// What a light shows for one frame of an animation.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Frame {
+    /// The whole light in one colour.
+    Colour(Hsbk),
+    /// Each zone of a multizone light, from the first zone.
+    Zones(Vec<Hsbk>),
+}
+
+/// A source of frames. Given the time since the animation started, not counting
+/// time spent paused, returns the frame to show, or None once it has finished.
+pub trait Animation: Send {
+    fn frame(&mut self, t: Duration) -> Option<Frame>;
+}
+
+impl<F: FnMut(Duration) -> Option<Frame> + Send> Animation for F {
+    fn frame(&mut self, t: Duration) -> Option<Frame> {
+        self(t)
+    }
+}
+
+/// Frames at points in time, blended in between.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Timeline {
+    keyframes: Vec<(Duration, Frame)>,
+    interpolation: Interpolation,
+    looped: bool,
+    finished: bool,
+}
+
+impl Timeline {
+    pub fn new(interpolation: Interpolation) -> Timeline {
+        Timeline {
+            keyframes: vec![],
+            interpolation,
+            looped: false,
+            finished: false,
+        }
+    }
+
+    /// Adds a frame to show at the time from the start.
+    pub fn keyframe(mut self, at: Duration, frame: Frame) -> Timeline {
+        let i = self.keyframes.partition_point(|&(t, _)| t <= at);
+        self.keyframes.insert(i, (at, frame));
+        self
+    }
+
+    /// Starts again from the beginning after the last keyframe, forever. The
+    /// last keyframe should match the first, for the loop to be seamless.
+    pub fn looped(mut self) -> Timeline {
+        self.looped = true;
+        self
+    }
+
+    /// Cycles through the colours, one period per full cycle, and back to the
+    /// first colour, forever.
+    pub fn cycle(colours: &[Hsbk], period: Duration, interpolation: Interpolation) -> Timeline {
+        let mut timeline = Timeline::new(interpolation).looped();
+        if colours.is_empty() {
+            return timeline;
+        }
+
+        let step = period / colours.len() as u32;
+        for (i, c) in colours.iter().enumerate() {
+            timeline = timeline.keyframe(step * i as u32, Frame::Colour(*c));
+        }
+        timeline.keyframe(period, Frame::Colour(colours[0]))
+    }
+
+    /// The time of the last keyframe.
+    pub fn length(&self) -> Duration {
+        self.keyframes.last().map_or(Duration::ZERO, |&(t, _)| t)
+    }
+}
+
+impl Animation for Timeline {
+    fn frame(&mut self, t: Duration) -> Option<Frame> {
+        let length = self.length();
+        if self.finished || self.keyframes.is_empty() {
+            return None;
+        }
+
+        let t = match (self.looped, length.as_nanos()) {
+            (true, n) if n > 0 => Duration::from_nanos((t.as_nanos() % n) as u64),
+            _ => t,
+        };
+
+        // Past the end, the last frame is shown once more, so it isn't skipped.
+        if t >= length {
+            self.finished = !self.looped;
+            return self.keyframes.last().map(|(_, f)| f.clone());
+        }
+
+        let next = self.keyframes.partition_point(|&(at, _)| at <= t);
+        if next == 0 {
+            return Some(self.keyframes[0].1.clone());
+        }
+
+        let (from_at, ref from) = self.keyframes[next - 1];
+        let (to_at, ref to) = self.keyframes[next];
+        let progress = (t - from_at).as_secs_f64() / (to_at - from_at).as_secs_f64();
+        Some(mix_frames(from, to, progress, self.interpolation))
+    }
+}
+
+/// A band of colour moving along a multizone light, over a background, one pass
+/// per period.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chase {
+    pub colour: Hsbk,
+    pub background: Hsbk,
+    pub zones: usize,
+    pub width: usize,
+    pub period: Duration,
+}
+
+impl Animation for Chase {
+    fn frame(&mut self, t: Duration) -> Option<Frame> {
+        if self.zones == 0 || self.period.is_zero() {
+            return None;
+        }
+
+        let pass = (t.as_secs_f64() / self.period.as_secs_f64()).fract();
+        let head = (pass * self.zones as f64) as usize;
+        let zones = (0..self.zones)
+            .map(|i| {
+                // Zones behind the head, wrapping round to the far end.
+                let behind = (head + self.zones - i) % self.zones;
+                if behind < self.width {
+                    self.colour
+                } else {
+                    self.background
+                }
+            })
+            .collect();
+        Some(Frame::Zones(zones))
+    }
+}
+
+/// A colour flickering like a candle flame, with brightness wandering between
+/// 60% and 100% of the colour's own.
+#[derive(Debug, Clone)]
+pub struct Candle {
+    colour: Hsbk,
+    state: u64,
+    level: f64,
+}
+
+impl Candle {
+    pub fn new(colour: Hsbk) -> Candle {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Candle::with_seed(colour, nanos)
+    }
+
+    /// A candle that flickers the same way each time, for the same seed.
+    pub fn with_seed(colour: Hsbk, seed: u64) -> Candle {
+        Candle {
+            colour,
+            state: seed | 1,
+            level: 1.0,
+        }
+    }
+
+    // Xorshift, from 0 to 1. Good enough for a flame.
+    fn random(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Animation for Candle {
+    fn frame(&mut self, _: Duration) -> Option<Frame> {
+        // A random walk, pulled back towards full brightness.
+        let step = (self.random() - 0.5) * 0.3 + (1.0 - self.level) * 0.2;
+        self.level = (self.level + step).clamp(0.6, 1.0);
+
+        let brightness = (f64::from(self.colour.brightness) * self.level).round() as u16;
+        Some(Frame::Colour(Hsbk {
+            brightness,
+            ..self.colour
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// Plays an animation on a light from a background thread.
+///
+/// Frames are sent without waiting for acknowledgement, through the client's
+/// rate limited queue, where a frame the light can't take yet is replaced by
+/// the next. Each frame's transition lasts one frame, so the light fades
+/// smoothly from frame to frame rather than stepping. Zones frames use
+/// SetExtendedColorZones, which needs recent multizone firmware.
+pub struct Animator {
+    control: Arc<(Mutex<Control>, Condvar)>,
+    finished: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Animator {
+    /// Starts playing the animation, at up to fps frames per second. The frame
+    /// rate is capped at the client's rate limit.
+    pub fn start<A: Animation + 'static>(light: Light, animation: A, fps: f64) -> Animator {
+        let fps = fps.min(light.client().messages_per_second()).max(0.1);
+        let control = Arc::new((Mutex::new(Control::Running), Condvar::new()));
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let worker = {
+            let control = control.clone();
+            let finished = finished.clone();
+            thread::spawn(move || {
+                play(&light, animation, fps, &control);
+                finished.store(true, Ordering::SeqCst);
+            })
+        };
+
+        Animator {
+            control,
+            finished,
+            worker: Some(worker),
+        }
+    }
+
+    /// Stops sending frames, leaving the light as it is, until resumed. The
+    /// animation's clock stops too.
+    pub fn pause(&self) {
+        self.set_control(Control::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.set_control(Control::Running);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.control.0.lock().unwrap() == Control::Paused
+    }
+
+    /// Whether the animation has finished, or been cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    /// Stops the animation for good, leaving the light showing the last frame
+    /// sent, and waits for the background thread.
+    pub fn cancel(&mut self) {
+        self.set_control(Control::Cancelled);
+        self.join();
+    }
+
+    /// Waits for the animation to finish by itself.
+    pub fn join(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    fn set_control(&self, to: Control) {
+        let (ref control, ref changed) = *self.control;
+        let mut control = control.lock().unwrap();
+        if *control != Control::Cancelled {
+            *control = to;
+        }
+        changed.notify_all();
+    }
+}
+
+impl Drop for Animator {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn play<A: Animation>(
+    light: &Light,
+    mut animation: A,
+    fps: f64,
+    control: &(Mutex<Control>, Condvar),
+) {
+    let interval = Duration::from_secs_f64(1.0 / fps);
+    let duration = interval.as_millis() as u32;
+    let (ref state, ref changed) = *control;
+
+    let mut clock = Duration::ZERO;
+    let mut last = Instant::now();
+    loop {
+        {
+            let mut state = state.lock().unwrap();
+            if *state == Control::Paused {
+                while *state == Control::Paused {
+                    state = changed.wait(state).unwrap();
+                }
+                // Time spent paused doesn't count.
+                last = Instant::now();
+            }
+            if *state == Control::Cancelled {
+                return;
+            }
+        }
+
+        let now = Instant::now();
+        clock += now - last;
+        last = now;
+
+        match animation.frame(clock) {
+            Some(frame) => send(light, &frame, duration),
+            None => return,
+        }
+
+        // Sleep until the next frame, waking early to pause or cancel.
+        let next = now + interval;
+        let mut state = state.lock().unwrap();
+        while *state == Control::Running {
+            let now = Instant::now();
+            if now >= next {
+                break;
+            }
+            state = changed.wait_timeout(state, next - now).unwrap().0;
+        }
+    }
+}
+
+// Sends a frame, with kelvin clamped to the light's product range as `Light`
+// does.
+fn send(light: &Light, frame: &Frame, duration: u32) {
+    let (message_type, payload) = match *frame {
+        Frame::Colour(ref c) => (102, messages::set_colour_payload(&light.clamp(c), duration)),
+        Frame::Zones(ref zones) => {
+            let zones = light.clamp_all(&zones[..zones.len().min(MAX_FRAME_ZONES)]);
+            (
+                510,
+                messages::set_extended_colour_zones_payload(duration, ZoneApply::Apply, 0, &zones),
+            )
+        }
+    };
+    light
+        .client()
+        .set_latest(light.socket_addr(), light.target(), message_type, payload);
+}
+
+// Blends two frames. A single colour blends with zones as if every zone were
+// that colour. Zones of different lengths can't be blended, so step instead.
+fn mix_frames(from: &Frame, to: &Frame, t: f64, interpolation: Interpolation) -> Frame {
+    match (from, to) {
+        (Frame::Colour(a), Frame::Colour(b)) => {
+            Frame::Colour(gradient::mix(a, b, t, interpolation))
+        }
+        (Frame::Colour(a), Frame::Zones(b)) => Frame::Zones(
+            b.iter()
+                .map(|z| gradient::mix(a, z, t, interpolation))
+                .collect(),
+        ),
+        (Frame::Zones(a), Frame::Colour(b)) => Frame::Zones(
+            a.iter()
+                .map(|z| gradient::mix(z, b, t, interpolation))
+                .collect(),
+        ),
+        (Frame::Zones(a), Frame::Zones(b)) if a.len() == b.len() => Frame::Zones(
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| gradient::mix(x, y, t, interpolation))
+                .collect(),
+        ),
+        _ => from.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{send, Animation, Animator, Candle, Chase, Frame, Timeline};
+    use client::Client;
+    use colour::Hsbk;
+    use gradient::Interpolation;
+    use light::Light;
+    use products;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn hue(degrees: f64) -> Hsbk {
+        Hsbk::from_degrees(degrees, 100.0, 100.0, 3500)
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut timeline = Timeline::new(Interpolation::Hsv)
+            .keyframe(ms(1000), Frame::Colour(hue(120.0)))
+            .keyframe(ms(0), Frame::Colour(hue(0.0)));
+
+        assert_eq!(timeline.frame(ms(0)), Some(Frame::Colour(hue(0.0))));
+        match timeline.frame(ms(500)) {
+            Some(Frame::Colour(c)) => assert!((c.hue_degrees() - 60.0).abs() < 0.01),
+            f => panic!("unexpected frame {:?}", f),
+        }
+        assert_eq!(timeline.frame(ms(1200)), Some(Frame::Colour(hue(120.0))));
+        assert_eq!(timeline.frame(ms(1300)), None);
+    }
+
+    #[test]
+    fn test_timeline_cycle_loops() {
+        let colours = [hue(0.0), hue(120.0), hue(240.0)];
+        let mut cycle = Timeline::cycle(&colours, ms(3000), Interpolation::Hsv);
+
+        assert_eq!(cycle.length(), ms(3000));
+        assert_eq!(cycle.frame(ms(1000)), Some(Frame::Colour(hue(120.0))));
+        assert_eq!(cycle.frame(ms(7000)), Some(Frame::Colour(hue(120.0))));
+        assert_eq!(cycle.frame(ms(9000)), Some(Frame::Colour(hue(0.0))));
+    }
+
+    #[test]
+    fn test_chase() {
+        let (on, off) = (hue(0.0), hue(240.0));
+        let mut chase = Chase {
+            colour: on,
+            background: off,
+            zones: 4,
+            width: 2,
+            period: ms(400),
+        };
+
+        assert_eq!(
+            chase.frame(ms(0)),
+            Some(Frame::Zones(vec![on, off, off, on]))
+        );
+        assert_eq!(
+            chase.frame(ms(200)),
+            Some(Frame::Zones(vec![off, on, on, off]))
+        );
+    }
+
+    #[test]
+    fn test_candle_stays_in_range() {
+        let colour = Hsbk::from_degrees(30.0, 80.0, 50.0, 2000);
+        let mut candle = Candle::with_seed(colour, 42);
+        for i in 0..1000 {
+            match candle.frame(ms(i)) {
+                Some(Frame::Colour(c)) => {
+                    assert!(c.brightness <= colour.brightness);
+                    assert!(c.brightness >= (f64::from(colour.brightness) * 0.6) as u16);
+                    assert_eq!((c.hue, c.kelvin), (colour.hue, colour.kelvin));
+                }
+                f => panic!("unexpected frame {:?}", f),
+            }
+        }
+    }
+
+    #[test]
+    fn test_animator_pause_and_cancel() {
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device.set_nonblocking(true).unwrap();
+        let light = Light::new(
+            Arc::new(Client::new().unwrap()),
+            device.local_addr().unwrap(),
+            [0; 8],
+        );
+        let received = |device: &UdpSocket| {
+            let mut buf = [0; 1024];
+            let mut n = 0;
+            while device.recv_from(&mut buf).is_ok() {
+                n += 1;
+            }
+            n
+        };
+
+        let mut animator = Animator::start(light, |_| Some(Frame::Colour(hue(0.0))), 20.0);
+        thread::sleep(ms(300));
+        assert!(received(&device) > 0);
+
+        animator.pause();
+        assert!(animator.is_paused());
+        thread::sleep(ms(50));
+        received(&device);
+        thread::sleep(ms(200));
+        assert_eq!(received(&device), 0);
+
+        animator.resume();
+        thread::sleep(ms(200));
+        assert!(received(&device) > 0);
+
+        animator.cancel();
+        assert!(animator.is_finished());
+    }
+
+    #[test]
+    fn test_send_clamps_kelvin() {
+        // A Mini White to Warm, which goes no cooler than 4000K.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device.set_read_timeout(Some(ms(1000))).unwrap();
+        let light = Light::new(
+            Arc::new(Client::new().unwrap()),
+            device.local_addr().unwrap(),
+            [0; 8],
+        )
+        .with_product(products::lookup(1, 50));
+
+        send(&light, &Frame::Colour(Hsbk::new(0, 0, 65535, 9000)), 0);
+        let mut buf = [0; 1024];
+        device.recv_from(&mut buf).unwrap();
+        assert_eq!(u16::from_le_bytes([buf[43], buf[44]]), 4000);
+    }
+}
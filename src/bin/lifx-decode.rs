@@ -0,0 +1,84 @@
+extern crate rustylifx;
+
+use rustylifx::decode;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::process;
+
+const USAGE: &str = "\
+Usage: lifx-decode [options] [hex]
+
+Decodes a LIFX message field by field, flagging anything out of place, such as
+reserved bits that aren't zero. The message is given in hex, with any spacing,
+colons or 0x prefixes. With no message given, each line read from stdin is
+decoded as a message.
+
+Options:
+  --file <path>       Decode the raw bytes of a file, such as a saved datagram
+  -h, --help          Show this help
+";
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("lifx-decode: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Returns whether every message decoded without warnings.
+fn run() -> Result<bool, io::Error> {
+    let mut file: Option<String> = None;
+    let mut hex: Vec<String> = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(true);
+            }
+            "--file" => match args.next() {
+                Some(f) => file = Some(f),
+                None => usage_error("--file needs a value"),
+            },
+            a if a.starts_with("--") => usage_error(&format!("unknown option {}", a)),
+            a => hex.push(a.to_string()),
+        }
+    }
+
+    if let Some(path) = file {
+        return show(&fs::read(path)?);
+    }
+    if !hex.is_empty() {
+        return show(&decode::parse_hex(&hex.join(" "))?);
+    }
+
+    let mut clean = true;
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        clean &= show(&decode::parse_hex(&line)?)?;
+        println!();
+    }
+    Ok(clean)
+}
+
+fn show(bytes: &[u8]) -> Result<bool, io::Error> {
+    let decoded = decode::decode(bytes)?;
+    print!("{}", decoded);
+    Ok(decoded.warnings().is_empty())
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx-decode: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,85 @@
+extern crate rustylifx;
+
+use rustylifx::client::Client;
+use rustylifx::metrics::{self, Exporter};
+use rustylifx::registry::{DeviceRegistry, RegistryConfig};
+
+use std::env;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: lifx-exporter [options]
+
+Polls every light on the LAN, and serves their power, colour, Wi-Fi signal,
+uptime and request counts at /metrics, for Prometheus to scrape.
+
+Options:
+  --listen <addr>     Address to serve on [default: 127.0.0.1:9778]
+  --subnet <ip>       Broadcast address to discover on [default: 255.255.255.255]
+  --poll <secs>       How often to rediscover and poll lights [default: 30]
+  -h, --help          Show this help
+";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("lifx-exporter: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), io::Error> {
+    let mut listen = "127.0.0.1:9778".to_string();
+    let mut subnet = Ipv4Addr::new(255, 255, 255, 255);
+    let mut poll = Duration::from_secs(30);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(v) => v,
+            None => usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            "--listen" => listen = value(),
+            "--subnet" => match value().parse() {
+                Ok(s) => subnet = s,
+                Err(_) => usage_error("--subnet must be an IPv4 address"),
+            },
+            "--poll" => match value().parse() {
+                Ok(s) => poll = Duration::from_secs(s),
+                Err(_) => usage_error("--poll must be a number of seconds"),
+            },
+            a => usage_error(&format!("unknown argument {}", a)),
+        }
+    }
+
+    let config = RegistryConfig {
+        poll_interval: poll,
+        ..RegistryConfig::default()
+    };
+    let mut registry = DeviceRegistry::with_config(Arc::new(Client::new()?), subnet, config);
+    registry.refresh()?;
+    registry.start();
+
+    let mut exporter = Exporter::new(Arc::new(registry));
+    exporter.poll();
+    exporter.start(poll);
+    println!(
+        "lifx-exporter: found {} lights, serving metrics on {}",
+        exporter.metrics().len(),
+        listen
+    );
+    metrics::serve(Arc::new(exporter), TcpListener::bind(&listen)?)
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx-exporter: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,95 @@
+extern crate rustylifx;
+
+use rustylifx::client::Client;
+use rustylifx::http::{self, HttpApi};
+use rustylifx::registry::{DeviceRegistry, RegistryConfig};
+
+use std::env;
+use std::io;
+use std::net::{Ipv4Addr, TcpListener};
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: lifx-http [options]
+
+Serves a local version of the LIFX HTTP API, controlling lights over the LAN:
+
+  GET  /v1/lights/:selector
+  PUT  /v1/lights/:selector/state    {\"power\": \"on\", \"color\": \"red\", \"brightness\": 0.5, \"duration\": 1}
+  POST /v1/lights/:selector/toggle   {\"duration\": 1}
+
+Selectors are all, label:<label>, group:<group>, location:<location>, id:<mac>
+and ip:<ip>.
+
+Options:
+  --listen <addr>     Address to serve on [default: 127.0.0.1:8080]
+  --subnet <ip>       Broadcast address to discover on [default: 255.255.255.255]
+  --poll <secs>       How often to rediscover and poll lights [default: 10]
+  --token <token>     Require Authorization: Bearer <token> on every request
+  -h, --help          Show this help
+";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("lifx-http: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), io::Error> {
+    let mut listen = "127.0.0.1:8080".to_string();
+    let mut subnet = Ipv4Addr::new(255, 255, 255, 255);
+    let mut poll = Duration::from_secs(10);
+    let mut token: Option<String> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(v) => v,
+            None => usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            "--listen" => listen = value(),
+            "--subnet" => match value().parse() {
+                Ok(s) => subnet = s,
+                Err(_) => usage_error("--subnet must be an IPv4 address"),
+            },
+            "--poll" => match value().parse() {
+                Ok(s) => poll = Duration::from_secs(s),
+                Err(_) => usage_error("--poll must be a number of seconds"),
+            },
+            "--token" => token = Some(value()),
+            a => usage_error(&format!("unknown argument {}", a)),
+        }
+    }
+
+    let config = RegistryConfig {
+        poll_interval: poll,
+        ..RegistryConfig::default()
+    };
+    let mut registry = DeviceRegistry::with_config(Arc::new(Client::new()?), subnet, config);
+    registry.refresh()?;
+    registry.start();
+    println!(
+        "lifx-http: found {} lights, serving on {}",
+        registry.online().len(),
+        listen
+    );
+
+    let mut api = HttpApi::new(Arc::new(registry));
+    if let Some(ref token) = token {
+        api = api.with_token(token);
+    }
+    http::serve(Arc::new(api), TcpListener::bind(&listen)?)
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx-http: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
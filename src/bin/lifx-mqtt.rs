@@ -0,0 +1,110 @@
+extern crate rustylifx;
+
+use rustylifx::client::Client;
+use rustylifx::mqtt::{BridgeConfig, MqttBridge};
+use rustylifx::registry::{DeviceRegistry, RegistryConfig};
+
+use std::env;
+use std::io;
+use std::net::Ipv4Addr;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: lifx-mqtt [options]
+
+Bridges lights on the LAN to an MQTT broker, announcing them to Home Assistant.
+Each light's state is published to <prefix>/<id>/state, and commands are taken
+from <prefix>/<id>/set and <prefix>/<id>/set/{power,colour,brightness,kelvin}.
+
+Options:
+  --broker <host:port>    Broker to connect to [default: localhost:1883]
+  --username <name>       Broker username
+  --password <password>   Broker password
+  --client-id <id>        MQTT client id [default: rustylifx]
+  --prefix <prefix>       Topic prefix [default: lifx]
+  --discovery <prefix>    Home Assistant discovery prefix [default: homeassistant]
+  --no-discovery          Don't announce lights to Home Assistant
+  --subnet <ip>           Broadcast address to discover on [default: 255.255.255.255]
+  --poll <secs>           How often to rediscover, poll and publish lights [default: 10]
+  -h, --help              Show this help
+";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("lifx-mqtt: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), io::Error> {
+    let mut config = BridgeConfig::default();
+    let mut subnet = Ipv4Addr::new(255, 255, 255, 255);
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(v) => v,
+            None => usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            "--broker" => config.broker = value(),
+            "--username" => config.connect.username = Some(value()),
+            "--password" => config.connect.password = Some(value()),
+            "--client-id" => config.client_id = value(),
+            "--prefix" => config.topic_prefix = value(),
+            "--discovery" => config.discovery_prefix = Some(value()),
+            "--no-discovery" => config.discovery_prefix = None,
+            "--subnet" => match value().parse() {
+                Ok(s) => subnet = s,
+                Err(_) => usage_error("--subnet must be an IPv4 address"),
+            },
+            "--poll" => match value().parse() {
+                Ok(s) => config.state_interval = Duration::from_secs(s),
+                Err(_) => usage_error("--poll must be a number of seconds"),
+            },
+            a => usage_error(&format!("unknown argument {}", a)),
+        }
+    }
+
+    let registry_config = RegistryConfig {
+        poll_interval: config.state_interval,
+        ..RegistryConfig::default()
+    };
+    let mut registry =
+        DeviceRegistry::with_config(Arc::new(Client::new()?), subnet, registry_config);
+    registry.refresh()?;
+    registry.start();
+    println!(
+        "lifx-mqtt: found {} lights, bridging to {}",
+        registry.online().len(),
+        config.broker
+    );
+
+    // Reconnects whenever the broker goes away, backing off up to a minute.
+    let bridge = MqttBridge::new(Arc::new(registry), config);
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        let result = bridge.run();
+        if started.elapsed() > Duration::from_secs(60) {
+            backoff = Duration::from_secs(1);
+        }
+        if let Err(e) = result {
+            eprintln!("lifx-mqtt: {}, reconnecting in {:?}", e, backoff);
+        }
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(Duration::from_secs(60));
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx-mqtt: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
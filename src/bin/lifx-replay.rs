@@ -0,0 +1,113 @@
+extern crate rustylifx;
+
+use rustylifx::capture::{self, Datagram, Direction};
+use rustylifx::network;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::net::SocketAddr;
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+
+const USAGE: &str = "\
+Usage: lifx-replay <command> <recording> [arguments]
+
+Works with recordings made by lifx --record, or Capture::to_file.
+
+Commands:
+  show <recording>               List each datagram in the recording
+  replay <recording>             Resend the recording's sent datagrams, with their
+                                 original timing, and list the replies
+  pcap <recording> <file>        Export the recording as a pcap file, for Wireshark
+
+Options:
+  --to <ip:port>      Send replayed datagrams here, instead of to the recorded
+                      device, such as to a simulator or another light
+  --wait <ms>         How long to wait for replies after the last send [default: 1000]
+  -h, --help          Show this help
+";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("lifx-replay: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), io::Error> {
+    let mut to: Option<SocketAddr> = None;
+    let mut wait = Duration::from_millis(1000);
+    let mut rest: Vec<String> = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(v) => v,
+            None => usage_error(&format!("{} needs a value", arg)),
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return Ok(());
+            }
+            "--to" => match value().parse() {
+                Ok(a) => to = Some(a),
+                Err(_) => usage_error("--to must be an ip:port address"),
+            },
+            "--wait" => match value().parse() {
+                Ok(ms) => wait = Duration::from_millis(ms),
+                Err(_) => usage_error("--wait must be a number of milliseconds"),
+            },
+            a if a.starts_with("--") => usage_error(&format!("unknown option {}", a)),
+            a => rest.push(a.to_string()),
+        }
+    }
+
+    let (command, path) = match (rest.first(), rest.get(1)) {
+        (Some(c), Some(p)) => (c.as_str(), p),
+        (None, _) => usage_error("no command given"),
+        (Some(c), None) => usage_error(&format!("{} needs a recording", c)),
+    };
+    let datagrams = capture::open_recording(path)?;
+
+    match command {
+        "show" => datagrams.iter().for_each(show),
+        "replay" => capture::replay(&datagrams, to, wait)?.iter().for_each(show),
+        "pcap" => {
+            let out = match rest.get(2) {
+                Some(o) => o,
+                None => usage_error("pcap needs a file to write"),
+            };
+            let mut writer = BufWriter::new(File::create(out)?);
+            capture::write_pcap(&mut writer, &datagrams)?;
+            println!("wrote {} datagrams to {}", datagrams.len(), out);
+        }
+        c => usage_error(&format!("unknown command {}", c)),
+    }
+    Ok(())
+}
+
+fn show(d: &Datagram) {
+    let time = d.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let arrow = match d.direction {
+        Direction::Sent => "->",
+        Direction::Received => "<-",
+    };
+    let message_type = d.bytes.get(32..34);
+    println!(
+        "{}.{:06} {} {} type {}\n    {}",
+        time.as_secs(),
+        time.subsec_micros(),
+        arrow,
+        d.peer,
+        message_type.map_or("?".to_string(), |t| u16::from_le_bytes([t[0], t[1]])
+            .to_string()),
+        network::hex_dump(&d.bytes)
+    );
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx-replay: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,600 @@
+extern crate rustylifx;
+
+use rustylifx::capture::Capture;
+use rustylifx::circadian::Circadian;
+use rustylifx::client::Client;
+use rustylifx::colour::Hsbk;
+use rustylifx::config::{Config, ScheduledAction};
+use rustylifx::group::{Delivery, GroupReport, LightGroup, Outcome};
+use rustylifx::json::Json;
+use rustylifx::light::Light;
+use rustylifx::registry::{DeviceRegistry, RegisteredDevice, RegistryConfig};
+use rustylifx::scene::Scene;
+use rustylifx::schedule::{Action, Job, Schedule};
+use rustylifx::selector::Selector;
+
+use std::env;
+use std::io;
+use std::net::Ipv4Addr;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const USAGE: &str = "\
+Usage: lifx [options] <command> [arguments]
+
+Commands:
+  discover                     List every device on the subnet
+  state <selector>             Show the state of the selected devices
+  on <selector>                Turn the selected devices on
+  off <selector>               Turn the selected devices off
+  color <selector> <colour>    Change the colour, e.g. red, #ff8800,
+                               hsb(120, 100%, 50%), kelvin:2700 brightness:30%
+  label <selector> [label]     Show, or with a label change, a device's label
+  ping <selector>              Measure the round trip time to each device
+  schedule [list]              Run the config file's schedules until stopped,
+                               or list when each next runs
+  circadian <selector>         Have the selected devices follow the sun until
+                               stopped, leaving alone any changed by hand
+
+Selectors:
+  all, label:<label>, group:<group>, location:<location>, id:<mac>, ip:<ip>,
+  a bare label, MAC or IP address, or an alias or group from the config file.
+  Named colours from the config file can be used wherever a colour is given.
+
+Options:
+  --subnet <ip>       Broadcast address to discover on [default: 255.255.255.255]
+  --wait <ms>         How long to wait for devices to answer discovery [default: 1000]
+  --timeout <ms>      How long to wait for each reply [default: 3000]
+  --duration <ms>     Transition time for changes [default: from the config, or 0]
+  --count <n>         Echo requests per device, for ping [default: 3]
+  --json              Write JSON instead of a table
+  --record <file>     Record every datagram sent and received, see lifx-replay
+  --config <file>     Config file of aliases, groups and colours, as TOML or JSON
+                      [default: $LIFX_CONFIG, or ~/.config/lifx/config.toml]
+  -h, --help          Show this help
+";
+
+struct Options {
+    subnet: Ipv4Addr,
+    wait: Duration,
+    timeout: Duration,
+    duration: Option<u32>,
+    count: u32,
+    json: bool,
+    record: Option<String>,
+    config: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (options, args) = match parse_options(&args) {
+        Ok(v) => v,
+        Err(e) => usage_error(&e),
+    };
+
+    match run(&options, &args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("lifx: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Splits options from the command and its arguments, which may be mixed.
+fn parse_options(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options {
+        subnet: Ipv4Addr::new(255, 255, 255, 255),
+        wait: Duration::from_millis(1000),
+        timeout: Duration::from_millis(3000),
+        duration: None,
+        count: 3,
+        json: false,
+        record: None,
+        config: None,
+    };
+    let mut rest: Vec<String> = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} needs a value", name))
+                .map(|v| v.to_string())
+        };
+        let millis = |name: &str, v: String| {
+            v.parse::<u64>()
+                .map(Duration::from_millis)
+                .map_err(|_| format!("{} must be a number of milliseconds", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            "--json" => options.json = true,
+            "--record" => options.record = Some(value(arg)?),
+            "--config" => options.config = Some(value(arg)?),
+            "--subnet" => {
+                options.subnet = value(arg)?
+                    .parse()
+                    .map_err(|_| "--subnet must be an IPv4 address".to_string())?
+            }
+            "--wait" => options.wait = millis(arg, value(arg)?)?,
+            "--timeout" => options.timeout = millis(arg, value(arg)?)?,
+            "--duration" => options.duration = Some(millis(arg, value(arg)?)?.as_millis() as u32),
+            "--count" => {
+                options.count = value(arg)?
+                    .parse()
+                    .map_err(|_| "--count must be a number".to_string())?
+            }
+            a if a.starts_with("--") => return Err(format!("unknown option {}", a)),
+            a => rest.push(a.to_string()),
+        }
+    }
+
+    Ok((options, rest))
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("lifx: {}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+// Returns whether every device succeeded.
+fn run(options: &Options, args: &[String]) -> Result<bool, io::Error> {
+    let command = match args.first() {
+        Some(c) => c.as_str(),
+        None => usage_error("no command given"),
+    };
+    let config = load_config(options)?;
+    let duration = options.duration.unwrap_or_else(|| {
+        config
+            .default_duration
+            .map_or(0, |d| d.as_millis().min(u32::MAX as u128) as u32)
+    });
+    let selector = || -> Vec<Selector> {
+        match args.get(1).map(|arg| config.selectors(arg)) {
+            Some(Ok(s)) => s,
+            Some(Err(e)) => usage_error(&e),
+            None => usage_error(&format!("{} needs a selector", command)),
+        }
+    };
+
+    let mut client = Client::new()?.with_timeout(options.timeout).with_retries(1);
+    if let Some(ref path) = options.record {
+        client = client.with_capture(Arc::new(Capture::to_file(path)?));
+    }
+    let client = Arc::new(client);
+    let registry_config = RegistryConfig {
+        discovery_wait: options.wait,
+        ..RegistryConfig::default()
+    };
+    let registry = DeviceRegistry::with_config(client.clone(), options.subnet, registry_config);
+    registry.add(&config.known_devices());
+
+    match command {
+        "discover" => {
+            registry.refresh()?;
+            print_devices(options, &registry.online());
+            Ok(true)
+        }
+        "state" => {
+            let devices = select(&registry, &config, &selector(), true)?;
+            print_devices(options, &devices);
+            Ok(true)
+        }
+        "on" | "off" => {
+            let devices = select(&registry, &config, &selector(), false)?;
+            let group = LightGroup::from_registered(client, &devices);
+            let report = group.set_power(command == "on", duration, Delivery::Unicast);
+            Ok(print_report(options, &devices, &report))
+        }
+        "color" | "colour" => {
+            let selector = selector();
+            if args.len() < 3 {
+                usage_error(&format!("{} needs a colour", command));
+            }
+            let colour: Hsbk = match config.colour(&args[2..].join(" ")) {
+                Ok(c) => c,
+                Err(e) => usage_error(&e.to_string()),
+            };
+            let devices = select(&registry, &config, &selector, false)?;
+            let group = LightGroup::from_registered(client, &devices);
+            let report = group.set_color(&colour, duration, Delivery::Unicast);
+            Ok(print_report(options, &devices, &report))
+        }
+        "label" => {
+            let devices = select(&registry, &config, &selector(), args.len() < 3)?;
+            if args.len() < 3 {
+                print_devices(options, &devices);
+                return Ok(true);
+            }
+            if devices.len() > 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "a label can only be given to one device at a time",
+                ));
+            }
+            let light = Light::from_registered(client, &devices[0]);
+            light.set_label(&args[2..].join(" "))?;
+            Ok(true)
+        }
+        "ping" => {
+            let devices = select(&registry, &config, &selector(), false)?;
+            Ok(ping(options, &client, &devices))
+        }
+        "circadian" => {
+            let location = match config.location {
+                Some(location) => location,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "circadian mode needs a [location] in the config file",
+                    ))
+                }
+            };
+            let devices = select(&registry, &config, &selector(), false)?;
+            let target = config.circadian.target(&location, SystemTime::now());
+            println!(
+                "Following the sun with {} device(s), now at {}",
+                devices.len(),
+                format_colour(&target)
+            );
+            let lights = devices
+                .iter()
+                .map(|d| Light::from_registered(client.clone(), d))
+                .collect();
+            Circadian::start(lights, location, config.circadian.clone()).join();
+            Ok(true)
+        }
+        "schedule" => {
+            let list = match args.get(1).map(String::as_str) {
+                None => false,
+                Some("list") => true,
+                Some(a) => usage_error(&format!("unknown argument {}", a)),
+            };
+            let schedule = schedule(&registry, &config, duration)?;
+            print_schedule(&schedule);
+            if !list {
+                schedule.start().join();
+            }
+            Ok(true)
+        }
+        c => usage_error(&format!("unknown command {}", c)),
+    }
+}
+
+// The config file given, or the default one if it exists, or else an empty config.
+fn load_config(options: &Options) -> Result<Config, io::Error> {
+    if let Some(ref path) = options.config {
+        return Config::load(path);
+    }
+    match Config::default_path() {
+        Some(ref path) if path.exists() => Config::load(path),
+        _ => Ok(Config::default()),
+    }
+}
+
+// The online devices that any of the selectors match, failing if there are
+// none. Discovery is skipped when every selector names a device with a fixed
+// address in the config, unless the devices' state is wanted.
+fn select(
+    registry: &DeviceRegistry,
+    config: &Config,
+    selectors: &[Selector],
+    state: bool,
+) -> Result<Vec<RegisteredDevice>, io::Error> {
+    let fixed = |s: &Selector| match *s {
+        Selector::Id(target) => config
+            .devices
+            .iter()
+            .any(|d| d.target[..6] == target[..6] && d.ip.is_some()),
+        _ => false,
+    };
+    if state || !selectors.iter().all(fixed) {
+        registry.refresh()?;
+    }
+
+    let online = registry.online();
+    let devices: Vec<RegisteredDevice> = online
+        .into_iter()
+        .filter(|d| selectors.iter().any(|s| s.matches(d)))
+        .collect();
+    if devices.is_empty() {
+        let names: Vec<String> = selectors.iter().map(|s| s.to_string()).collect();
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no devices match {}", names.join(", ")),
+        ));
+    }
+    Ok(devices)
+}
+
+// The config's schedules, with their lights found and scenes loaded.
+fn schedule(
+    registry: &DeviceRegistry,
+    config: &Config,
+    duration: u32,
+) -> Result<Schedule, io::Error> {
+    if config.schedules.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the config file has no schedules",
+        ));
+    }
+
+    let mut schedule = Schedule::new(registry.client().clone()).with_utc_offset(config.utc_offset);
+    if let Some(location) = config.location {
+        schedule = schedule.with_location(location);
+    }
+    // Found together, so that devices are discovered at most once.
+    let all: Vec<Selector> = config
+        .schedules
+        .iter()
+        .flat_map(|s| s.lights.iter().cloned())
+        .collect();
+    let found = if all.is_empty() {
+        vec![]
+    } else {
+        select(registry, config, &all, false)?
+    };
+
+    for s in &config.schedules {
+        let devices: Vec<&RegisteredDevice> = found
+            .iter()
+            .filter(|d| s.lights.iter().any(|l| l.matches(d)))
+            .collect();
+        if devices.is_empty() && !s.lights.is_empty() {
+            eprintln!("lifx: no devices found for {}", s.name);
+        }
+        let action = match s.action {
+            ScheduledAction::Power(on) => Action::Power(on),
+            ScheduledAction::Colour(hsbk) => Action::Colour(hsbk),
+            ScheduledAction::Wake(hsbk) => Action::Wake(hsbk),
+            ScheduledAction::Scene(ref path) => Action::Scene(Scene::load(path)?),
+        };
+        schedule.add(Job {
+            name: s.name.clone(),
+            when: s.when.clone(),
+            lights: devices
+                .iter()
+                .map(|d| Light::from_registered(registry.client().clone(), d))
+                .collect(),
+            action,
+            duration: s.duration.map_or(duration, |d| d.as_millis() as u32),
+        })?;
+    }
+    Ok(schedule.with_devices(registry.devices()))
+}
+
+fn print_schedule(schedule: &Schedule) {
+    let mut rows = vec![vec!["NAME", "WHEN", "LIGHTS", "NEXT"]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<String>>()];
+    let now = SystemTime::now();
+    for job in schedule.jobs() {
+        let lights = match job.action {
+            Action::Scene(ref scene) => scene.lights.len(),
+            _ => job.lights.len(),
+        };
+        rows.push(vec![
+            job.name.clone(),
+            job.when.to_string(),
+            lights.to_string(),
+            schedule
+                .next_run(job, now)
+                .map_or("never".to_string(), |t| schedule.local_time(t).to_string()),
+        ]);
+    }
+    print_table(&rows);
+}
+
+fn print_devices(options: &Options, devices: &[RegisteredDevice]) {
+    if options.json {
+        println!("{}", Json::Array(devices.iter().map(device_json).collect()));
+        return;
+    }
+
+    let mut rows = vec![
+        vec!["MAC", "IP", "LABEL", "GROUP", "PRODUCT", "POWER", "COLOUR"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>(),
+    ];
+    for d in devices {
+        let (power, colour) = match d.state {
+            Some(ref s) => (
+                if s.power != 0 { "on" } else { "off" }.to_string(),
+                format_colour(&s.hsbk),
+            ),
+            None => (String::new(), String::new()),
+        };
+        rows.push(vec![
+            d.mac_address[..17].to_string(),
+            d.socket_addr.ip().to_string(),
+            d.label.clone().unwrap_or_default(),
+            d.group.clone().unwrap_or_default(),
+            d.product_info().map_or("", |p| p.name).to_string(),
+            power,
+            colour,
+        ]);
+    }
+    print_table(&rows);
+}
+
+fn device_json(d: &RegisteredDevice) -> Json {
+    let state = d.state.as_ref();
+    Json::object(vec![
+        ("mac", Json::from(&d.mac_address[..17])),
+        ("ip", Json::from(d.socket_addr.ip().to_string())),
+        ("label", Json::from(d.label.clone())),
+        ("group", Json::from(d.group.clone())),
+        ("location", Json::from(d.location.clone())),
+        ("product", Json::from(d.product_info().map(|p| p.name))),
+        ("power", Json::from(state.map(|s| s.power != 0))),
+        ("hue", Json::from(state.map(|s| s.hsbk.hue_degrees()))),
+        (
+            "saturation",
+            Json::from(state.map(|s| s.hsbk.saturation_percent())),
+        ),
+        (
+            "brightness",
+            Json::from(state.map(|s| s.hsbk.brightness_percent())),
+        ),
+        ("kelvin", Json::from(state.map(|s| s.hsbk.kelvin))),
+    ])
+}
+
+// Prints each device's outcome, and returns whether all succeeded.
+fn print_report(options: &Options, devices: &[RegisteredDevice], report: &GroupReport) -> bool {
+    let label = |target: [u8; 8]| {
+        devices
+            .iter()
+            .find(|d| d.target == target)
+            .and_then(|d| d.label.clone())
+            .unwrap_or_default()
+    };
+    let error = |o: &Outcome| match *o {
+        Outcome::Succeeded => None,
+        Outcome::TimedOut => Some("timed out".to_string()),
+        Outcome::Failed(ref e) => Some(e.clone()),
+    };
+
+    if options.json {
+        let results = report
+            .results
+            .iter()
+            .map(|r| {
+                Json::object(vec![
+                    (
+                        "mac",
+                        Json::from(&rustylifx::convert::target_to_mac(r.target)[..17]),
+                    ),
+                    ("label", Json::from(label(r.target))),
+                    ("ok", Json::from(r.outcome == Outcome::Succeeded)),
+                    ("error", Json::from(error(&r.outcome))),
+                ])
+            })
+            .collect();
+        println!("{}", Json::Array(results));
+    } else {
+        let mut rows = vec![vec![
+            "MAC".to_string(),
+            "LABEL".to_string(),
+            "RESULT".to_string(),
+        ]];
+        for r in &report.results {
+            rows.push(vec![
+                rustylifx::convert::target_to_mac(r.target)[..17].to_string(),
+                label(r.target),
+                error(&r.outcome).unwrap_or_else(|| "ok".to_string()),
+            ]);
+        }
+        print_table(&rows);
+    }
+
+    report.all_succeeded()
+}
+
+// Pings each device in turn, and returns whether every device answered.
+fn ping(options: &Options, client: &Arc<Client>, devices: &[RegisteredDevice]) -> bool {
+    let mut all_answered = true;
+    let mut results: Vec<Json> = vec![];
+    let mut rows = vec![vec!["MAC", "LABEL", "SENT", "LOST", "MIN", "AVG", "MAX"]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<String>>()];
+
+    for d in devices {
+        let light = Light::from_registered(client.clone(), d);
+        let times: Vec<f64> = (0..options.count)
+            .filter_map(|_| light.ping().ok())
+            .map(|t| t.as_secs_f64() * 1000.0)
+            .collect();
+        let lost = options.count as usize - times.len();
+        all_answered &= !times.is_empty();
+
+        let min = times
+            .iter()
+            .cloned()
+            .fold(None, |m: Option<f64>, t| Some(m.map_or(t, |m| m.min(t))));
+        let max = times
+            .iter()
+            .cloned()
+            .fold(None, |m: Option<f64>, t| Some(m.map_or(t, |m| m.max(t))));
+        let avg = if times.is_empty() {
+            None
+        } else {
+            Some(times.iter().sum::<f64>() / times.len() as f64)
+        };
+
+        if options.json {
+            results.push(Json::object(vec![
+                ("mac", Json::from(&d.mac_address[..17])),
+                ("label", Json::from(d.label.clone())),
+                ("sent", Json::from(options.count)),
+                ("lost", Json::from(lost as u32)),
+                ("min_ms", Json::from(min)),
+                ("avg_ms", Json::from(avg)),
+                ("max_ms", Json::from(max)),
+            ]));
+        } else {
+            let ms = |t: Option<f64>| t.map_or("-".to_string(), |t| format!("{:.1}ms", t));
+            rows.push(vec![
+                d.mac_address[..17].to_string(),
+                d.label.clone().unwrap_or_default(),
+                options.count.to_string(),
+                lost.to_string(),
+                ms(min),
+                ms(avg),
+                ms(max),
+            ]);
+        }
+    }
+
+    if options.json {
+        println!("{}", Json::Array(results));
+    } else {
+        print_table(&rows);
+    }
+    all_answered
+}
+
+fn format_colour(c: &Hsbk) -> String {
+    if c.saturation == 0 {
+        return format!("white {:.0}% {}K", c.brightness_percent(), c.kelvin);
+    }
+    format!(
+        "hsb({:.0}, {:.0}%, {:.0}%) {}K",
+        c.hue_degrees(),
+        c.saturation_percent(),
+        c.brightness_percent(),
+        c.kelvin
+    )
+}
+
+fn print_table(rows: &[Vec<String>]) {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .map(|r| r.get(i).map_or(0, |c| c.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{:width$}", c, width = widths[i]))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
@@ -0,0 +1,522 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use decode;
+use json::Json;
+use network;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Where a client records its datagrams, if anywhere.
+pub(crate) type CaptureSlot = Arc<RwLock<Option<Arc<Capture>>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    /// The direction as it's written in recordings and logs.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Direction::Sent => "sent",
+            Direction::Received => "received",
+        }
+    }
+}
+
+/// A datagram sent or received, as captured.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Datagram {
+    pub time: SystemTime,
+    pub direction: Direction,
+    /// The address of the socket that sent or received it.
+    pub local: SocketAddr,
+    /// The address of the device at the other end.
+    pub peer: SocketAddr,
+    pub bytes: Vec<u8>,
+}
+
+/// Records every datagram a client sends and receives, see `Client::with_capture`.
+///
+/// Datagrams are kept in memory, or when recording to a file are instead
+/// written as they happen, so that a long recording doesn't grow without
+/// bound, one JSON object per line:
+///
+/// ```text
+/// {"time_us":1700000000000000,"direction":"sent","local":"0.0.0.0:50123",
+///  "peer":"192.168.1.20:56700","type":101,"message":"GetColor","sequence":4,
+///  "payload":"","bytes":"24 00 00 34 ..."}
+/// ```
+///
+/// Messages are decoded as far as they can be, as `lifx-decode` does; anything
+/// that isn't a message is kept as bytes alone. Read a recording back with
+/// `read_recording`.
+pub struct Capture {
+    datagrams: Mutex<Vec<Datagram>>,
+    writer: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Capture {
+    /// A capture kept only in memory.
+    pub fn new() -> Capture {
+        Capture {
+            datagrams: Mutex::new(vec![]),
+            writer: None,
+        }
+    }
+
+    /// A capture recorded to a file, replacing it if it exists.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> Result<Capture, io::Error> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Capture {
+            datagrams: Mutex::new(vec![]),
+            writer: Some(Mutex::new(Box::new(file))),
+        })
+    }
+
+    pub fn record(&self, direction: Direction, local: SocketAddr, peer: SocketAddr, bytes: &[u8]) {
+        let datagram = Datagram {
+            time: SystemTime::now(),
+            direction,
+            local,
+            peer,
+            bytes: bytes.to_vec(),
+        };
+        if let Some(ref writer) = self.writer {
+            let mut writer = writer.lock().unwrap();
+            let written = writeln!(writer, "{}", to_json(&datagram)).and_then(|_| writer.flush());
+            if let Err(e) = written {
+                warn!("writing capture failed: {}", e);
+            }
+        } else {
+            self.datagrams.lock().unwrap().push(datagram);
+        }
+    }
+
+    /// Every datagram captured so far, oldest first. Always empty when recording
+    /// to a file.
+    pub fn datagrams(&self) -> Vec<Datagram> {
+        self.datagrams.lock().unwrap().clone()
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Capture {
+        Capture::new()
+    }
+}
+
+fn to_json(d: &Datagram) -> Json {
+    let time_us = d
+        .time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |t| t.as_micros() as u64);
+    let mut fields = vec![
+        ("time_us", Json::Number(time_us as f64)),
+        ("direction", Json::from(d.direction.name())),
+        ("local", Json::from(d.local.to_string())),
+        ("peer", Json::from(d.peer.to_string())),
+    ];
+    if let Ok(decoded) = decode::decode(&d.bytes) {
+        fields.push(("type", Json::from(decoded.message_type)));
+        fields.push((
+            "message",
+            Json::from(decode::message_name(decoded.message_type)),
+        ));
+        fields.push(("sequence", Json::from(u16::from(d.bytes[23]))));
+        fields.push(("payload", Json::from(payload_summary(&decoded))));
+    }
+    fields.push(("bytes", Json::from(network::hex_dump(&d.bytes))));
+    Json::object(fields)
+}
+
+// The payload's fields, as name=value, leaving out the bytes already recorded.
+fn payload_summary(decoded: &decode::Decoded) -> String {
+    decoded
+        .sections
+        .iter()
+        .filter(|s| s.name == "Payload")
+        .flat_map(|s| s.fields.iter())
+        .filter(|f| f.name != "bytes")
+        .map(|f| format!("{}={}", f.name, f.value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn from_json(json: &Json) -> Result<Datagram, io::Error> {
+    let field = |name: &str| {
+        json.get(name)
+            .ok_or_else(|| invalid(&format!("missing {}", name)))
+    };
+    let addr = |name: &str| -> Result<SocketAddr, io::Error> {
+        field(name)?
+            .as_str()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid(&format!("bad {}", name)))
+    };
+
+    let time_us = field("time_us")?
+        .as_f64()
+        .ok_or_else(|| invalid("bad time_us"))?;
+    let direction = match field("direction")?.as_str() {
+        Some("sent") => Direction::Sent,
+        Some("received") => Direction::Received,
+        _ => return Err(invalid("bad direction")),
+    };
+    let bytes = field("bytes")?
+        .as_str()
+        .ok_or_else(|| invalid("bad bytes"))?
+        .split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid("bad bytes"))?;
+
+    Ok(Datagram {
+        time: UNIX_EPOCH + Duration::from_micros(time_us as u64),
+        direction,
+        local: addr("local")?,
+        peer: addr("peer")?,
+        bytes,
+    })
+}
+
+/// Reads a recording written by a capture to a file.
+pub fn read_recording<R: BufRead>(reader: R) -> Result<Vec<Datagram>, io::Error> {
+    let mut datagrams = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let datagram = Json::parse(&line)
+            .and_then(|json| from_json(&json))
+            .map_err(|e| io::Error::new(e.kind(), format!("line {}: {}", i + 1, e)))?;
+        datagrams.push(datagram);
+    }
+    Ok(datagrams)
+}
+
+/// Reads a recording from a file, see `read_recording`.
+pub fn open_recording<P: AsRef<Path>>(path: P) -> Result<Vec<Datagram>, io::Error> {
+    read_recording(BufReader::new(File::open(path)?))
+}
+
+/// Resends the sent datagrams of a recording, keeping their original spacing
+/// in time, to their original device or to the one given. Returns what was
+/// received in reply, including anything arriving within wait of the last send.
+///
+/// The datagrams are sent unchanged, source and sequence numbers included.
+pub fn replay(
+    datagrams: &[Datagram],
+    to: Option<SocketAddr>,
+    wait: Duration,
+) -> Result<Vec<Datagram>, io::Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    let local = socket.local_addr()?;
+    let received: Arc<Mutex<Vec<Datagram>>> = Arc::new(Mutex::new(vec![]));
+
+    let sent: Vec<&Datagram> = datagrams
+        .iter()
+        .filter(|d| d.direction == Direction::Sent)
+        .collect();
+    let first = match sent.first() {
+        Some(d) => d.time,
+        None => return Ok(vec![]),
+    };
+
+    let listener = {
+        let socket = socket.try_clone()?;
+        socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+        let received = received.clone();
+        let deadline: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+        let done = deadline.clone();
+        let handle = thread::spawn(move || {
+            let mut buf = [0u8; 1500];
+            loop {
+                if let Some(deadline) = *done.lock().unwrap() {
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                }
+                if let Ok((n, peer)) = socket.recv_from(&mut buf) {
+                    received.lock().unwrap().push(Datagram {
+                        time: SystemTime::now(),
+                        direction: Direction::Received,
+                        local,
+                        peer,
+                        bytes: buf[..n].to_vec(),
+                    });
+                }
+            }
+        });
+        (handle, deadline)
+    };
+
+    let start = Instant::now();
+    let result = sent.iter().try_for_each(|d| {
+        let offset = d.time.duration_since(first).unwrap_or_default();
+        if let Some(delay) = (start + offset).checked_duration_since(Instant::now()) {
+            thread::sleep(delay);
+        }
+        socket.send_to(&d.bytes, to.unwrap_or(d.peer)).map(|_| ())
+    });
+
+    *listener.1.lock().unwrap() = Some(Instant::now() + wait);
+    let _ = listener.0.join();
+    result?;
+
+    let received = received.lock().unwrap().clone();
+    Ok(received)
+}
+
+/// Writes datagrams as a pcap file, which Wireshark and tcpdump open, each
+/// wrapped in IPv4 and UDP headers. Datagrams to or from IPv6 addresses are
+/// left out.
+pub fn write_pcap<W: Write>(writer: &mut W, datagrams: &[Datagram]) -> Result<(), io::Error> {
+    // Version 2.4, microsecond timestamps, and raw IP packets (link type 101).
+    writer.write_all(&0xA1B2_C3D4u32.to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&4u16.to_le_bytes())?;
+    writer.write_all(&[0; 8])?;
+    writer.write_all(&65535u32.to_le_bytes())?;
+    writer.write_all(&101u32.to_le_bytes())?;
+
+    for d in datagrams {
+        let (from, to) = match d.direction {
+            Direction::Sent => (d.local, d.peer),
+            Direction::Received => (d.peer, d.local),
+        };
+        let packet = match ipv4_udp_packet(from, to, &d.bytes) {
+            Some(p) => p,
+            None => continue,
+        };
+        let time = d.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        writer.write_all(&(time.as_secs() as u32).to_le_bytes())?;
+        writer.write_all(&time.subsec_micros().to_le_bytes())?;
+        writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+        writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+        writer.write_all(&packet)?;
+    }
+    writer.flush()
+}
+
+fn ipv4_udp_packet(from: SocketAddr, to: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    let (src, dst) = match (from.ip(), to.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => (src, dst),
+        _ => return None,
+    };
+    let udp_len = 8 + payload.len();
+    let total_len = 20 + udp_len;
+    if total_len > usize::from(u16::MAX) {
+        return None;
+    }
+
+    let mut packet = Vec::with_capacity(total_len);
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&(total_len as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0, 64, 17, 0, 0]);
+    packet.extend_from_slice(&src.octets());
+    packet.extend_from_slice(&dst.octets());
+    let checksum = ip_checksum(&packet);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    // A UDP checksum of zero means none was computed, which IPv4 allows.
+    packet.extend_from_slice(&from.port().to_be_bytes());
+    packet.extend_from_slice(&to.port().to_be_bytes());
+    packet.extend_from_slice(&(udp_len as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(payload);
+    Some(packet)
+}
+
+fn ip_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|w| u32::from(u16::from_be_bytes([w[0], w[1]])))
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid recording: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ip_checksum, read_recording, replay, to_json, write_pcap, Capture, Datagram, Direction,
+    };
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::BufReader;
+    use std::net::{SocketAddr, UdpSocket};
+    use std::process;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_recording_round_trip() {
+        let capture = Capture::new();
+        let mut message = vec![0u8; 36];
+        message[23] = 7;
+        message[32] = 101;
+        capture.record(
+            Direction::Sent,
+            addr("0.0.0.0:50123"),
+            addr("192.168.1.20:56700"),
+            &message,
+        );
+        capture.record(
+            Direction::Received,
+            addr("0.0.0.0:50123"),
+            addr("192.168.1.20:56700"),
+            &[1, 2, 3],
+        );
+
+        let datagrams = capture.datagrams();
+        let text: String = datagrams
+            .iter()
+            .map(|d| format!("{}\n", to_json(d)))
+            .collect();
+        assert!(
+            text.contains(r#""type":101,"message":"GetColor","sequence":7"#),
+            "{}",
+            text
+        );
+
+        let read = read_recording(text.as_bytes()).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].bytes, message);
+        assert_eq!(read[1].direction, Direction::Received);
+        assert_eq!(read[1].bytes, vec![1, 2, 3]);
+
+        let err = read_recording(&b"{\"time_us\": 1}\n"[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1: invalid recording: missing direction"
+        );
+    }
+
+    #[test]
+    fn test_decoded_messages() {
+        let mut set_power = vec![0u8; 36];
+        set_power[32] = 117;
+        set_power.extend_from_slice(&[0xFF, 0xFF, 0xE8, 0x03, 0, 0]);
+        // A StateService cut short, which can't be parsed.
+        let mut short = vec![0u8; 37];
+        short[32] = 3;
+
+        let json = |direction, bytes: &[u8]| {
+            to_json(&Datagram {
+                time: UNIX_EPOCH,
+                direction,
+                local: addr("0.0.0.0:50123"),
+                peer: addr("192.168.1.20:56700"),
+                bytes: bytes.to_vec(),
+            })
+            .to_string()
+        };
+        let sent = json(Direction::Sent, &set_power);
+        assert!(sent.contains(r#""message":"SetLightPower""#), "{}", sent);
+        assert!(sent.contains("level=65535 duration=1000"), "{}", sent);
+
+        let received = json(Direction::Received, &short);
+        assert!(received.contains(r#""type":3"#), "{}", received);
+        assert!(received.contains(r#""bytes":""#), "{}", received);
+    }
+
+    #[test]
+    fn test_recording_to_file() {
+        let path = env::temp_dir().join(format!("rustylifx-capture-{}.jsonl", process::id()));
+        let capture = Capture::to_file(&path).unwrap();
+        capture.record(
+            Direction::Received,
+            addr("0.0.0.0:50123"),
+            addr("192.168.1.20:56700"),
+            &[1, 2, 3],
+        );
+
+        // Written out, not kept.
+        assert!(capture.datagrams().is_empty());
+        let read = read_recording(BufReader::new(File::open(&path).unwrap())).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].bytes, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_write_pcap() {
+        let capture = Capture::new();
+        capture.record(
+            Direction::Received,
+            addr("192.168.1.2:50123"),
+            addr("192.168.1.20:56700"),
+            &[0xAB; 4],
+        );
+        let mut pcap = vec![];
+        write_pcap(&mut pcap, &capture.datagrams()).unwrap();
+
+        assert_eq!(&pcap[0..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+        assert_eq!(pcap.len(), 24 + 16 + 20 + 8 + 4);
+        let ip = &pcap[40..60];
+        assert_eq!(&ip[12..16], &[192, 168, 1, 20]);
+        assert_eq!(ip_checksum(ip), 0);
+        assert_eq!(&pcap[60..62], &56700u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_replay() {
+        // A stand-in device, echoing one datagram back.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 64];
+            let (n, from) = device.recv_from(&mut buf).unwrap();
+            device.send_to(&buf[..n], from).unwrap();
+        });
+
+        let capture = Capture::new();
+        capture.record(
+            Direction::Sent,
+            addr("0.0.0.0:1"),
+            addr("192.0.2.1:56700"),
+            b"hello",
+        );
+        let received = replay(
+            &capture.datagrams(),
+            Some(device_addr),
+            Duration::from_millis(500),
+        )
+        .unwrap();
+        responder.join().unwrap();
+
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].bytes, b"hello".to_vec());
+        assert_eq!(received[0].peer, device_addr);
+    }
+}
@@ -0,0 +1,465 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use colour::Hsbk;
+use light::{Light, LightState};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sun::Location;
+
+// The sun's elevation at the end of civil twilight, below which it's night.
+const TWILIGHT: f64 = -6.0;
+
+// The lowest noon that counts as full daylight, so that a sun that only just
+// clears the horizon, or not even that, in a polar winter gives a dim day.
+const LOWEST_NOON: f64 = 10.0;
+
+// How far a light's colour can drift from what was sent, in rounding by the
+// light, before it's taken to have been changed by hand.
+const KELVIN_TOLERANCE: i32 = 50;
+const WORD_TOLERANCE: i32 = 656;
+
+// The transition for a light just turned on, which should look right at once.
+const TURN_ON_TRANSITION: Duration = Duration::from_secs(1);
+
+/// How lights follow the sun: their colour temperature and brightness at night
+/// and at midday, and how often, and how gently, they're changed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CircadianConfig {
+    pub night_kelvin: u16,
+    pub day_kelvin: u16,
+    /// In percent.
+    pub night_brightness: f64,
+    /// In percent.
+    pub day_brightness: f64,
+    /// How often each light is polled, and changed. A manual change is noticed
+    /// at the next poll.
+    pub poll_interval: Duration,
+    /// How long each change takes, up to the poll interval.
+    pub transition: Duration,
+    /// How long a light changed by hand is left alone. Turning it off and on
+    /// again hands it back sooner.
+    pub resume_after: Duration,
+}
+
+impl Default for CircadianConfig {
+    fn default() -> CircadianConfig {
+        CircadianConfig {
+            night_kelvin: 2200,
+            day_kelvin: 5500,
+            night_brightness: 30.0,
+            day_brightness: 100.0,
+            poll_interval: Duration::new(60, 0),
+            transition: Duration::new(30, 0),
+            resume_after: Duration::new(2 * 60 * 60, 0),
+        }
+    }
+}
+
+impl CircadianConfig {
+    /// The colour lights should be at, at the location and time: the night's
+    /// colour and brightness while the sun is below civil twilight, moving to
+    /// the day's as it climbs to its noon height.
+    pub fn target(&self, location: &Location, at: SystemTime) -> Hsbk {
+        let daylight = daylight(location, at);
+        let kelvin = f64::from(self.night_kelvin)
+            + daylight * (f64::from(self.day_kelvin) - f64::from(self.night_kelvin));
+        let brightness =
+            self.night_brightness + daylight * (self.day_brightness - self.night_brightness);
+        Hsbk::new(
+            0,
+            0,
+            (brightness.clamp(0.0, 100.0) / 100.0 * 65535.0).round() as u16,
+            kelvin.round() as u16,
+        )
+    }
+}
+
+/// How far through the day the sun is, from 0 while it's more than 6° below
+/// the horizon, to 1 at its height at noon that day. Using the day's own noon
+/// means midday lights are as bright in winter as in summer, except where the
+/// sun stays within 10° of the horizon.
+pub fn daylight(location: &Location, at: SystemTime) -> f64 {
+    let seconds = match at.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    };
+    // The day by the sun at the location, rather than by UTC.
+    let day = ((seconds + location.longitude * 240.0) / 86400.0).floor() as i64;
+    let noon = location.solar_noon(day);
+    let noon = if noon >= 0 {
+        UNIX_EPOCH + Duration::from_secs(noon as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(noon.unsigned_abs())
+    };
+
+    let highest = location.elevation(noon).max(LOWEST_NOON);
+    ((location.elevation(at) - TWILIGHT) / (highest - TWILIGHT)).clamp(0.0, 1.0)
+}
+
+// What the controller knows of one light.
+#[derive(Debug, Clone, PartialEq)]
+struct Track {
+    target: [u8; 8],
+    // The colour last sent, while the light was under the controller's hand.
+    sent: Option<Hsbk>,
+    // Until when the light is left alone, after it was changed by hand.
+    paused_until: Option<Instant>,
+    // Whether the light was off when last polled.
+    was_off: bool,
+}
+
+impl Track {
+    fn new(target: [u8; 8]) -> Track {
+        Track {
+            target,
+            sent: None,
+            paused_until: None,
+            was_off: false,
+        }
+    }
+
+    // Given the light's state, just polled, decides whether to send it the
+    // target colour, returning the transition to send it with.
+    fn step(
+        &mut self,
+        state: &LightState,
+        target: &Hsbk,
+        now: Instant,
+        config: &CircadianConfig,
+    ) -> Option<Duration> {
+        if !state.power {
+            // Off lights are left off, and hand back any pause when next on.
+            self.sent = None;
+            self.paused_until = None;
+            self.was_off = true;
+            return None;
+        }
+
+        let transition = match self.paused_until {
+            Some(until) if now < until => return None,
+            Some(_) => {
+                self.paused_until = None;
+                config.transition
+            }
+            None => match self.sent {
+                Some(ref sent) if changed(sent, &state.hsbk) => {
+                    self.sent = None;
+                    self.paused_until = Some(now + config.resume_after);
+                    return None;
+                }
+                Some(_) => config.transition,
+                None if self.was_off => TURN_ON_TRANSITION,
+                None => config.transition,
+            },
+        };
+
+        self.was_off = false;
+        self.sent = Some(*target);
+        Some(transition.min(config.poll_interval))
+    }
+}
+
+fn changed(sent: &Hsbk, polled: &Hsbk) -> bool {
+    (i32::from(sent.kelvin) - i32::from(polled.kelvin)).abs() > KELVIN_TOLERANCE
+        || (i32::from(sent.brightness) - i32::from(polled.brightness)).abs() > WORD_TOLERANCE
+        || (i32::from(sent.saturation) - i32::from(polled.saturation)).abs() > WORD_TOLERANCE
+}
+
+/// Keeps lights following the sun, from a background thread, until stopped or
+/// dropped.
+///
+/// Each poll, every light that's on is sent the colour for the time of day.
+/// A light found at a colour other than the one it was sent has been changed
+/// by hand, by an app or a switch, and is left alone for a while; lights that
+/// are off are left off.
+pub struct Circadian {
+    tracks: Arc<Mutex<Vec<Track>>>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Circadian {
+    pub fn start(lights: Vec<Light>, location: Location, config: CircadianConfig) -> Circadian {
+        let tracks = Arc::new(Mutex::new(
+            lights.iter().map(|l| Track::new(l.target())).collect(),
+        ));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker = {
+            let tracks = tracks.clone();
+            let running = running.clone();
+            thread::spawn(move || {
+                let lights: Vec<Light> = lights.into_iter().map(with_product).collect();
+                while running.load(Ordering::SeqCst) {
+                    let target = config.target(&location, SystemTime::now());
+                    follow(&lights, &tracks, &target, &config);
+
+                    // Sleep in short steps, so that stopping is not held up.
+                    let next = Instant::now() + config.poll_interval;
+                    while running.load(Ordering::SeqCst) && Instant::now() < next {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            })
+        };
+
+        Circadian {
+            tracks,
+            running,
+            worker: Some(worker),
+        }
+    }
+
+    /// The lights being left alone, after being changed by hand.
+    pub fn paused(&self) -> Vec<[u8; 8]> {
+        let now = Instant::now();
+        let tracks = self.tracks.lock().unwrap();
+        tracks
+            .iter()
+            .filter(|t| t.paused_until.is_some_and(|until| now < until))
+            .map(|t| t.target)
+            .collect()
+    }
+
+    /// Hands a paused light back to the controller, at the next poll.
+    pub fn resume(&self, target: [u8; 8]) {
+        let mut tracks = self.tracks.lock().unwrap();
+        for t in tracks.iter_mut().filter(|t| t.target == target) {
+            t.paused_until = t.paused_until.map(|_| Instant::now());
+        }
+    }
+
+    /// Stops changing the lights, leaving them as they are.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.join();
+    }
+
+    /// Follows the sun on the calling thread's time, never returning: only
+    /// `stop` ends the controller, and it can't be called while this waits.
+    pub fn join(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Circadian {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Polls every light, in parallel, and sends the target to those that should
+// have it.
+fn follow(lights: &[Light], tracks: &Mutex<Vec<Track>>, target: &Hsbk, config: &CircadianConfig) {
+    thread::scope(|s| {
+        for (i, light) in lights.iter().enumerate() {
+            s.spawn(move || {
+                // Sent and compared as the light can show it, so a light short of
+                // the target's kelvin isn't taken to have been changed by hand.
+                let target = &light.clamp(target);
+                let state = match light.state() {
+                    Ok(state) => state,
+                    Err(e) => {
+                        warn!("polling {} failed: {}", light.socket_addr(), e);
+                        return;
+                    }
+                };
+                let transition = {
+                    let mut tracks = tracks.lock().unwrap();
+                    let paused = tracks[i].paused_until.is_some();
+                    let transition = tracks[i].step(&state, target, Instant::now(), config);
+                    if !paused && tracks[i].paused_until.is_some() {
+                        info!(
+                            "{} was changed by hand, leaving it alone",
+                            light.socket_addr()
+                        );
+                    }
+                    transition
+                };
+
+                if let Some(transition) = transition {
+                    if let Err(e) = light.set_color(target, transition.as_millis() as u32) {
+                        warn!("changing {} failed: {}", light.socket_addr(), e);
+                        // Not knowing what the light shows, don't mistake it for a manual change.
+                        tracks.lock().unwrap()[i].sent = None;
+                    }
+                }
+            });
+        }
+    });
+}
+
+// Asks a light for its product if the registry didn't know it, so its kelvin
+// range is known.
+fn with_product(light: Light) -> Light {
+    if light.known_product().is_some() {
+        return light;
+    }
+    match light.product() {
+        Ok(product) => light.with_product(product),
+        Err(e) => {
+            warn!(
+                "finding the product of {} failed: {}",
+                light.socket_addr(),
+                e
+            );
+            light
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{daylight, CircadianConfig, Track, TURN_ON_TRANSITION};
+    use client::Client;
+    use colour::Hsbk;
+    use light::{Light, LightState};
+    use products;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+    use sun::Location;
+
+    // 2024-06-21 and 2024-12-21, in days since 1970-01-01.
+    const SOLSTICE: u64 = 19895;
+    const MIDWINTER: u64 = 20078;
+
+    #[test]
+    fn test_daylight() {
+        let at = |day: u64, hour: u64, minute: u64| {
+            UNIX_EPOCH + Duration::from_secs(day * 86400 + hour * 3600 + minute * 60)
+        };
+        let london = Location::new(51.5074, -0.1278);
+        let config = CircadianConfig::default();
+
+        // Full daylight at noon, summer and winter, and night at midnight.
+        assert!(daylight(&london, at(SOLSTICE, 12, 2)) > 0.999);
+        assert!(daylight(&london, at(MIDWINTER, 11, 58)) > 0.999);
+        assert_eq!(daylight(&london, at(SOLSTICE, 0, 0)), 0.0);
+        assert_eq!(
+            config.target(&london, at(SOLSTICE, 12, 2)),
+            Hsbk::new(0, 0, 65535, 5500)
+        );
+        assert_eq!(
+            config.target(&london, at(SOLSTICE, 0, 0)),
+            Hsbk::new(0, 0, 19661, 2200)
+        );
+
+        // Rising through the morning, and falling through the afternoon.
+        let morning: Vec<f64> = (5..12)
+            .map(|h| daylight(&london, at(SOLSTICE, h, 0)))
+            .collect();
+        assert!(morning.windows(2).all(|w| w[0] < w[1]), "{:?}", morning);
+        let afternoon: Vec<f64> = (13..21)
+            .map(|h| daylight(&london, at(SOLSTICE, h, 0)))
+            .collect();
+        assert!(afternoon.windows(2).all(|w| w[0] > w[1]), "{:?}", afternoon);
+
+        // A dim twilight at noon in the polar night, or none further north, and
+        // day all night under the midnight sun.
+        let tromso = Location::new(69.6492, 18.9553);
+        let noon = daylight(&tromso, at(MIDWINTER, 10, 45));
+        assert!(noon > 0.1 && noon < 0.3, "{}", noon);
+        let svalbard = Location::new(78.2232, 15.6267);
+        assert_eq!(daylight(&svalbard, at(MIDWINTER, 10, 55)), 0.0);
+        assert!(daylight(&tromso, at(SOLSTICE, 22, 45)) > 0.1);
+    }
+
+    #[test]
+    fn test_step() {
+        let config = CircadianConfig::default();
+        let target = Hsbk::new(0, 0, 52428, 4000);
+        let state = |hsbk: Hsbk, power: bool| LightState {
+            hsbk,
+            power,
+            label: String::new(),
+        };
+        let now = Instant::now();
+        let mut track = Track::new([0; 8]);
+
+        // Taken over slowly when first seen, and kept following while it stays as sent.
+        let old = Hsbk::new(21845, 65535, 65535, 3500);
+        assert_eq!(
+            track.step(&state(old, true), &target, now, &config),
+            Some(config.transition)
+        );
+        assert_eq!(
+            track.step(&state(target, true), &target, now, &config),
+            Some(config.transition)
+        );
+        let rounded = Hsbk {
+            kelvin: 4010,
+            ..target
+        };
+        assert_eq!(
+            track.step(&state(rounded, true), &target, now, &config),
+            Some(config.transition)
+        );
+
+        // Dimmed by hand, so left alone until the pause ends.
+        let dimmed = Hsbk {
+            brightness: 13107,
+            ..target
+        };
+        assert_eq!(
+            track.step(&state(dimmed, true), &target, now, &config),
+            None
+        );
+        let later = now + Duration::from_secs(60 * 60);
+        assert_eq!(
+            track.step(&state(dimmed, true), &target, later, &config),
+            None
+        );
+        let later = now + config.resume_after;
+        assert_eq!(
+            track.step(&state(dimmed, true), &target, later, &config),
+            Some(config.transition)
+        );
+
+        // Left off when off, and put right at once when turned on, even if paused.
+        let red = Hsbk::new(0, 65535, 65535, 3500);
+        assert_eq!(track.step(&state(red, true), &target, later, &config), None);
+        assert!(track.paused_until.is_some());
+        assert_eq!(
+            track.step(&state(red, false), &target, later, &config),
+            None
+        );
+        assert_eq!(track.paused_until, None);
+        assert_eq!(
+            track.step(&state(red, true), &target, later, &config),
+            Some(TURN_ON_TRANSITION)
+        );
+        assert_eq!(track.sent, Some(target));
+    }
+
+    #[test]
+    fn test_step_clamped() {
+        // A Mini White to Warm goes no cooler than 4000K, so a daylight target
+        // is sent and compared as 4000K rather than seen as changed by hand.
+        let client = Arc::new(Client::new().unwrap());
+        let addr = "192.168.1.10:56700".parse().unwrap();
+        let light = Light::new(client, addr, [0; 8]).with_product(products::lookup(1, 50));
+        let target = light.clamp(&Hsbk::new(0, 0, 65535, 5500));
+        assert_eq!(target.kelvin, 4000);
+
+        let config = CircadianConfig::default();
+        let state = LightState {
+            hsbk: target,
+            power: true,
+            label: String::new(),
+        };
+        let now = Instant::now();
+        let mut track = Track::new([0; 8]);
+        for _ in 0..2 {
+            assert_eq!(
+                track.step(&state, &target, now, &config),
+                Some(config.transition)
+            );
+        }
+    }
+}
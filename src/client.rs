@@ -0,0 +1,739 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use capture::{Capture, CaptureSlot, Direction};
+use codec;
+use network::{self, Device};
+use ratelimit::RateLimiter;
+use request::{Frame, FrameAddress, Header, Payload, ProtocolHeader, Request, RequestBin};
+use response::{self, Payload as ResponsePayload, Response, ResponseData};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const LIFX_PORT: u16 = 56700;
+const ACKNOWLEDGEMENT: u16 = 45;
+
+type Pending = Arc<Mutex<HashMap<u8, Sender<(SocketAddr, Response)>>>>;
+type Outbox = Arc<(Mutex<Vec<Queued>>, Condvar)>;
+
+/// Counts of the requests made to a device, kept by the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RequestStats {
+    /// Requests made, however many times each was sent.
+    pub requests: u64,
+    /// Requests that were answered.
+    pub replies: u64,
+    /// Times a request was resent, after an attempt went unanswered.
+    pub retries: u64,
+    /// Requests that went unanswered after every attempt.
+    pub timeouts: u64,
+    /// The time from sending to the reply, summed over every answered request.
+    pub latency: Duration,
+    /// The time from sending to the reply, for the latest answered request.
+    pub last_latency: Option<Duration>,
+}
+
+// A message waiting for its device's rate limit to allow it to be sent.
+struct Queued {
+    socket_addr: SocketAddr,
+    target: [u8; 8],
+    message_type: u16,
+    msg_bin: RequestBin,
+}
+
+/// A client holding a single socket, shared by every request it makes.
+///
+/// Responses are read on a background thread and matched to their requests by
+/// sequence number, so many requests may be in flight at once, from many threads.
+///
+/// Messages to each device are rate limited, by default to 20 per second. Requests
+/// wait for the limit to allow them, while messages sent with `set_latest` are
+/// queued, and replaced by any later message of the same type to the same device.
+pub struct Client {
+    socket: UdpSocket,
+    source: u32,
+    sequence: AtomicUsize,
+    pending: Pending,
+    limiter: Arc<Mutex<RateLimiter>>,
+    outbox: Outbox,
+    timeout: Duration,
+    retries: u32,
+    stats: Mutex<HashMap<[u8; 8], RequestStats>>,
+    capture: CaptureSlot,
+    running: Arc<AtomicBool>,
+    receiver: Option<JoinHandle<()>>,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+impl Client {
+    pub fn new() -> Result<Client, io::Error> {
+        let local_sock_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+        let socket = UdpSocket::bind(local_sock_addr)?;
+        socket.set_broadcast(true)?;
+        socket.set_write_timeout(Some(Duration::new(3, 0)))?;
+
+        let recv_socket = socket.try_clone()?;
+        recv_socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let dispatch_socket = socket.try_clone()?;
+
+        // Any non-zero source will do, the devices echo it back in each response.
+        let source = 2 + socket.local_addr()?.port() as u32;
+
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let limiter = Arc::new(Mutex::new(RateLimiter::default()));
+        let outbox: Outbox = Arc::new((Mutex::new(vec![]), Condvar::new()));
+        let capture: CaptureSlot = Arc::new(RwLock::new(None));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let receiver = {
+            let pending = pending.clone();
+            let capture = capture.clone();
+            let running = running.clone();
+            thread::spawn(move || receive(recv_socket, source, pending, capture, running))
+        };
+
+        let dispatcher = {
+            let limiter = limiter.clone();
+            let outbox = outbox.clone();
+            let capture = capture.clone();
+            let running = running.clone();
+            thread::spawn(move || dispatch(dispatch_socket, limiter, outbox, capture, running))
+        };
+
+        Ok(Client {
+            socket,
+            source,
+            sequence: AtomicUsize::new(0),
+            pending,
+            limiter,
+            outbox,
+            timeout: Duration::new(3, 0),
+            retries: 2,
+            stats: Mutex::new(HashMap::new()),
+            capture,
+            running,
+            receiver: Some(receiver),
+            dispatcher: Some(dispatcher),
+        })
+    }
+
+    /// Sets how long to wait for each reply before retrying.
+    pub fn with_timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many times an unanswered request is resent.
+    pub fn with_retries(mut self, retries: u32) -> Client {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the rate limit applied to each device, and how many messages may be
+    /// sent in a burst before it applies.
+    ///
+    /// Panics if the rate isn't a positive, finite number.
+    pub fn with_rate_limit(self, messages_per_second: f64, burst: u32) -> Client {
+        *self.limiter.lock().unwrap() = RateLimiter::new(messages_per_second, burst);
+        self
+    }
+
+    /// Records every datagram the client sends and receives from now on.
+    pub fn with_capture(self, capture: Arc<Capture>) -> Client {
+        *self.capture.write().unwrap() = Some(capture);
+        self
+    }
+
+    /// The most messages sent to each device per second.
+    pub fn messages_per_second(&self) -> f64 {
+        self.limiter.lock().unwrap().messages_per_second()
+    }
+
+    pub fn source(&self) -> u32 {
+        self.source
+    }
+
+    /// Broadcasts GetService on the subnet, and collects every device that replies
+    /// within the wait period.
+    pub fn discover(&self, subnet: Ipv4Addr, wait: Duration) -> Result<Vec<Device>, io::Error> {
+        let replies = self.broadcast(subnet, 2, Payload(vec![]), false, wait)?;
+
+        let mut devices: Vec<Device> = vec![];
+        for (src_sock_addr, resp) in replies {
+            let port = match resp.payload {
+                // Service 1 is UDP, the only one in use.
+                ResponsePayload::StateService(ref p) if p.service == 1 => p.port as u16,
+                _ => continue,
+            };
+            if devices.iter().any(|d| match d.response {
+                Some(ref r) => r.target == resp.target,
+                None => false,
+            }) {
+                continue;
+            }
+            devices.push(Device {
+                socket_addr: SocketAddr::new(src_sock_addr.ip(), port),
+                response: Some(resp),
+            });
+        }
+
+        Ok(devices)
+    }
+
+    /// Broadcasts a Set message to every device on the subnet, and collects the
+    /// acknowledgements that arrive within the wait period.
+    pub fn broadcast_set(
+        &self,
+        subnet: Ipv4Addr,
+        message_type: u16,
+        payload: Payload,
+        wait: Duration,
+    ) -> Result<Vec<(SocketAddr, Response)>, io::Error> {
+        let replies = self.broadcast(subnet, message_type, payload, true, wait)?;
+        Ok(replies
+            .into_iter()
+            .filter(|(_, resp)| resp.message_type == ACKNOWLEDGEMENT)
+            .collect())
+    }
+
+    fn broadcast(
+        &self,
+        subnet: Ipv4Addr,
+        message_type: u16,
+        payload: Payload,
+        ack_required: bool,
+        wait: Duration,
+    ) -> Result<Vec<(SocketAddr, Response)>, io::Error> {
+        let broadcast_ip = network::ensure_ip_is_broadcast(subnet);
+        let broadcast_sock_addr = SocketAddr::new(broadcast_ip, LIFX_PORT);
+
+        let (sequence, rx) = self.register()?;
+        let msg_bin = self.request_bin(
+            [0; 8],
+            true,
+            message_type,
+            payload,
+            ack_required,
+            false,
+            sequence,
+        );
+        self.acquire([0; 8]);
+        let sent = self.send_to(&msg_bin, broadcast_sock_addr);
+
+        let mut replies = vec![];
+        let deadline = Instant::now() + wait;
+        while sent.is_ok() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            match rx.recv_timeout(deadline - now) {
+                Ok(reply) => replies.push(reply),
+                Err(_) => break,
+            }
+        }
+
+        self.unregister(sequence);
+        sent.map(|_| replies)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Counts of the requests made to the device so far.
+    pub fn stats(&self, target: [u8; 8]) -> RequestStats {
+        self.stats
+            .lock()
+            .unwrap()
+            .get(&target)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Sends a Get message to the device, and waits for its State reply.
+    pub fn get(
+        &self,
+        socket_addr: SocketAddr,
+        target: [u8; 8],
+        message_type: u16,
+    ) -> Result<Response, io::Error> {
+        self.request(socket_addr, target, message_type, Payload(vec![]), false)
+    }
+
+    /// Sends a Get message with a payload to the device, and waits for its reply.
+    pub fn get_with(
+        &self,
+        socket_addr: SocketAddr,
+        target: [u8; 8],
+        message_type: u16,
+        payload: Payload,
+    ) -> Result<Response, io::Error> {
+        self.request(socket_addr, target, message_type, payload, false)
+    }
+
+    /// Sends a Set message to the device, and waits for it to be acknowledged.
+    pub fn set(
+        &self,
+        socket_addr: SocketAddr,
+        target: [u8; 8],
+        message_type: u16,
+        payload: Payload,
+    ) -> Result<Response, io::Error> {
+        self.request(socket_addr, target, message_type, payload, true)
+    }
+
+    /// Queues a Set message for the device, without waiting for it to be sent or
+    /// acknowledged. If a message of the same type to the same device is still
+    /// queued, it is replaced, so that when messages are sent faster than the rate
+    /// limit allows the latest one wins and the rest are dropped.
+    pub fn set_latest(
+        &self,
+        socket_addr: SocketAddr,
+        target: [u8; 8],
+        message_type: u16,
+        payload: Payload,
+    ) {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst) as u8;
+        let msg_bin =
+            self.request_bin(target, false, message_type, payload, false, false, sequence);
+
+        let (ref queue, ref ready) = *self.outbox;
+        enqueue(
+            &mut queue.lock().unwrap(),
+            Queued {
+                socket_addr,
+                target,
+                message_type,
+                msg_bin,
+            },
+        );
+        ready.notify_one();
+    }
+
+    // Blocks until the rate limit allows a message to be sent to the target.
+    fn acquire(&self, target: [u8; 8]) {
+        loop {
+            let result = self
+                .limiter
+                .lock()
+                .unwrap()
+                .try_acquire(target, Instant::now());
+            match result {
+                Ok(()) => return,
+                Err(wait) => thread::sleep(wait),
+            }
+        }
+    }
+
+    fn request(
+        &self,
+        socket_addr: SocketAddr,
+        target: [u8; 8],
+        message_type: u16,
+        payload: Payload,
+        ack_required: bool,
+    ) -> Result<Response, io::Error> {
+        self.record(target, |s| s.requests += 1);
+        for attempt in 0..self.retries + 1 {
+            if attempt > 0 {
+                self.record(target, |s| s.retries += 1);
+            }
+            let (sequence, rx) = self.register()?;
+            let msg_bin = self.request_bin(
+                target,
+                false,
+                message_type,
+                Payload(payload.0.clone()),
+                ack_required,
+                !ack_required,
+                sequence,
+            );
+
+            self.acquire(target);
+            let sent_at = Instant::now();
+            let result = self
+                .send_to(&msg_bin, socket_addr)
+                .and_then(|_| self.await_reply(&rx, ack_required));
+            self.unregister(sequence);
+
+            match result {
+                Ok(resp) => {
+                    let latency = sent_at.elapsed();
+                    self.record(target, |s| {
+                        s.replies += 1;
+                        s.latency += latency;
+                        s.last_latency = Some(latency);
+                    });
+                    return Ok(resp);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => debug!(
+                    "no reply to type {} from {}, attempt {}",
+                    message_type,
+                    socket_addr,
+                    attempt + 1
+                ),
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.record(target, |s| s.timeouts += 1);
+        warn!(
+            "no reply to type {} from {} after {} attempts",
+            message_type,
+            socket_addr,
+            self.retries + 1
+        );
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no reply from {}", socket_addr),
+        ))
+    }
+
+    fn record<F: FnOnce(&mut RequestStats)>(&self, target: [u8; 8], f: F) {
+        f(self.stats.lock().unwrap().entry(target).or_default());
+    }
+
+    fn await_reply(
+        &self,
+        rx: &Receiver<(SocketAddr, Response)>,
+        ack_required: bool,
+    ) -> Result<Response, io::Error> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+            }
+
+            match rx.recv_timeout(deadline - now) {
+                Ok((_, resp)) => {
+                    if (resp.message_type == ACKNOWLEDGEMENT) == ack_required {
+                        return Ok(resp);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        "client receiver stopped",
+                    ));
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn request_bin(
+        &self,
+        target: [u8; 8],
+        tagged: bool,
+        message_type: u16,
+        payload: Payload,
+        ack_required: bool,
+        res_required: bool,
+        sequence: u8,
+    ) -> RequestBin {
+        let msg = Request::new(
+            Header::new(
+                Frame::new(0, tagged, true, 1024, self.source),
+                FrameAddress::new(target, [0; 6], 0, ack_required, res_required, sequence),
+                ProtocolHeader::new(0, message_type, 0),
+            ),
+            payload,
+        );
+
+        RequestBin::from(msg)
+    }
+
+    fn send_to(&self, msg_bin: &RequestBin, socket_addr: SocketAddr) -> Result<(), io::Error> {
+        send_to(&self.socket, msg_bin, socket_addr, &self.capture)
+    }
+
+    // Allocates a sequence number, and a channel on which its replies will arrive.
+    // Fails if every sequence number is waiting on a reply.
+    fn register(&self) -> Result<(u8, Receiver<(SocketAddr, Response)>), io::Error> {
+        let mut pending = self.pending.lock().unwrap();
+        for _ in 0..256 {
+            let sequence = self.sequence.fetch_add(1, Ordering::SeqCst) as u8;
+            if let Entry::Vacant(slot) = pending.entry(sequence) {
+                let (tx, rx) = mpsc::channel();
+                slot.insert(tx);
+                return Ok((sequence, rx));
+            }
+        }
+
+        Err(io::Error::other(
+            "all 256 sequence numbers are waiting on replies",
+        ))
+    }
+
+    fn unregister(&self, sequence: u8) {
+        self.pending.lock().unwrap().remove(&sequence);
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.outbox.1.notify_all();
+        if let Some(receiver) = self.receiver.take() {
+            let _ = receiver.join();
+        }
+        if let Some(dispatcher) = self.dispatcher.take() {
+            let _ = dispatcher.join();
+        }
+    }
+}
+
+fn send_to(
+    socket: &UdpSocket,
+    msg_bin: &RequestBin,
+    socket_addr: SocketAddr,
+    capture: &CaptureSlot,
+) -> Result<(), io::Error> {
+    match socket.send_to(&msg_bin.0, socket_addr) {
+        Ok(_) => {
+            network::log_message(Direction::Sent, &msg_bin.0, socket_addr);
+            record(capture, socket, Direction::Sent, socket_addr, &msg_bin.0);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("sending to {} failed: {}", socket_addr, e);
+            Err(e)
+        }
+    }
+}
+
+// Hands a datagram to the client's capture, if it has one.
+fn record(
+    capture: &CaptureSlot,
+    socket: &UdpSocket,
+    direction: Direction,
+    peer: SocketAddr,
+    bytes: &[u8],
+) {
+    if let Some(ref capture) = *capture.read().unwrap() {
+        if let Ok(local) = socket.local_addr() {
+            capture.record(direction, local, peer, bytes);
+        }
+    }
+}
+
+// Queues a message, replacing any queued message of the same type to the same
+// device in place, so that it keeps its position in the queue.
+fn enqueue(queue: &mut Vec<Queued>, msg: Queued) {
+    match queue
+        .iter_mut()
+        .find(|q| q.target == msg.target && q.message_type == msg.message_type)
+    {
+        Some(q) => *q = msg,
+        None => queue.push(msg),
+    }
+}
+
+// Sends queued messages as each device's rate limit allows.
+fn dispatch(
+    socket: UdpSocket,
+    limiter: Arc<Mutex<RateLimiter>>,
+    outbox: Outbox,
+    capture: CaptureSlot,
+    running: Arc<AtomicBool>,
+) {
+    let (ref queue, ref ready) = *outbox;
+    let mut queue = queue.lock().unwrap();
+    while running.load(Ordering::SeqCst) {
+        let mut next_wait: Option<Duration> = None;
+        let mut i = 0;
+        while i < queue.len() {
+            let allowed = limiter
+                .lock()
+                .unwrap()
+                .try_acquire(queue[i].target, Instant::now());
+            match allowed {
+                Ok(()) => {
+                    let msg = queue.remove(i);
+                    let _ = send_to(&socket, &msg.msg_bin, msg.socket_addr, &capture);
+                }
+                Err(wait) => {
+                    next_wait = Some(next_wait.map_or(wait, |w| w.min(wait)));
+                    i += 1;
+                }
+            }
+        }
+
+        // Wake when a device's limit next allows a message, or a message is queued.
+        let wait = next_wait.unwrap_or_else(|| Duration::from_millis(200));
+        queue = ready.wait_timeout(queue, wait).unwrap().0;
+    }
+}
+
+// Reads every datagram arriving on the socket, handing each to its request.
+fn receive(
+    socket: UdpSocket,
+    source: u32,
+    pending: Pending,
+    capture: CaptureSlot,
+    running: Arc<AtomicBool>,
+) {
+    let mut resp_buf = [0; 1024];
+    while running.load(Ordering::SeqCst) {
+        let (sz, src_sock_addr) = match socket.recv_from(&mut resp_buf) {
+            Ok(v) => v,
+            // The read timeout, there so that a stopped client is noticed.
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::Interrupted =>
+            {
+                continue
+            }
+            Err(e) => {
+                // Such as an ICMP port unreachable reported on Windows. Back off
+                // rather than spin, in case the error persists.
+                warn!("receiving failed: {}", e);
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        };
+
+        record(
+            &capture,
+            &socket,
+            Direction::Received,
+            src_sock_addr,
+            &resp_buf[0..sz],
+        );
+
+        // Ignore anything too short to hold a header, or not meant for this
+        // client, before parsing the rest.
+        match codec::decode_header(&resp_buf[0..sz]) {
+            Ok(ref header) if header.frame.source == source => (),
+            _ => continue,
+        }
+        let resp = response::parse_response(ResponseData(resp_buf[0..sz].to_vec()));
+
+        network::log_message(Direction::Received, &resp_buf[0..sz], src_sock_addr);
+
+        let pending = pending.lock().unwrap();
+        if let Some(tx) = pending.get(&resp.sequence_number) {
+            let _ = tx.send((src_sock_addr, resp));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{enqueue, Client, Queued, RequestStats};
+    use capture::{Capture, Direction};
+    use request::RequestBin;
+    use response::Payload;
+    use std::io;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn queued(target: u8, message_type: u16, body: u8) -> Queued {
+        Queued {
+            socket_addr: "192.168.1.10:56700".parse().unwrap(),
+            target: [target, 0, 0, 0, 0, 0, 0, 0],
+            message_type,
+            msg_bin: RequestBin(vec![body]),
+        }
+    }
+
+    #[test]
+    fn test_enqueue_latest_wins() {
+        let mut queue = vec![];
+        enqueue(&mut queue, queued(1, 102, 1));
+        enqueue(&mut queue, queued(2, 102, 2));
+        enqueue(&mut queue, queued(1, 117, 3));
+        enqueue(&mut queue, queued(1, 102, 4));
+
+        let bodies: Vec<u8> = queue.iter().map(|q| q.msg_bin.0[0]).collect();
+        assert_eq!(bodies, vec![4, 2, 3]);
+    }
+
+    #[test]
+    fn test_get_round_trip() {
+        // A stand-in device, answering one GetPower with StatePower.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 1024];
+            let (_, from) = device.recv_from(&mut buf).unwrap();
+            let mut reply = buf[0..36].to_vec();
+            reply[0] = 38;
+            reply[8..14].clone_from_slice(&[0xD0, 0x73, 0xD5, 1, 2, 3]);
+            reply[32] = 22;
+            reply.extend_from_slice(&[0xFF, 0xFF]);
+            device.send_to(&reply, from).unwrap();
+        });
+
+        let capture = Arc::new(Capture::new());
+        let client = Client::new().unwrap().with_capture(capture.clone());
+        let resp = client.get(device_addr, [0; 8], 20).unwrap();
+        responder.join().unwrap();
+
+        assert_eq!(resp.mac_address, "D0:73:D5:01:02:03:00:00");
+        match resp.payload {
+            Payload::StatePower(p) => assert_eq!(p.level, 65535),
+            p => panic!("unexpected payload: {:?}", p),
+        }
+        let stats = client.stats([0; 8]);
+        assert_eq!((stats.requests, stats.replies, stats.retries), (1, 1, 0));
+        assert_eq!(stats.last_latency, Some(stats.latency));
+
+        let datagrams = capture.datagrams();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].direction, Direction::Sent);
+        assert_eq!(datagrams[1].direction, Direction::Received);
+        assert_eq!(datagrams[1].peer, device_addr);
+    }
+
+    #[test]
+    fn test_stats_count_timeouts() {
+        // A device that never answers.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let client = Client::new()
+            .unwrap()
+            .with_timeout(Duration::from_millis(20))
+            .with_retries(2);
+
+        let err = client
+            .get(device.local_addr().unwrap(), [1; 8], 20)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let stats = client.stats([1; 8]);
+        assert_eq!((stats.requests, stats.replies), (1, 0));
+        assert_eq!((stats.retries, stats.timeouts), (2, 1));
+        assert_eq!(client.stats([2; 8]), RequestStats::default());
+    }
+
+    #[test]
+    fn test_register_fails_when_sequences_run_out() {
+        let client = Client::new().unwrap();
+        let held: Vec<_> = (0..256).map(|_| client.register().unwrap()).collect();
+        assert!(client.register().is_err());
+
+        client.unregister(held[7].0);
+        assert_eq!(client.register().unwrap().0, held[7].0);
+    }
+}
@@ -0,0 +1,505 @@
+// Encodes and decodes messages in caller provided buffers, with no allocation,
+// so that it can be used on hot paths such as animation loops. Only `core` is
+// used here, and this module is all that is built without the `std` feature.
+
+use core::fmt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The size of every message's header.
+pub const HEADER_SIZE: usize = 36;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The buffer is shorter than the message needs.
+    ShortBuffer { needed: usize, available: usize },
+    /// The message is longer than the 16 bit size field can hold.
+    TooLong(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecError::ShortBuffer { needed, available } => write!(
+                f,
+                "buffer of {} bytes is too short, {} are needed",
+                available, needed
+            ),
+            CodecError::TooLong(len) => write!(f, "a {} byte message is too long", len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Header {
+    pub frame: Frame,
+    pub frame_address: FrameAddress,
+    pub protocol_header: ProtocolHeader,
+}
+
+impl Header {
+    /// Decodes the 36 byte header at the start of a message, the inverse of the
+    /// encoding in `RequestBin::from`, so that re-encoding it gives the same
+    /// bytes. None if there are fewer than 36 bytes.
+    pub fn decode(bytes: &[u8]) -> Option<Header> {
+        decode_header(bytes).ok()
+    }
+
+    pub fn new(
+        frame: Frame,
+        frame_address: FrameAddress,
+        protocol_header: ProtocolHeader,
+    ) -> Header {
+        Header {
+            frame,
+            frame_address,
+            protocol_header,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frame {
+    // First 2 bytes
+    pub size: u16,
+
+    // Second two bytes
+    pub origin: u8,
+    // For discovery using Device::GetService use true and target all zeroes.
+    // For all other requests set to false and target to device MAC address.
+    pub tagged: bool,
+    pub addressable: bool, // Must be true
+    pub protocol: u16,     // Must be 1024
+
+    // Final 4 bytes
+    pub source: u32,
+}
+
+impl Frame {
+    pub fn new(origin: u8, tagged: bool, addressable: bool, protocol: u16, source: u32) -> Frame {
+        Frame {
+            size: 0,
+            origin,
+            tagged,
+            addressable,
+            protocol,
+            source,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameAddress {
+    // MAC address (6 bytes) left-justified with two 0 bytes, or all 0s for all devices
+    pub target: [u8; 8],
+    pub reserved: [u8; 6],
+    pub reserved_2: u8,
+    pub ack_required: bool,
+    pub res_required: bool,
+    pub sequence: u8,
+}
+
+impl FrameAddress {
+    pub fn new(
+        target: [u8; 8],
+        reserved: [u8; 6],
+        reserved_2: u8,
+        ack_required: bool,
+        res_required: bool,
+        sequence: u8,
+    ) -> FrameAddress {
+        FrameAddress {
+            target,
+            reserved,
+            reserved_2,
+            ack_required,
+            res_required,
+            sequence,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ProtocolHeader {
+    pub reserved: u64,
+    pub message_type: u16,
+    pub reserved_2: u16,
+}
+
+impl ProtocolHeader {
+    pub fn new(reserved: u64, message_type: u16, reserved_2: u16) -> ProtocolHeader {
+        ProtocolHeader {
+            reserved,
+            message_type,
+            reserved_2,
+        }
+    }
+}
+
+/// Writes a header into the first 36 bytes of buf, giving a message size of
+/// the header plus payload_len. `header.frame.size` is ignored, as it is when
+/// encoding a `RequestBin`.
+pub fn encode_header(
+    header: &Header,
+    payload_len: usize,
+    buf: &mut [u8],
+) -> Result<usize, CodecError> {
+    let size = HEADER_SIZE + payload_len;
+    if size > usize::from(u16::MAX) {
+        return Err(CodecError::TooLong(size));
+    }
+    let buf = slice_mut(buf, 0, HEADER_SIZE)?;
+
+    let frame = &header.frame;
+    let frame_bits = u16::from(frame.origin & 0x03) << 14
+        | u16::from(frame.tagged) << 13
+        | u16::from(frame.addressable) << 12
+        | frame.protocol & 0x0FFF;
+    buf[0..2].copy_from_slice(&(size as u16).to_le_bytes());
+    buf[2..4].copy_from_slice(&frame_bits.to_le_bytes());
+    buf[4..8].copy_from_slice(&frame.source.to_le_bytes());
+
+    let address = &header.frame_address;
+    buf[8..16].copy_from_slice(&address.target);
+    // The reserved bytes are written reversed.
+    for (i, b) in address.reserved.iter().rev().enumerate() {
+        buf[16 + i] = *b;
+    }
+    buf[22] = (address.reserved_2 & 0x3F) << 2
+        | u8::from(address.ack_required) << 1
+        | u8::from(address.res_required);
+    buf[23] = address.sequence;
+
+    let protocol = &header.protocol_header;
+    buf[24..32].copy_from_slice(&protocol.reserved.to_le_bytes());
+    buf[32..34].copy_from_slice(&protocol.message_type.to_le_bytes());
+    buf[34..36].copy_from_slice(&protocol.reserved_2.to_le_bytes());
+    Ok(HEADER_SIZE)
+}
+
+/// Writes a message into buf, returning its length. The payload is copied as
+/// is, so must already be in wire order.
+pub fn encode(header: &Header, payload: &[u8], buf: &mut [u8]) -> Result<usize, CodecError> {
+    encode_with(header, buf, |w| w.bytes(payload))
+}
+
+/// Writes a message into buf, the payload written in place by the closure,
+/// returning the message's length.
+///
+/// ```
+/// # use rustylifx::codec::{self, Header};
+/// # fn set_color(header: &Header) -> Result<(), codec::CodecError> {
+/// let mut buf = [0u8; 64];
+/// let len = codec::encode_with(header, &mut buf, |w| {
+///     w.u8(0)?;
+///     w.hsbk(21845, 65535, 32768, 3500)?;
+///     w.u32(250)
+/// })?;
+/// assert_eq!(len, 49);
+/// # Ok(())
+/// # }
+/// ```
+pub fn encode_with<F>(header: &Header, buf: &mut [u8], payload: F) -> Result<usize, CodecError>
+where
+    F: FnOnce(&mut Writer) -> Result<(), CodecError>,
+{
+    let payload_len = {
+        let mut writer = Writer::new(slice_mut(buf, HEADER_SIZE, 0)?);
+        // Short buffer errors are reported in terms of the whole message.
+        payload(&mut writer).map_err(|e| match e {
+            CodecError::ShortBuffer { needed, available } => CodecError::ShortBuffer {
+                needed: HEADER_SIZE + needed,
+                available: HEADER_SIZE + available,
+            },
+            e => e,
+        })?;
+        writer.position()
+    };
+    encode_header(header, payload_len, buf)?;
+    Ok(HEADER_SIZE + payload_len)
+}
+
+/// Reads the header at the start of a message.
+pub fn decode_header(bytes: &[u8]) -> Result<Header, CodecError> {
+    let mut r = Reader::new(slice(bytes, 0, HEADER_SIZE)?);
+    let size = r.u16()?;
+    let frame_bits = r.u16()?;
+    let source = r.u32()?;
+    let mut target = [0u8; 8];
+    target.copy_from_slice(r.bytes(8)?);
+    let mut reserved = [0u8; 6];
+    reserved.copy_from_slice(r.bytes(6)?);
+    reserved.reverse();
+    let flags = r.u8()?;
+    let sequence = r.u8()?;
+
+    Ok(Header {
+        frame: Frame {
+            size,
+            origin: (frame_bits >> 14) as u8,
+            tagged: frame_bits & 0x2000 != 0,
+            addressable: frame_bits & 0x1000 != 0,
+            protocol: frame_bits & 0x0FFF,
+            source,
+        },
+        frame_address: FrameAddress {
+            target,
+            reserved,
+            reserved_2: flags >> 2,
+            ack_required: flags & 0x02 != 0,
+            res_required: flags & 0x01 != 0,
+            sequence,
+        },
+        protocol_header: ProtocolHeader {
+            reserved: r.u64()?,
+            message_type: r.u16()?,
+            reserved_2: r.u16()?,
+        },
+    })
+}
+
+/// Reads a message's header, and returns a reader over its payload.
+pub fn decode(bytes: &[u8]) -> Result<(Header, Reader<'_>), CodecError> {
+    let header = decode_header(bytes)?;
+    Ok((header, Reader::new(&bytes[HEADER_SIZE..])))
+}
+
+fn slice(bytes: &[u8], start: usize, len: usize) -> Result<&[u8], CodecError> {
+    if bytes.len() < start + len {
+        return Err(CodecError::ShortBuffer {
+            needed: start + len,
+            available: bytes.len(),
+        });
+    }
+    Ok(&bytes[start..])
+}
+
+fn slice_mut(buf: &mut [u8], start: usize, len: usize) -> Result<&mut [u8], CodecError> {
+    if buf.len() < start + len {
+        return Err(CodecError::ShortBuffer {
+            needed: start + len,
+            available: buf.len(),
+        });
+    }
+    Ok(&mut buf[start..])
+}
+
+/// Writes little-endian fields one after another into a buffer.
+pub struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Writer<'a> {
+        Writer { buf, pos: 0 }
+    }
+
+    /// How many bytes have been written.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> Result<(), CodecError> {
+        let end = self.pos + bytes.len();
+        slice_mut(self.buf, self.pos, bytes.len())?[..bytes.len()].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    /// Writes len zero bytes, for reserved fields.
+    pub fn zeros(&mut self, len: usize) -> Result<(), CodecError> {
+        for b in &mut slice_mut(self.buf, self.pos, len)?[..len] {
+            *b = 0;
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    pub fn u8(&mut self, v: u8) -> Result<(), CodecError> {
+        self.bytes(&[v])
+    }
+
+    pub fn u16(&mut self, v: u16) -> Result<(), CodecError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, v: u32) -> Result<(), CodecError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, v: u64) -> Result<(), CodecError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    pub fn f32(&mut self, v: f32) -> Result<(), CodecError> {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    pub fn hsbk(
+        &mut self,
+        hue: u16,
+        saturation: u16,
+        brightness: u16,
+        kelvin: u16,
+    ) -> Result<(), CodecError> {
+        self.u16(hue)?;
+        self.u16(saturation)?;
+        self.u16(brightness)?;
+        self.u16(kelvin)
+    }
+}
+
+/// Reads little-endian fields one after another from a buffer.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    /// How many bytes are left to read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        let bytes = self.bytes;
+        let b = &slice(bytes, self.pos, len)?[..len];
+        self.pos += len;
+        Ok(b)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), CodecError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, CodecError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, CodecError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, CodecError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, CodecError> {
+        let mut a = [0u8; 8];
+        a.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(a))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, CodecError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    /// Reads hue, saturation, brightness and kelvin.
+    pub fn hsbk(&mut self) -> Result<[u16; 4], CodecError> {
+        Ok([self.u16()?, self.u16()?, self.u16()?, self.u16()?])
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{decode, decode_header, encode, encode_with, CodecError, Header};
+    use colour::Hsbk;
+    use messages::set_colour_payload;
+    use request::{Frame, FrameAddress, Payload, ProtocolHeader, Request, RequestBin};
+
+    fn header(message_type: u16) -> Header {
+        Header::new(
+            Frame::new(1, false, true, 1024, 0xDEAD_BEEF),
+            FrameAddress::new(
+                [0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0],
+                [1, 2, 3, 4, 5, 6],
+                5,
+                true,
+                false,
+                77,
+            ),
+            ProtocolHeader::new(0x0102_0304_0506_0708, message_type, 3),
+        )
+    }
+
+    #[test]
+    fn test_encode_matches_request_bin() {
+        let hsbk = Hsbk::new(21845, 65535, 32768, 3500);
+        let expected =
+            RequestBin::from(Request::new(header(102), set_colour_payload(&hsbk, 250))).0;
+
+        let mut buf = [0u8; 64];
+        let len = encode_with(&header(102), &mut buf, |w| {
+            w.u8(0)?;
+            w.hsbk(hsbk.hue, hsbk.saturation, hsbk.brightness, hsbk.kelvin)?;
+            w.u32(250)
+        })
+        .unwrap();
+        assert_eq!(&buf[..len], &expected[..]);
+
+        let expected = RequestBin::from(Request::new(header(2), Payload(vec![]))).0;
+        let len = encode(&header(2), &[], &mut buf).unwrap();
+        assert_eq!(&buf[..len], &expected[..]);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut buf = [0u8; 64];
+        let len = encode(&header(22), &[0xFF, 0xFF], &mut buf).unwrap();
+
+        let (decoded, mut payload) = decode(&buf[..len]).unwrap();
+        assert_eq!(
+            decoded,
+            Header {
+                frame: Frame {
+                    size: 38,
+                    ..header(22).frame
+                },
+                ..header(22)
+            }
+        );
+        assert_eq!(payload.u16(), Ok(65535));
+        assert_eq!(payload.remaining(), 0);
+        assert_eq!(
+            payload.u8(),
+            Err(CodecError::ShortBuffer {
+                needed: 3,
+                available: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_short_buffers() {
+        let mut buf = [0u8; 40];
+        assert_eq!(
+            encode(&header(2), &[0; 8], &mut buf),
+            Err(CodecError::ShortBuffer {
+                needed: 44,
+                available: 40
+            })
+        );
+        assert_eq!(
+            decode_header(&buf[..35]),
+            Err(CodecError::ShortBuffer {
+                needed: 36,
+                available: 35
+            })
+        );
+        assert_eq!(
+            encode(&header(2), &[0; 65500], &mut vec![0; 65600]),
+            Err(CodecError::TooLong(65536))
+        );
+    }
+}
@@ -1,12 +1,21 @@
 use super::request::*;
 
+use css;
+use products::Product;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 /// Hsb colour representation - hue, saturation, brightness (aka value).
 /// Aka HSV (LIFX terminology) - hue, saturation, value.
 /// This is not the same as HSL as used in CSS.
 /// LIFX uses Hsb aka HSV, not HSL.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Hsb {
     pub hue: u16,
     pub saturation: u8,
@@ -14,6 +23,8 @@ pub struct Hsb {
 }
 
 ///  Rgb colour representation - red, green, blue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Rgb {
     pub red: u8,
     pub green: u8,
@@ -22,14 +33,50 @@ pub struct Rgb {
 
 /// Hsbk colour representation - hue, saturation, brightness, kelvin.
 /// Kelvin seems to be relevant only to whites - temperature of white.
-#[derive(Debug)]
+///
+/// This is the colour as sent to and received from devices, each element held in
+/// the protocol's two byte word, so nothing is lost between a get and a set.
+/// Hue words wrap around the colour wheel, a step of 1 being 360/65536 degrees,
+/// saturation and brightness words span 0 to 100 percent, and kelvin is in
+/// degrees kelvin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Hsbk {
     pub hue: u16,
-    pub saturation: u8,
-    pub brightness: u8,
+    pub saturation: u16,
+    pub brightness: u16,
     pub kelvin: u16,
 }
 
+/// CIE 1931 chromaticity coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Xy {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Oklab colour representation - perceptual lightness, and green-red and
+/// blue-yellow axes. Distances in Oklab roughly match perceived differences,
+/// which makes it a good space to mix colours in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Oklab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+/// Oklch colour representation - Oklab in polar form, lightness, chroma and hue
+/// in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Oklch {
+    pub l: f64,
+    pub c: f64,
+    pub h: f64,
+}
+
 impl Hsb {
     pub fn new(h: u16, s: u8, b: u8) -> Hsb {
         Hsb {
@@ -40,30 +87,419 @@ impl Hsb {
     }
 }
 
+impl Hsbk {
+    pub fn new(hue: u16, saturation: u16, brightness: u16, kelvin: u16) -> Hsbk {
+        Hsbk {
+            hue,
+            saturation,
+            brightness,
+            kelvin,
+        }
+    }
+
+    /// Builds a colour from degrees and percentages, which may be fractional.
+    /// Values out of range are clamped, except hue which wraps.
+    pub fn from_degrees(hue: f64, saturation: f64, brightness: f64, kelvin: u16) -> Hsbk {
+        Hsbk {
+            hue: hue_to_word(hue),
+            saturation: percent_to_word(saturation),
+            brightness: percent_to_word(brightness),
+            kelvin,
+        }
+    }
+
+    /// Builds a colour from whole degrees and percentages.
+    pub fn from_hsb(hsb: &Hsb, kelvin: u16) -> Hsbk {
+        Hsbk::from_degrees(
+            f64::from(hsb.hue),
+            f64::from(hsb.saturation),
+            f64::from(hsb.brightness),
+            kelvin,
+        )
+    }
+
+    /// The hue in degrees, from 0 to 360. Converting back with `from_degrees`
+    /// gives the same word.
+    pub fn hue_degrees(&self) -> f64 {
+        f64::from(self.hue) * DEGREES_UBOUND as f64 / HUE_STEPS
+    }
+
+    /// The saturation in percent, from 0 to 100.
+    pub fn saturation_percent(&self) -> f64 {
+        word_to_percent(self.saturation)
+    }
+
+    /// The brightness in percent, from 0 to 100.
+    pub fn brightness_percent(&self) -> f64 {
+        word_to_percent(self.brightness)
+    }
+
+    /// Builds a colour from Rgb, without rounding hue, saturation and
+    /// brightness to whole degrees and percentages.
+    pub fn from_rgb(rgb: Rgb, kelvin: u16) -> Hsbk {
+        let (h, s, v) = rgb_to_hsv_f64(rgb);
+        Hsbk::from_degrees(h, s * 100.0, v * 100.0, kelvin)
+    }
+
+    /// The Rgb colour for display. Kelvin is ignored.
+    pub fn to_rgb(&self) -> Rgb {
+        hsv_f64_to_rgb(
+            self.hue_degrees(),
+            self.saturation_percent() / 100.0,
+            self.brightness_percent() / 100.0,
+        )
+    }
+}
+
+/// The kelvin given to parsed colours that don't set one.
+pub const DEFAULT_KELVIN: u16 = 3500;
+
+/// Parses a colour from text, as a base colour followed by any adjustments,
+/// separated by spaces. Later parts override earlier ones.
+///
+/// Base colours are one of `named_colours`, otherwise a CSS name, a white
+/// preset such as `warm`, `#rrggbb`,
+/// `rgb(r, g, b)`, or `hsb(h, s, b)` with hue in degrees and saturation and
+/// brightness in percent. Adjustments are `hue:120`, `saturation:50%`,
+/// `brightness:50%` and `kelvin:3500`.
+///
+/// ```text
+/// red
+/// #ff8800 brightness:40%
+/// hsb(120, 100%, 50%)
+/// kelvin:2700 brightness:30%
+/// ```
+///
+/// Without a base colour, adjustments apply to full brightness white.
+impl FromStr for Hsbk {
+    type Err = ParseColourError;
+
+    fn from_str(s: &str) -> Result<Hsbk, ParseColourError> {
+        let parts = split_colour(s)?;
+        if parts.is_empty() {
+            return Err(ParseColourError::new("empty colour"));
+        }
+
+        let mut colour = Hsbk::from_degrees(0.0, 0.0, 100.0, DEFAULT_KELVIN);
+        for (i, part) in parts.iter().enumerate() {
+            match part.find(':') {
+                Some(n) => adjust(&mut colour, &part[..n], &part[n + 1..])?,
+                None if i == 0 => colour = base_colour(part)?,
+                None => {
+                    return Err(ParseColourError::new(&format!(
+                        "unexpected {:?}, only the first part can be a colour",
+                        part
+                    )))
+                }
+            }
+        }
+
+        Ok(colour)
+    }
+}
+
+/// Why a colour couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseColourError(String);
+
+impl ParseColourError {
+    fn new(msg: &str) -> ParseColourError {
+        ParseColourError(msg.to_string())
+    }
+}
+
+impl fmt::Display for ParseColourError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid colour: {}", self.0)
+    }
+}
+
+impl Error for ParseColourError {}
+
+// Splits on whitespace outside parentheses, so `rgb(1, 2, 3)` stays whole.
+fn split_colour(s: &str) -> Result<Vec<String>, ParseColourError> {
+    let mut parts: Vec<String> = vec![];
+    let mut part = String::new();
+    let mut depth = 0;
+
+    for c in s.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(ParseColourError::new("unmatched ')'")),
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if !part.is_empty() {
+                parts.push(part.clone());
+                part.clear();
+            }
+        } else if !c.is_whitespace() {
+            part.push(c);
+        }
+    }
+    if depth != 0 {
+        return Err(ParseColourError::new("unmatched '('"));
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+
+    Ok(parts)
+}
+
+fn base_colour(s: &str) -> Result<Hsbk, ParseColourError> {
+    let lower = s.to_lowercase();
+
+    if lower.starts_with('#') {
+        return hex_to_rgb(s)
+            .map(|rgb| Hsbk::from_rgb(rgb, DEFAULT_KELVIN))
+            .ok_or_else(|| ParseColourError::new(&format!("{:?} is not #rgb or #rrggbb hex", s)));
+    }
+
+    if let Some(args) = function_args(&lower, "rgb")? {
+        let v = args
+            .iter()
+            .map(|a| {
+                a.parse::<u8>().map_err(|_| {
+                    ParseColourError::new(&format!("rgb() value {:?} is not 0-255", a))
+                })
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        let rgb = Rgb {
+            red: v[0],
+            green: v[1],
+            blue: v[2],
+        };
+        return Ok(Hsbk::from_rgb(rgb, DEFAULT_KELVIN));
+    }
+
+    if let Some(args) = function_args(&lower, "hsb")? {
+        return Ok(Hsbk::from_degrees(
+            parse_number("hue", &args[0], 0.0, 360.0)?,
+            parse_percent("saturation", &args[1])?,
+            parse_percent("brightness", &args[2])?,
+            DEFAULT_KELVIN,
+        ));
+    }
+
+    if let Some(kelvin) = get_white(&lower) {
+        return Ok(kelvin.white(100.0));
+    }
+    // The older named colours come first, so that names such as `red`, which
+    // are also CSS names, keep the colours they've always had.
+    if named_colours().contains(&lower) {
+        return Ok(Hsbk::from_hsb(&get_colour(&lower), DEFAULT_KELVIN));
+    }
+    if let Some(rgb) = css::lookup(&lower) {
+        return Ok(Hsbk::from_rgb(rgb, DEFAULT_KELVIN));
+    }
+
+    Err(ParseColourError::new(&format!("unknown colour {:?}", s)))
+}
+
+// The three arguments of `name(a, b, c)`, or None if s isn't a call to name.
+fn function_args(s: &str, name: &str) -> Result<Option<Vec<String>>, ParseColourError> {
+    let args = match s
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        Some(args) => args,
+        None => return Ok(None),
+    };
+
+    let args: Vec<String> = args.split(',').map(|a| a.trim().to_string()).collect();
+    if args.len() != 3 {
+        return Err(ParseColourError::new(&format!(
+            "{}() takes 3 values, got {}",
+            name,
+            args.len()
+        )));
+    }
+    Ok(Some(args))
+}
+
+fn adjust(colour: &mut Hsbk, key: &str, value: &str) -> Result<(), ParseColourError> {
+    match key.to_lowercase().as_str() {
+        "hue" | "h" => {
+            let hue = parse_number("hue", value, 0.0, 360.0)?;
+            colour.hue = hue_to_word(hue);
+        }
+        "saturation" | "sat" | "s" => {
+            colour.saturation = percent_to_word(parse_percent("saturation", value)?);
+        }
+        "brightness" | "bri" | "b" => {
+            colour.brightness = percent_to_word(parse_percent("brightness", value)?);
+        }
+        "kelvin" | "k" => {
+            let kelvin = parse_number(
+                "kelvin",
+                value,
+                f64::from(MIN_KELVIN),
+                f64::from(MAX_KELVIN),
+            )?;
+            colour.kelvin = kelvin.round() as u16;
+        }
+        _ => {
+            return Err(ParseColourError::new(&format!(
+                "unknown setting {:?}, expected hue, saturation, brightness or kelvin",
+                key
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_percent(name: &str, value: &str) -> Result<f64, ParseColourError> {
+    parse_number(name, value.trim_end_matches('%'), 0.0, 100.0)
+}
+
+fn parse_number(name: &str, value: &str, min: f64, max: f64) -> Result<f64, ParseColourError> {
+    match value.parse::<f64>() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(ParseColourError::new(&format!(
+            "{} must be {} to {}, got {:?}",
+            name, min, max, value
+        ))),
+    }
+}
+
+/// A colour temperature in kelvin, within the range LIFX lights accept.
+///
+/// Lights reject kelvin outside their own range, which is often narrower, so
+/// clamp to the product's range with `for_product` when it's known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "u16", into = "u16")
+)]
+pub struct Kelvin(u16);
+
+/// The lowest colour temperature any LIFX light accepts.
+pub const MIN_KELVIN: u16 = 1500;
+/// The highest colour temperature any LIFX light accepts.
+pub const MAX_KELVIN: u16 = 9000;
+
+impl Kelvin {
+    /// A colour temperature, clamped to the range LIFX lights accept.
+    pub fn new(kelvin: u16) -> Kelvin {
+        Kelvin(kelvin.clamp(MIN_KELVIN, MAX_KELVIN))
+    }
+
+    /// A colour temperature, clamped to the range of the product if known.
+    pub fn for_product(kelvin: u16, product: Option<&Product>) -> Kelvin {
+        match product {
+            Some(p) => Kelvin(kelvin.clamp(p.min_kelvin, p.max_kelvin)),
+            None => Kelvin::new(kelvin),
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+
+    /// A white of this temperature, at the brightness in percent.
+    pub fn white(&self, brightness: f64) -> Hsbk {
+        Hsbk::from_degrees(0.0, 0.0, brightness, self.0)
+    }
+
+    /// An approximation of the colour of this temperature, for display.
+    ///
+    /// Uses Tanner Helland's fit to the blackbody curve, which is close enough
+    /// to show warm and cool whites but is not colorimetrically accurate.
+    pub fn to_rgb(&self) -> Rgb {
+        let t = f64::from(self.0) / 100.0;
+
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698_727_446 * (t - 60.0).powf(-0.133_204_759_2)
+        };
+        let green = if t <= 66.0 {
+            99.470_802_586_1 * t.ln() - 161.119_568_166_1
+        } else {
+            288.122_169_528_3 * (t - 60.0).powf(-0.075_514_849_2)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.517_731_223_1 * (t - 10.0).ln() - 305.044_792_730_7
+        };
+
+        Rgb {
+            red: unit_to_byte(red / 255.0),
+            green: unit_to_byte(green / 255.0),
+            blue: unit_to_byte(blue / 255.0),
+        }
+    }
+}
+
+impl From<Kelvin> for u16 {
+    fn from(k: Kelvin) -> u16 {
+        k.0
+    }
+}
+
+/// Clamps, as `Kelvin::new` does.
+impl From<u16> for Kelvin {
+    fn from(k: u16) -> Kelvin {
+        Kelvin::new(k)
+    }
+}
+
 impl From<Hsbk> for Hsb {
     fn from(c: Hsbk) -> Hsb {
-        Hsb::new(c.hue, c.saturation, c.brightness)
+        Hsb::new(
+            hue_word_to_degrees(c.hue),
+            saturation_word_to_percent(c.saturation),
+            brightness_word_to_percent(c.brightness),
+        )
     }
 }
 
 /// The max value of the two byte representation of colour element as used in the protocol.
 const WORD_SIZE: usize = 65535;
+/// Hue wraps, so the word one past the max is 360 degrees, the same as 0.
+const HUE_STEPS: f64 = 65536.0;
 const DEGREES_UBOUND: usize = 360;
 const PERCENT_UBOUND: usize = 100;
 
-// (WORD_SIZE / DEGREES_UBOUND) is ~182.0417
-// The two-byte represenation only represents integers, so decimals will be truncated.
-// This can result in a get_state returning a slightly different result from the
-// preceding set_state for hue, saturation, and brightness.
+// (HUE_STEPS / DEGREES_UBOUND) is ~182.0444
+// Whole degrees and percentages can't represent every word, so the functions
+// below round to the nearest. Hsbk holds the words themselves, and converts to
+// and from fractional degrees and percentages without loss.
+
+fn hue_to_word(degrees: f64) -> u16 {
+    let d = degrees % DEGREES_UBOUND as f64;
+    let d = if d < 0.0 {
+        d + DEGREES_UBOUND as f64
+    } else {
+        d
+    };
+    ((d * HUE_STEPS / DEGREES_UBOUND as f64).round() as u32 % 65536) as u16
+}
+
+fn percent_to_word(percent: f64) -> u16 {
+    let p = percent.max(0.0).min(PERCENT_UBOUND as f64);
+    (p * WORD_SIZE as f64 / PERCENT_UBOUND as f64).round() as u16
+}
+
+fn word_to_percent(word: u16) -> f64 {
+    f64::from(word) * PERCENT_UBOUND as f64 / WORD_SIZE as f64
+}
 
 pub fn hue_degrees_to_word(degrees: u16) -> [u8; 2] {
-    let f = degrees as f64 * WORD_SIZE as f64 / DEGREES_UBOUND as f64;
-    let b = RequestBin::u16_to_u8_array(f.round() as u16);
+    let b = RequestBin::u16_to_u8_array(hue_to_word(f64::from(degrees)));
     [b[0], b[1]]
 }
 
 pub fn hue_word_to_degrees(word: u16) -> u16 {
-    (word as usize * 360 / WORD_SIZE) as u16
+    Hsbk::new(word, 0, 0, 0).hue_degrees().round() as u16
 }
 
 pub fn saturation_percent_to_word(percent: u8) -> [u8; 2] {
@@ -73,7 +509,7 @@ pub fn saturation_percent_to_word(percent: u8) -> [u8; 2] {
 }
 
 pub fn saturation_word_to_percent(word: u16) -> u8 {
-    (word as usize * 100 / WORD_SIZE) as u8
+    word_to_percent(word).round() as u8
 }
 
 pub fn brightness_percent_to_word(percent: u8) -> [u8; 2] {
@@ -81,15 +517,34 @@ pub fn brightness_percent_to_word(percent: u8) -> [u8; 2] {
 }
 
 pub fn brightness_word_to_percent(word: u16) -> u8 {
-    (word as usize * 100 / WORD_SIZE) as u8
+    word_to_percent(word).round() as u8
 }
 
 pub fn rgb_to_hsv(rgb: Rgb) -> Hsb {
-    let r1 = rgb.red as f32 / 255.0;
-    let g1 = rgb.green as f32 / 255.0;
-    let b1 = rgb.blue as f32 / 255.0;
+    let (h, s, v) = rgb_to_hsv_f64(rgb);
+
+    Hsb {
+        hue: h as u16,
+        saturation: (s * 100.0) as u8,
+        brightness: (v * 100.0) as u8,
+    }
+}
 
-    let mut floats: Vec<f32> = vec![r1, g1, b1];
+pub fn hsv_to_rgb(hsb: Hsb) -> Rgb {
+    hsv_f64_to_rgb(
+        f64::from(hsb.hue),
+        f64::from(hsb.saturation) / 100.0,
+        f64::from(hsb.brightness) / 100.0,
+    )
+}
+
+// Hue in degrees, saturation and value from 0 to 1.
+fn rgb_to_hsv_f64(rgb: Rgb) -> (f64, f64, f64) {
+    let r1 = f64::from(rgb.red) / 255.0;
+    let g1 = f64::from(rgb.green) / 255.0;
+    let b1 = f64::from(rgb.blue) / 255.0;
+
+    let mut floats: Vec<f64> = vec![r1, g1, b1];
     floats.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
     let cmax = floats[2];
     let cmin = floats[0];
@@ -97,13 +552,15 @@ pub fn rgb_to_hsv(rgb: Rgb) -> Hsb {
 
     // Hue.
     let h = match cmax {
-        _ if r1 == cmax => (((g1 - b1) / d) % 6.0) * 60.0,
+        _ if d == 0.0 => 0.0,
+        _ if r1 == cmax => (((g1 - b1) / d).rem_euclid(6.0)) * 60.0,
         _ if g1 == cmax => (((b1 - r1) / d) + 2.0) * 60.0,
         _ if b1 == cmax => (((r1 - g1) / d) + 4.0) * 60.0,
         _ => 0.0,
     };
 
     // Saturation.
+    #[allow(clippy::redundant_guards)]
     let s = match cmax {
         x if x == 0.0 => 0.0,
         //0.0 => 0.0,
@@ -113,17 +570,194 @@ pub fn rgb_to_hsv(rgb: Rgb) -> Hsb {
     // Value / brightness.
     let v = cmax;
 
-    Hsb {
-        hue: h as u16,
-        saturation: (s * 100.0) as u8,
-        brightness: (v * 100.0) as u8,
+    (h, s, v)
+}
+
+fn hsv_f64_to_rgb(h: f64, s: f64, v: f64) -> Rgb {
+    let c = v * s;
+    let h1 = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h1 % 2.0 - 1.0).abs());
+    let (r1, g1, b1) = match h1 as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+
+    Rgb {
+        red: unit_to_byte(r1 + m),
+        green: unit_to_byte(g1 + m),
+        blue: unit_to_byte(b1 + m),
+    }
+}
+
+/// Parses a hex colour, as in CSS - `#ff8800`, `ff8800` or the short `#f80`.
+pub fn hex_to_rgb(hex: &str) -> Option<Rgb> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if !digits.is_ascii() {
+        return None;
+    }
+
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match digits.len() {
+        6 => Some(Rgb {
+            red: channel(&digits[0..2])?,
+            green: channel(&digits[2..4])?,
+            blue: channel(&digits[4..6])?,
+        }),
+        3 => Some(Rgb {
+            red: channel(&digits[0..1])? * 17,
+            green: channel(&digits[1..2])? * 17,
+            blue: channel(&digits[2..3])? * 17,
+        }),
+        _ => None,
+    }
+}
+
+/// Formats a colour as lower case hex, as in CSS - `#ff8800`.
+pub fn rgb_to_hex(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb.red, rgb.green, rgb.blue)
+}
+
+/// Converts sRGB to CIE 1931 xy chromaticity, and relative luminance (Y) from 0
+/// to 1. Black has no chromaticity, so is given the D65 white point.
+pub fn rgb_to_xy(rgb: Rgb) -> (Xy, f64) {
+    let (r, g, b) = linear_rgb(rgb);
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+
+    let sum = x + y + z;
+    if sum == 0.0 {
+        return (D65, 0.0);
+    }
+    (
+        Xy {
+            x: x / sum,
+            y: y / sum,
+        },
+        y,
+    )
+}
+
+/// Converts CIE 1931 xy chromaticity and relative luminance (Y) to sRGB. Colours
+/// outside the sRGB gamut are clipped.
+pub fn xy_to_rgb(xy: Xy, luminance: f64) -> Rgb {
+    if xy.y == 0.0 {
+        return Rgb {
+            red: 0,
+            green: 0,
+            blue: 0,
+        };
+    }
+
+    let y = luminance;
+    let x = xy.x * y / xy.y;
+    let z = (1.0 - xy.x - xy.y) * y / xy.y;
+
+    let r = 3.2406 * x - 1.5372 * y - 0.4986 * z;
+    let g = -0.9689 * x + 1.8758 * y + 0.0415 * z;
+    let b = 0.0557 * x - 0.2040 * y + 1.0570 * z;
+
+    from_linear_rgb(r, g, b)
+}
+
+/// The chromaticity of D65, the white point of sRGB.
+pub const D65: Xy = Xy {
+    x: 0.3127,
+    y: 0.3290,
+};
+
+pub fn rgb_to_oklab(rgb: Rgb) -> Oklab {
+    let (r, g, b) = linear_rgb(rgb);
+
+    let l = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+    let m = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+    let s = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+
+    Oklab {
+        l: 0.210_454_255_3 * l + 0.793_617_785_0 * m - 0.004_072_046_8 * s,
+        a: 1.977_998_495_1 * l - 2.428_592_205_0 * m + 0.450_593_709_9 * s,
+        b: 0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766_0 * s,
+    }
+}
+
+/// Converts Oklab to sRGB. Colours outside the sRGB gamut are clipped.
+pub fn oklab_to_rgb(lab: Oklab) -> Rgb {
+    let l = lab.l + 0.396_337_777_4 * lab.a + 0.215_803_757_3 * lab.b;
+    let m = lab.l - 0.105_561_345_8 * lab.a - 0.063_854_172_8 * lab.b;
+    let s = lab.l - 0.089_484_177_5 * lab.a - 1.291_485_548_0 * lab.b;
+
+    let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+
+    from_linear_rgb(
+        4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s,
+        -1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s,
+        -0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701_0 * s,
+    )
+}
+
+pub fn oklab_to_oklch(lab: Oklab) -> Oklch {
+    Oklch {
+        l: lab.l,
+        c: (lab.a * lab.a + lab.b * lab.b).sqrt(),
+        h: lab.b.atan2(lab.a).to_degrees().rem_euclid(360.0),
     }
 }
 
+pub fn oklch_to_oklab(lch: Oklch) -> Oklab {
+    let h = lch.h.to_radians();
+    Oklab {
+        l: lch.l,
+        a: lch.c * h.cos(),
+        b: lch.c * h.sin(),
+    }
+}
+
+// Removes the sRGB transfer function, giving linear light from 0 to 1.
+fn linear_rgb(rgb: Rgb) -> (f64, f64, f64) {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    (linear(rgb.red), linear(rgb.green), linear(rgb.blue))
+}
+
+// Applies the sRGB transfer function to linear light, clipping to the gamut.
+fn from_linear_rgb(r: f64, g: f64, b: f64) -> Rgb {
+    let encode = |c: f64| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if c <= 0.003_130_8 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        unit_to_byte(c)
+    };
+    Rgb {
+        red: encode(r),
+        green: encode(g),
+        blue: encode(b),
+    }
+}
+
+fn unit_to_byte(c: f64) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
 
     use colour::*;
+    use products;
 
     #[test]
     fn test_hue_degrees_to_word() {
@@ -131,6 +765,15 @@ mod tests {
         assert_eq!([0x47, 0x1C], hue_degrees_to_word(100));
         assert_eq!([0x44, 0x44], hue_degrees_to_word(96));
         assert_eq!([0x43, 0x8E], hue_degrees_to_word(95));
+        assert_eq!(hue_degrees_to_word(360), hue_degrees_to_word(0));
+        for degrees in 0..360 {
+            let word = u16::from_be_bytes(hue_degrees_to_word(degrees));
+            assert_eq!(
+                word,
+                Hsbk::from_degrees(f64::from(degrees), 0.0, 0.0, 0).hue
+            );
+            assert_eq!(hue_word_to_degrees(word), degrees);
+        }
     }
 
     #[test]
@@ -150,7 +793,7 @@ mod tests {
         struct Test {
             rgb: Rgb,
             hsb: Hsb,
-        };
+        }
 
         let tests = vec![
             Test {
@@ -188,6 +831,280 @@ mod tests {
             assert_eq!(res.brightness, t.hsb.brightness);
         }
     }
+
+    #[test]
+    fn test_hsbk_degrees_round_trip() {
+        for word in 0..=65535u16 {
+            let c = Hsbk::new(word, word, word, 3500);
+            let back = Hsbk::from_degrees(
+                c.hue_degrees(),
+                c.saturation_percent(),
+                c.brightness_percent(),
+                c.kelvin,
+            );
+            assert_eq!(back, c);
+        }
+    }
+
+    #[test]
+    fn test_hsb_round_trip() {
+        for percent in 0..=100u8 {
+            let hsb = Hsb::new(u16::from(percent) * 3, percent, percent);
+            assert_eq!(Hsb::from(Hsbk::from_hsb(&hsb, 3500)), hsb);
+        }
+    }
+
+    #[test]
+    fn test_hsbk_from_degrees_bounds() {
+        let c = Hsbk::from_degrees(-120.0, 150.0, -5.0, 3500);
+        assert_eq!(c.hue, 43691);
+        assert_eq!(c.saturation, 65535);
+        assert_eq!(c.brightness, 0);
+        assert_eq!(Hsbk::from_degrees(360.0, 0.0, 0.0, 0).hue, 0);
+    }
+
+    #[test]
+    fn test_rgb_to_hsv_hue_wraps() {
+        let hsb = rgb_to_hsv(Rgb {
+            red: 255,
+            green: 0,
+            blue: 128,
+        });
+        assert_eq!(hsb.hue, 329);
+    }
+
+    #[test]
+    fn test_hsv_to_rgb() {
+        let tests = vec![
+            (Hsb::new(0, 100, 100), (255, 0, 0)),
+            (Hsb::new(120, 100, 100), (0, 255, 0)),
+            (Hsb::new(240, 100, 100), (0, 0, 255)),
+            (Hsb::new(60, 100, 50), (128, 128, 0)),
+            (Hsb::new(39, 100, 100), (255, 166, 0)),
+            (Hsb::new(300, 0, 75), (191, 191, 191)),
+            (Hsb::new(330, 100, 100), (255, 0, 128)),
+        ];
+
+        for (hsb, (red, green, blue)) in tests {
+            assert_eq!(hsv_to_rgb(hsb), Rgb { red, green, blue }, "{:?}", hsb);
+        }
+    }
+
+    #[test]
+    fn test_hsbk_rgb_round_trip() {
+        for &(red, green, blue) in &[(255, 136, 0), (12, 34, 56), (0, 0, 0), (255, 255, 255)] {
+            let rgb = Rgb { red, green, blue };
+            assert_eq!(Hsbk::from_rgb(rgb, 3500).to_rgb(), rgb);
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        let orange = Rgb {
+            red: 255,
+            green: 136,
+            blue: 0,
+        };
+        assert_eq!(hex_to_rgb("#ff8800"), Some(orange));
+        assert_eq!(hex_to_rgb("FF8800"), Some(orange));
+        assert_eq!(hex_to_rgb("#f80"), Some(orange));
+        assert_eq!(hex_to_rgb("#ff880"), None);
+        assert_eq!(hex_to_rgb("#gg8800"), None);
+        assert_eq!(hex_to_rgb("#ff88é"), None);
+        assert_eq!(rgb_to_hex(orange), "#ff8800");
+    }
+
+    #[test]
+    fn test_rgb_to_xy() {
+        let (xy, y) = rgb_to_xy(Rgb {
+            red: 255,
+            green: 255,
+            blue: 255,
+        });
+        assert!((xy.x - D65.x).abs() < 0.001, "{:?}", xy);
+        assert!((xy.y - D65.y).abs() < 0.001, "{:?}", xy);
+        assert!((y - 1.0).abs() < 0.001);
+
+        let (xy, _) = rgb_to_xy(Rgb {
+            red: 255,
+            green: 0,
+            blue: 0,
+        });
+        assert!((xy.x - 0.64).abs() < 0.001, "{:?}", xy);
+        assert!((xy.y - 0.33).abs() < 0.001, "{:?}", xy);
+    }
+
+    #[test]
+    fn test_xy_round_trip() {
+        for &(red, green, blue) in &[(255, 136, 0), (12, 34, 56), (0, 0, 255), (200, 200, 200)] {
+            let rgb = Rgb { red, green, blue };
+            let (xy, y) = rgb_to_xy(rgb);
+            assert_eq!(xy_to_rgb(xy, y), rgb);
+        }
+    }
+
+    #[test]
+    fn test_rgb_to_oklab() {
+        let lab = rgb_to_oklab(Rgb {
+            red: 255,
+            green: 255,
+            blue: 255,
+        });
+        assert!((lab.l - 1.0).abs() < 0.001, "{:?}", lab);
+        assert!(lab.a.abs() < 0.001 && lab.b.abs() < 0.001, "{:?}", lab);
+
+        let lab = rgb_to_oklab(Rgb {
+            red: 255,
+            green: 0,
+            blue: 0,
+        });
+        assert!((lab.l - 0.628).abs() < 0.001, "{:?}", lab);
+        assert!((lab.a - 0.2249).abs() < 0.001, "{:?}", lab);
+        assert!((lab.b - 0.1258).abs() < 0.001, "{:?}", lab);
+
+        let lch = oklab_to_oklch(lab);
+        assert!((lch.c - 0.2577).abs() < 0.001, "{:?}", lch);
+        assert!((lch.h - 29.23).abs() < 0.01, "{:?}", lch);
+    }
+
+    #[test]
+    fn test_oklab_round_trip() {
+        for &(red, green, blue) in &[(255, 136, 0), (12, 34, 56), (0, 0, 255), (0, 0, 0)] {
+            let rgb = Rgb { red, green, blue };
+            let lab = rgb_to_oklab(rgb);
+            assert_eq!(oklab_to_rgb(lab), rgb);
+            assert_eq!(oklab_to_rgb(oklch_to_oklab(oklab_to_oklch(lab))), rgb);
+        }
+    }
+
+    #[test]
+    fn test_kelvin_clamps() {
+        assert_eq!(Kelvin::new(1000).value(), MIN_KELVIN);
+        assert_eq!(Kelvin::new(3500).value(), 3500);
+        assert_eq!(Kelvin::new(12000).value(), MAX_KELVIN);
+
+        let white = products::lookup(products::LIFX_VENDOR, 10);
+        assert_eq!(Kelvin::for_product(2500, white).value(), 2700);
+        assert_eq!(Kelvin::for_product(9000, white).value(), 6500);
+        assert_eq!(Kelvin::for_product(2500, None).value(), 2500);
+    }
+
+    #[test]
+    fn test_kelvin_to_rgb() {
+        let rgb = |k| {
+            let c = Kelvin::new(k).to_rgb();
+            (c.red, c.green, c.blue)
+        };
+        assert_eq!(rgb(1500), (255, 108, 0));
+        assert_eq!(rgb(6600), (255, 255, 255));
+        let cool = rgb(9000);
+        assert!(cool.0 < cool.2, "{:?}", cool);
+    }
+
+    #[test]
+    fn test_white_presets() {
+        for name in named_whites() {
+            assert!(get_white(&name).is_some(), "{}", name);
+        }
+        assert_eq!(get_white("Warm"), Some(Kelvin::new(2700)));
+        assert_eq!(get_white("purple"), None);
+
+        let w = get_white("neutral").unwrap().white(50.0);
+        assert_eq!((w.saturation, w.kelvin), (0, 3500));
+    }
+
+    #[test]
+    fn test_parse_colour() {
+        let tests = vec![
+            ("red", Hsbk::from_degrees(0.0, 100.0, 50.0, 3500)),
+            (
+                "Deep_Sky_Blue",
+                Hsbk::from_hsb(&get_colour("deep_sky_blue"), 3500),
+            ),
+            ("deepskyblue", "#00bfff".parse().unwrap()),
+            ("purple", "#800080".parse().unwrap()),
+            ("#ff0000", Hsbk::from_degrees(0.0, 100.0, 100.0, 3500)),
+            (
+                "rgb(0, 255, 0)",
+                Hsbk::from_degrees(120.0, 100.0, 100.0, 3500),
+            ),
+            (
+                "hsb(240, 50%, 25)",
+                Hsbk::from_degrees(240.0, 50.0, 25.0, 3500),
+            ),
+            ("kelvin:2700", Hsbk::from_degrees(0.0, 0.0, 100.0, 2700)),
+            ("warm", Hsbk::from_degrees(0.0, 0.0, 100.0, 2700)),
+            (
+                "red brightness:100%",
+                Hsbk::from_degrees(0.0, 100.0, 100.0, 3500),
+            ),
+            (
+                "  blue   kelvin:5000 saturation:20% ",
+                Hsbk::from_degrees(240.0, 20.0, 50.0, 5000),
+            ),
+            ("brightness:10%", Hsbk::from_degrees(0.0, 0.0, 10.0, 3500)),
+            (
+                "cornflower",
+                Hsbk::from_hsb(&get_colour("cornflower"), 3500),
+            ),
+        ];
+
+        for (s, want) in tests {
+            assert_eq!(s.parse::<Hsbk>(), Ok(want), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_parse_colour_errors() {
+        let tests = vec![
+            ("", "invalid colour: empty colour"),
+            ("purpel", "invalid colour: unknown colour \"purpel\""),
+            ("#ff00", "invalid colour: \"#ff00\" is not #rgb or #rrggbb hex"),
+            ("rgb(1, 2)", "invalid colour: rgb() takes 3 values, got 2"),
+            ("rgb(1, 2, 300)", "invalid colour: rgb() value \"300\" is not 0-255"),
+            ("rgb(1, 2, 3", "invalid colour: unmatched '('"),
+            (
+                "red brightness:150%",
+                "invalid colour: brightness must be 0 to 100, got \"150\"",
+            ),
+            (
+                "kelvin:100",
+                "invalid colour: kelvin must be 1500 to 9000, got \"100\"",
+            ),
+            (
+                "red blue",
+                "invalid colour: unexpected \"blue\", only the first part can be a colour",
+            ),
+            (
+                "red glow:1",
+                "invalid colour: unknown setting \"glow\", expected hue, saturation, brightness or kelvin",
+            ),
+        ];
+
+        for (s, want) in tests {
+            let err = s.parse::<Hsbk>().unwrap_err();
+            assert_eq!(err.to_string(), want, "{}", s);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let hsbk = Hsbk::new(21845, 65535, 32768, 3500);
+        let json = serde_json::to_string(&hsbk).unwrap();
+        assert_eq!(
+            json,
+            r#"{"hue":21845,"saturation":65535,"brightness":32768,"kelvin":3500}"#
+        );
+        assert_eq!(serde_json::from_str::<Hsbk>(&json).unwrap(), hsbk);
+
+        // Kelvin is a plain number, clamped when read.
+        assert_eq!(serde_json::to_string(&Kelvin::new(2700)).unwrap(), "2700");
+        assert_eq!(
+            serde_json::from_str::<Kelvin>("20000").unwrap(),
+            Kelvin::new(MAX_KELVIN)
+        );
+    }
 }
 
 pub fn named_colours() -> Vec<String> {
@@ -205,6 +1122,31 @@ pub fn named_colours() -> Vec<String> {
     ]
 }
 
+pub fn named_whites() -> Vec<String> {
+    vec![
+        "candlelight".to_string(),
+        "warm".to_string(),
+        "neutral".to_string(),
+        "daylight".to_string(),
+        "cool".to_string(),
+    ]
+}
+
+pub fn get_white(s: &str) -> Option<Kelvin> {
+    let white: &str = &(s.to_lowercase());
+    let kelvin = match white {
+        "candlelight" => 1500,
+        "warm" => 2700,
+        "neutral" => 3500,
+        "daylight" => 5600,
+        "cool" => 6500,
+        _ => return None,
+    };
+    Some(Kelvin::new(kelvin))
+}
+
+/// The colour with one of the names in `named_colours`. Panics on any other
+/// name; parse a `Hsbk` instead to accept any colour.
 pub fn get_colour(s: &str) -> Hsb {
     let colour: &str = &(s.to_lowercase());
     match colour {
@@ -0,0 +1,761 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use circadian::CircadianConfig;
+use colour::{self, Hsbk, ParseColourError};
+use convert;
+use json::Json;
+use registry::RegisteredDevice;
+use schedule::When;
+use selector::{self, Selector};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sun::Location;
+use toml;
+
+/// Devices, their aliases, groups of them, and named colours, set out in a
+/// config file so that lights can be named, and reached, without discovery.
+///
+/// ```text
+/// default_duration = 500    # milliseconds
+///
+/// [[devices]]
+/// mac = "d0:73:d5:01:02:03"
+/// ip = "192.168.1.20"       # optional, for reaching it without discovery
+/// alias = "desk-left"
+///
+/// [[devices]]
+/// mac = "d0:73:d5:01:02:04"
+/// alias = "desk-right"
+///
+/// [groups]
+/// desk-lamps = ["desk-left", "desk-right"]
+/// downstairs = ["group:Kitchen", "label:Hallway"]
+///
+/// [colours]
+/// sunset = "hsb(20, 90%, 60%)"
+/// reading = "kelvin:4000 brightness:80%"
+///
+/// [location]
+/// latitude = 51.5
+/// longitude = -0.13
+/// utc_offset = 60           # minutes, for schedules
+///
+/// [[schedules]]
+/// at = "30 6 * * mon-fri"
+/// lights = "bedroom"
+/// wake = "reading"
+/// duration = 1800000
+///
+/// [[schedules]]
+/// name = "evening"
+/// at = "sunset-30m"
+/// lights = ["desk-lamps", "label:Hallway"]
+/// power = "on"
+///
+/// [[schedules]]
+/// at = "0 23 * * *"
+/// scene = "night.json"
+///
+/// [circadian]
+/// night_kelvin = 2200
+/// day_kelvin = 5500
+/// night_brightness = 30     # percent
+/// day_brightness = 100
+/// resume_after = 120        # minutes
+/// ```
+///
+/// Only devices with a fixed `ip` skip discovery; addresses found by discovery
+/// aren't remembered, so other devices are discovered again on each use.
+///
+/// The same can be written in JSON, with the same keys. Group members are
+/// aliases or selectors, and colours are anything `Hsbk` parses. Each schedule
+/// has one of `power`, `colour`, `wake` or `scene`, and runs at the times `When`
+/// parses.
+///
+/// `utc_offset` is fixed, and doesn't follow daylight saving time: cron times
+/// run an hour off while the clocks are changed, until it's updated to match.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Config {
+    pub devices: Vec<DeviceConfig>,
+    /// Each group's name, and its members.
+    pub groups: Vec<(String, Vec<String>)>,
+    /// The transition time for changes that don't give one.
+    pub default_duration: Option<Duration>,
+    /// Each colour's name, and its definition.
+    pub colours: Vec<(String, String)>,
+    /// Where sunrise and sunset are worked out for.
+    pub location: Option<Location>,
+    /// Local time, in minutes ahead of UTC, ignoring daylight saving time.
+    pub utc_offset: i32,
+    pub schedules: Vec<ScheduleConfig>,
+    /// How lights follow the sun, in circadian mode.
+    pub circadian: CircadianConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceConfig {
+    pub target: [u8; 8],
+    /// A fixed address, at which the device is reached without discovery.
+    pub ip: Option<IpAddr>,
+    pub alias: Option<String>,
+}
+
+/// A job for a `Schedule`, with its lights still to be found.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScheduleConfig {
+    pub name: String,
+    pub when: When,
+    pub lights: Vec<Selector>,
+    pub action: ScheduledAction,
+    pub duration: Option<Duration>,
+}
+
+/// A `schedule::Action`, with any scene still to be loaded.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum ScheduledAction {
+    Power(bool),
+    Colour(Hsbk),
+    Wake(Hsbk),
+    Scene(PathBuf),
+}
+
+impl Config {
+    /// Reads a config file, as JSON if its name ends in .json, otherwise as TOML.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, io::Error> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path).and_then(|text| {
+            if path.extension().is_some_and(|e| e == "json") {
+                Config::parse_json(&text)
+            } else {
+                Config::parse_toml(&text)
+            }
+        });
+        let mut config =
+            config.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+        // Scenes are found beside the config file.
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for s in &mut config.schedules {
+            if let ScheduledAction::Scene(ref mut scene) = s.action {
+                *scene = dir.join(&scene);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Where the config is looked for when no file is given: `$LIFX_CONFIG`,
+    /// or lifx/config.toml in `$XDG_CONFIG_HOME` or ~/.config.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("LIFX_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+        Some(dir.join("lifx").join("config.toml"))
+    }
+
+    pub fn parse_toml(s: &str) -> Result<Config, io::Error> {
+        Config::from_json(&toml::parse(s)?)
+    }
+
+    pub fn parse_json(s: &str) -> Result<Config, io::Error> {
+        Config::from_json(&Json::parse(s)?)
+    }
+
+    // Reads a config from its JSON form, checking that every MAC address, IP
+    // address, group member and colour can be understood.
+    fn from_json(json: &Json) -> Result<Config, io::Error> {
+        let mut config = Config::default();
+        known_keys(
+            json,
+            "",
+            &[
+                "default_duration",
+                "devices",
+                "groups",
+                "colours",
+                "location",
+                "schedules",
+                "circadian",
+            ],
+        )?;
+
+        for d in array(json.get("devices"), "devices")? {
+            known_keys(d, "devices", &["mac", "ip", "alias"])?;
+            let field = |key: &str| match d.get(key) {
+                None | Some(&Json::Null) => Ok(None),
+                Some(v) => v
+                    .as_str()
+                    .map(Some)
+                    .ok_or_else(|| invalid(&format!("a device's {} must be a string", key))),
+            };
+            let mac = field("mac")?.ok_or_else(|| invalid("every device needs a mac"))?;
+            let target = selector::parse_id(mac)
+                .ok_or_else(|| invalid(&format!("{:?} is not a MAC address", mac)))?;
+            let ip = match field("ip")? {
+                Some(ip) => Some(
+                    ip.parse()
+                        .map_err(|_| invalid(&format!("{:?} is not an IP address", ip)))?,
+                ),
+                None => None,
+            };
+            config.devices.push(DeviceConfig {
+                target,
+                ip,
+                alias: field("alias")?.map(String::from),
+            });
+        }
+
+        for (name, members) in object(json.get("groups"), "groups")? {
+            let members = array(Some(members), &format!("group {}", name))?
+                .iter()
+                .map(|m| {
+                    m.as_str().map(String::from).ok_or_else(|| {
+                        invalid(&format!("group {} has a member that isn't a string", name))
+                    })
+                })
+                .collect::<Result<Vec<String>, io::Error>>()?;
+            config.groups.push((name.clone(), members));
+        }
+
+        for (name, definition) in object(json.get("colours"), "colours")? {
+            let definition = definition
+                .as_str()
+                .ok_or_else(|| invalid(&format!("colour {} must be a string", name)))?;
+            config.colours.push((name.clone(), definition.to_string()));
+        }
+
+        config.default_duration = match json.get("default_duration") {
+            None | Some(&Json::Null) => None,
+            Some(v) => match v.as_f64() {
+                Some(ms) if ms >= 0.0 => Some(Duration::from_millis(ms as u64)),
+                _ => return Err(invalid("default_duration must be a number of milliseconds")),
+            },
+        };
+
+        for (name, members) in &config.groups {
+            for m in members {
+                config
+                    .member(m)
+                    .map_err(|e| invalid(&format!("group {} has member {:?}: {}", name, m, e)))?;
+            }
+        }
+        for (name, _) in &config.colours {
+            config
+                .colour(name)
+                .map_err(|e| invalid(&format!("colour {}: {}", name, e)))?;
+        }
+
+        if let Some(location) = json.get("location") {
+            known_keys(
+                location,
+                "location",
+                &["latitude", "longitude", "utc_offset"],
+            )?;
+            let number = |key: &str, limit: f64| match location.get(key).and_then(Json::as_f64) {
+                Some(n) if n.abs() <= limit => Ok(n),
+                _ => Err(invalid(&format!(
+                    "location needs a {} from -{} to {}",
+                    key, limit, limit
+                ))),
+            };
+            config.location = Some(Location::new(
+                number("latitude", 90.0)?,
+                number("longitude", 180.0)?,
+            ));
+            config.utc_offset = match location.get("utc_offset") {
+                None | Some(&Json::Null) => 0,
+                Some(v) => match v.as_f64() {
+                    Some(m) if m.abs() <= 24.0 * 60.0 => m as i32,
+                    _ => return Err(invalid("utc_offset must be a number of minutes")),
+                },
+            };
+        }
+
+        if let Some(circadian) = json.get("circadian") {
+            config.circadian = parse_circadian(circadian)?;
+        }
+
+        for s in array(json.get("schedules"), "schedules")? {
+            let schedule = config.schedule(s)?;
+            config.schedules.push(schedule);
+        }
+
+        Ok(config)
+    }
+
+    // One of the schedules, which may refer to the devices, groups and colours.
+    fn schedule(&self, json: &Json) -> Result<ScheduleConfig, io::Error> {
+        let at = json
+            .get("at")
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid("every schedule needs an `at` time"))?;
+        let name = match json.get("name") {
+            None | Some(&Json::Null) => at.to_string(),
+            Some(v) => v
+                .as_str()
+                .ok_or_else(|| invalid("a schedule's name must be a string"))?
+                .to_string(),
+        };
+        let bad = |e: &str| invalid(&format!("schedule {}: {}", name, e));
+        known_keys(
+            json,
+            &format!("schedule {}", name),
+            &[
+                "name", "at", "lights", "power", "colour", "wake", "scene", "duration",
+            ],
+        )?;
+
+        let when: When = at.parse().map_err(|e: String| bad(&e))?;
+        if let When::Sun { .. } = when {
+            if self.location.is_none() {
+                return Err(bad("sunrise and sunset need a [location]"));
+            }
+        }
+
+        let string = |key: &str| match json.get(key) {
+            None | Some(&Json::Null) => Ok(None),
+            Some(v) => v
+                .as_str()
+                .map(Some)
+                .ok_or_else(|| bad(&format!("{} must be a string", key))),
+        };
+        let colour = |s: &str| self.colour(s).map_err(|e| bad(&e.to_string()));
+        let actions = vec![
+            string("power")?.map(|p| match p {
+                "on" => Ok(ScheduledAction::Power(true)),
+                "off" => Ok(ScheduledAction::Power(false)),
+                _ => Err(bad("power must be on or off")),
+            }),
+            string("colour")?.map(|c| colour(c).map(ScheduledAction::Colour)),
+            string("wake")?.map(|c| colour(c).map(ScheduledAction::Wake)),
+            string("scene")?.map(|p| Ok(ScheduledAction::Scene(PathBuf::from(p)))),
+        ];
+        let mut actions = actions.into_iter().flatten();
+        let action = match (actions.next(), actions.next()) {
+            (Some(action), None) => action?,
+            _ => return Err(bad("needs one of power, colour, wake or scene")),
+        };
+
+        let names = match json.get("lights") {
+            None | Some(&Json::Null) => vec![],
+            Some(Json::String(name)) => vec![name.as_str()],
+            Some(Json::Array(names)) => names
+                .iter()
+                .map(|n| n.as_str().ok_or_else(|| bad("lights must be strings")))
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(bad("lights must be a string or an array")),
+        };
+        let mut lights = vec![];
+        for n in names {
+            lights.extend(self.selectors(n).map_err(|e| bad(&e))?);
+        }
+        if lights.is_empty() && !matches!(action, ScheduledAction::Scene(_)) {
+            return Err(bad("needs lights"));
+        }
+
+        let duration = match json.get("duration") {
+            None | Some(&Json::Null) => None,
+            Some(v) => match v.as_f64() {
+                Some(ms) if ms >= 0.0 => Some(Duration::from_millis(ms as u64)),
+                _ => return Err(bad("duration must be a number of milliseconds")),
+            },
+        };
+
+        Ok(ScheduleConfig {
+            name,
+            when,
+            lights,
+            action,
+            duration,
+        })
+    }
+
+    /// The device with the alias, ignoring case.
+    pub fn device(&self, alias: &str) -> Option<&DeviceConfig> {
+        self.devices.iter().find(|d| match d.alias {
+            Some(ref a) => a.eq_ignore_ascii_case(alias),
+            None => false,
+        })
+    }
+
+    /// The selectors a group name or alias stands for, or None if it's neither.
+    pub fn resolve(&self, name: &str) -> Option<Vec<Selector>> {
+        if let Some((_, members)) = self.groups.iter().find(|g| g.0.eq_ignore_ascii_case(name)) {
+            return Some(members.iter().filter_map(|m| self.member(m).ok()).collect());
+        }
+        self.device(name).map(|d| vec![Selector::Id(d.target)])
+    }
+
+    /// The selectors a group name or alias stands for, or else the selector
+    /// itself.
+    pub fn selectors(&self, name: &str) -> Result<Vec<Selector>, String> {
+        match self.resolve(name) {
+            Some(selectors) => Ok(selectors),
+            None => name.parse().map(|s| vec![s]),
+        }
+    }
+
+    // A group member, an alias or a selector.
+    fn member(&self, member: &str) -> Result<Selector, String> {
+        match self.device(member) {
+            Some(d) => Ok(Selector::Id(d.target)),
+            None => member.parse(),
+        }
+    }
+
+    /// Parses a colour, where the first word may be one of the config's named
+    /// colours, e.g. `sunset brightness:40%`.
+    pub fn colour(&self, s: &str) -> Result<Hsbk, ParseColourError> {
+        let s = s.trim();
+        let (first, rest) = s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()));
+        match self
+            .colours
+            .iter()
+            .find(|c| c.0.eq_ignore_ascii_case(first))
+        {
+            Some((_, definition)) => format!("{} {}", definition, rest).parse(),
+            None => s.parse(),
+        }
+    }
+
+    /// The devices with a fixed address, which can be used without being
+    /// discovered, see `DeviceRegistry::add`. Each is labelled with its alias,
+    /// until it reports its own label.
+    pub fn known_devices(&self) -> Vec<RegisteredDevice> {
+        self.devices
+            .iter()
+            .filter_map(|d| {
+                let ip = d.ip?;
+                let mut device = RegisteredDevice::new(
+                    convert::target_to_mac(d.target),
+                    d.target,
+                    SocketAddr::new(ip, 56700),
+                );
+                device.label = d.alias.clone();
+                Some(device)
+            })
+            .collect()
+    }
+}
+
+// The [circadian] table, where every key is optional.
+fn parse_circadian(json: &Json) -> Result<CircadianConfig, io::Error> {
+    known_keys(
+        json,
+        "circadian",
+        &[
+            "night_kelvin",
+            "day_kelvin",
+            "night_brightness",
+            "day_brightness",
+            "resume_after",
+        ],
+    )?;
+    let mut config = CircadianConfig::default();
+    let number = |key: &str, min: f64, max: f64| match json.get(key) {
+        None | Some(&Json::Null) => Ok(None),
+        Some(v) => match v.as_f64() {
+            Some(n) if n >= min && n <= max => Ok(Some(n)),
+            _ => Err(invalid(&format!(
+                "circadian {} must be from {} to {}",
+                key, min, max
+            ))),
+        },
+    };
+    let kelvin = (f64::from(colour::MIN_KELVIN), f64::from(colour::MAX_KELVIN));
+
+    if let Some(k) = number("night_kelvin", kelvin.0, kelvin.1)? {
+        config.night_kelvin = k as u16;
+    }
+    if let Some(k) = number("day_kelvin", kelvin.0, kelvin.1)? {
+        config.day_kelvin = k as u16;
+    }
+    if let Some(b) = number("night_brightness", 0.0, 100.0)? {
+        config.night_brightness = b;
+    }
+    if let Some(b) = number("day_brightness", 0.0, 100.0)? {
+        config.day_brightness = b;
+    }
+    if let Some(m) = number("resume_after", 0.0, 7.0 * 24.0 * 60.0)? {
+        config.resume_after = Duration::from_secs((m * 60.0) as u64);
+    }
+    Ok(config)
+}
+
+fn array<'a>(json: Option<&'a Json>, name: &str) -> Result<&'a [Json], io::Error> {
+    match json {
+        None | Some(&Json::Null) => Ok(&[]),
+        Some(Json::Array(items)) => Ok(items),
+        Some(_) => Err(invalid(&format!("{} must be an array", name))),
+    }
+}
+
+fn object<'a>(json: Option<&'a Json>, name: &str) -> Result<&'a [(String, Json)], io::Error> {
+    match json {
+        None | Some(&Json::Null) => Ok(&[]),
+        Some(Json::Object(fields)) => Ok(fields),
+        Some(_) => Err(invalid(&format!("{} must be a table", name))),
+    }
+}
+
+// Refuses keys other than those given, so that a misspelt key isn't silently
+// ignored.
+fn known_keys(json: &Json, table: &str, keys: &[&str]) -> Result<(), io::Error> {
+    if let Json::Object(fields) = json {
+        if let Some((key, _)) = fields.iter().find(|f| !keys.contains(&f.0.as_str())) {
+            return Err(invalid(&match table {
+                "" => format!("unknown key {:?}", key),
+                _ => format!("unknown key {:?} in {}", key, table),
+            }));
+        }
+    }
+    Ok(())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid config: {}", msg),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ScheduleConfig, ScheduledAction};
+    use colour::Hsbk;
+    use selector::Selector;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use sun::Location;
+
+    const TOML: &str = r#"
+default_duration = 500
+
+[[devices]]
+mac = "d0:73:d5:01:02:03"
+ip = "192.168.1.20"
+alias = "desk-left"
+
+[[devices]]
+mac = "d073d5010204"
+alias = "Desk-Right"
+
+[groups]
+desk-lamps = ["desk-left", "desk-right"]
+downstairs = ["group:Kitchen", "label:Hallway"]
+
+[colours]
+sunset = "hsb(20, 90%, 60%)"
+"#;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse_toml(TOML).unwrap();
+        let json = Config::parse_json(
+            r#"{"default_duration": 500,
+                "devices": [
+                    {"mac": "d0:73:d5:01:02:03", "ip": "192.168.1.20", "alias": "desk-left"},
+                    {"mac": "d073d5010204", "alias": "Desk-Right"}
+                ],
+                "groups": {"desk-lamps": ["desk-left", "desk-right"],
+                           "downstairs": ["group:Kitchen", "label:Hallway"]},
+                "colours": {"sunset": "hsb(20, 90%, 60%)"}}"#,
+        )
+        .unwrap();
+        assert_eq!(config, json);
+        assert_eq!(config.default_duration, Some(Duration::from_millis(500)));
+
+        let left = [0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0];
+        let right = [0xD0, 0x73, 0xD5, 1, 2, 4, 0, 0];
+        assert_eq!(
+            config.resolve("Desk-Lamps"),
+            Some(vec![Selector::Id(left), Selector::Id(right)])
+        );
+        assert_eq!(
+            config.resolve("desk-right"),
+            Some(vec![Selector::Id(right)])
+        );
+        assert_eq!(
+            config.resolve("downstairs"),
+            Some(vec![
+                Selector::Group("Kitchen".to_string()),
+                Selector::Label("Hallway".to_string())
+            ])
+        );
+        assert_eq!(config.resolve("Kitchen"), None);
+
+        // Only devices with a fixed address are known without discovery.
+        let known = config.known_devices();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known[0].target, left);
+        assert_eq!(known[0].socket_addr, "192.168.1.20:56700".parse().unwrap());
+        assert_eq!(known[0].label, Some("desk-left".to_string()));
+    }
+
+    #[test]
+    fn test_config_colours() {
+        let config = Config::parse_toml(TOML).unwrap();
+        let sunset: Hsbk = "hsb(20, 90%, 60%)".parse().unwrap();
+        assert_eq!(config.colour("Sunset"), Ok(sunset));
+        assert_eq!(
+            config.colour("sunset brightness:40%"),
+            "hsb(20, 90%, 60%) brightness:40%".parse()
+        );
+        assert_eq!(config.colour("red"), "red".parse());
+    }
+
+    #[test]
+    fn test_config_schedules() {
+        let config = Config::parse_toml(&format!(
+            "{}{}",
+            TOML,
+            r#"
+[location]
+latitude = 51.5
+longitude = -0.13
+utc_offset = 60
+
+[[schedules]]
+at = "30 6 * * mon-fri"
+lights = "desk-left"
+wake = "sunset brightness:80%"
+duration = 1800000
+
+[[schedules]]
+name = "evening"
+at = "sunset-30m"
+lights = ["desk-lamps", "label:Hallway"]
+power = "on"
+
+[[schedules]]
+at = "0 23 * * *"
+scene = "night.json"
+
+[circadian]
+night_kelvin = 2000
+day_brightness = 90
+resume_after = 30
+"#
+        ))
+        .unwrap();
+
+        assert_eq!(config.location, Some(Location::new(51.5, -0.13)));
+        assert_eq!(config.circadian.night_kelvin, 2000);
+        assert_eq!(config.circadian.day_brightness, 90.0);
+        assert_eq!(config.circadian.resume_after, Duration::from_secs(30 * 60));
+        assert_eq!(config.circadian.day_kelvin, 5500);
+        assert_eq!(config.utc_offset, 60);
+        let left = Selector::Id([0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0]);
+        let right = Selector::Id([0xD0, 0x73, 0xD5, 1, 2, 4, 0, 0]);
+        assert_eq!(
+            config.schedules,
+            vec![
+                ScheduleConfig {
+                    name: "30 6 * * mon-fri".to_string(),
+                    when: "30 6 * * mon-fri".parse().unwrap(),
+                    lights: vec![left.clone()],
+                    action: ScheduledAction::Wake(
+                        "hsb(20, 90%, 60%) brightness:80%".parse().unwrap()
+                    ),
+                    duration: Some(Duration::from_secs(1800)),
+                },
+                ScheduleConfig {
+                    name: "evening".to_string(),
+                    when: "sunset-30m".parse().unwrap(),
+                    lights: vec![left, right, Selector::Label("Hallway".to_string())],
+                    action: ScheduledAction::Power(true),
+                    duration: None,
+                },
+                ScheduleConfig {
+                    name: "0 23 * * *".to_string(),
+                    when: "0 23 * * *".parse().unwrap(),
+                    lights: vec![],
+                    action: ScheduledAction::Scene(PathBuf::from("night.json")),
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let tests = vec![
+            (
+                "[[devices]]\nmac = \"d0:73\"",
+                "invalid config: \"d0:73\" is not a MAC address",
+            ),
+            (
+                "[[devices]]\nalias = \"desk\"",
+                "invalid config: every device needs a mac",
+            ),
+            (
+                "[groups]\ndesk = [\"colour:red\"]",
+                "invalid config: group desk has member \"colour:red\": unknown selector \
+                 \"colour\", expected all, label:, group:, location:, id: or ip:",
+            ),
+            (
+                "[colours]\nmud = \"brown-ish\"",
+                "invalid config: colour mud: invalid colour: unknown colour \"brown-ish\"",
+            ),
+            (
+                "default_duration = \"1s\"",
+                "invalid config: default_duration must be a number of milliseconds",
+            ),
+            (
+                "[[schedules]]\nat = \"sunset\"\npower = \"on\"\nlights = \"all\"",
+                "invalid config: schedule sunset: sunrise and sunset need a [location]",
+            ),
+            (
+                "[[schedules]]\nat = \"0 7 * * *\"\nlights = \"all\"",
+                "invalid config: schedule 0 7 * * *: needs one of power, colour, wake or scene",
+            ),
+            (
+                "[[schedules]]\nat = \"0 7 * * *\"\npower = \"on\"",
+                "invalid config: schedule 0 7 * * *: needs lights",
+            ),
+            (
+                "[colors]\nmud = \"brown\"",
+                "invalid config: unknown key \"colors\"",
+            ),
+            (
+                "[[devices]]\nmac = \"d0:73:d5:01:02:03\"\nalais = \"desk\"",
+                "invalid config: unknown key \"alais\" in devices",
+            ),
+            (
+                "[[schedules]]\nat = \"0 7 * * *\"\npower = \"on\"\nlight = \"all\"",
+                "invalid config: unknown key \"light\" in schedule 0 7 * * *",
+            ),
+            (
+                "[circadian]\nday_kelvin = 12000",
+                "invalid config: circadian day_kelvin must be from 1500 to 9000",
+            ),
+            (
+                "[location]\nlatitude = 95\nlongitude = 0",
+                "invalid config: location needs a latitude from -90 to 90",
+            ),
+        ];
+        for (s, want) in tests {
+            assert_eq!(
+                Config::parse_toml(s).unwrap_err().to_string(),
+                want,
+                "{}",
+                s
+            );
+        }
+    }
+}
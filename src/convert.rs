@@ -34,3 +34,41 @@ fn bitstr_to_u32(bits: &str) -> u32 {
         .iter()
         .fold(0, |acc, b| (acc << 1) + if *b == 48 { 0 } else { 1 })
 }
+
+/// Formats a target as a MAC address, as in `Response::mac_address`.
+pub fn target_to_mac(target: [u8; 8]) -> String {
+    as_hex(target.to_vec())
+}
+
+/// Parses a MAC address of six or eight hex bytes, separated by colons, into a
+/// target.
+pub fn mac_to_target(mac: &str) -> Option<[u8; 8]> {
+    let parts: Vec<&str> = mac.split(':').collect();
+    if parts.len() != 6 && parts.len() != 8 {
+        return None;
+    }
+
+    let mut target = [0u8; 8];
+    for (i, p) in parts.iter().enumerate() {
+        if p.len() != 2 {
+            return None;
+        }
+        target[i] = u8::from_str_radix(p, 16).ok()?;
+    }
+    Some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::*;
+
+    #[test]
+    fn test_mac_to_target() {
+        let target = [0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03, 0, 0];
+        assert_eq!(mac_to_target("d0:73:d5:01:02:03"), Some(target));
+        assert_eq!(mac_to_target("D0:73:D5:01:02:03:00:00"), Some(target));
+        assert_eq!(mac_to_target("D0:73:D5:01:02"), None);
+        assert_eq!(mac_to_target("D0:73:D5:01:02:0G"), None);
+        assert_eq!(target_to_mac(target), "D0:73:D5:01:02:03:00:00");
+    }
+}
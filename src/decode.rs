@@ -0,0 +1,625 @@
+use std::fmt;
+use std::io;
+
+use colour::Hsbk;
+use request::{Frame, Header};
+use response::{self, Payload, ResponseData};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// A message decoded field by field, for inspecting what a device sent.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Decoded {
+    pub message_type: u16,
+    pub sections: Vec<Section>,
+}
+
+/// The fields of one part of a message: the frame, frame address, protocol
+/// header or payload.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Section {
+    pub name: &'static str,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Field {
+    /// The byte range the field was read from. Bit fields share their bytes.
+    pub offset: usize,
+    pub len: usize,
+    pub name: String,
+    pub value: String,
+    /// Set when the field holds something it shouldn't, such as reserved bits
+    /// that aren't zero.
+    pub warning: Option<String>,
+}
+
+impl Decoded {
+    /// Every field with a warning.
+    pub fn warnings(&self) -> Vec<&Field> {
+        self.sections
+            .iter()
+            .flat_map(|s| s.fields.iter())
+            .filter(|f| f.warning.is_some())
+            .collect()
+    }
+}
+
+/// The name of a message type, if it's one this crate knows.
+pub fn message_name(message_type: u16) -> Option<&'static str> {
+    MESSAGE_NAMES
+        .iter()
+        .find(|m| m.0 == message_type)
+        .map(|m| m.1)
+}
+
+const MESSAGE_NAMES: [(u16, &str); 66] = [
+    (2, "GetService"),
+    (3, "StateService"),
+    (12, "GetHostInfo"),
+    (13, "StateHostInfo"),
+    (14, "GetHostFirmware"),
+    (15, "StateHostFirmware"),
+    (16, "GetWifiInfo"),
+    (17, "StateWifiInfo"),
+    (18, "GetWifiFirmware"),
+    (19, "StateWifiFirmware"),
+    (20, "GetPower"),
+    (21, "SetPower"),
+    (22, "StatePower"),
+    (23, "GetLabel"),
+    (24, "SetLabel"),
+    (25, "StateLabel"),
+    (32, "GetVersion"),
+    (33, "StateVersion"),
+    (34, "GetInfo"),
+    (35, "StateInfo"),
+    (38, "SetReboot"),
+    (45, "Acknowledgement"),
+    (48, "GetLocation"),
+    (49, "SetLocation"),
+    (50, "StateLocation"),
+    (51, "GetGroup"),
+    (52, "SetGroup"),
+    (53, "StateGroup"),
+    (58, "EchoRequest"),
+    (59, "EchoResponse"),
+    (101, "GetColor"),
+    (102, "SetColor"),
+    (103, "SetWaveform"),
+    (107, "LightState"),
+    (116, "GetLightPower"),
+    (117, "SetLightPower"),
+    (118, "StateLightPower"),
+    (119, "SetWaveformOptional"),
+    (120, "GetInfrared"),
+    (121, "StateInfrared"),
+    (122, "SetInfrared"),
+    (142, "GetHevCycle"),
+    (143, "SetHevCycle"),
+    (144, "StateHevCycle"),
+    (223, "StateUnhandled"),
+    (501, "SetColorZones"),
+    (502, "GetColorZones"),
+    (503, "StateZone"),
+    (506, "StateMultiZone"),
+    (507, "GetMultiZoneEffect"),
+    (508, "SetMultiZoneEffect"),
+    (509, "StateMultiZoneEffect"),
+    (510, "SetExtendedColorZones"),
+    (511, "GetExtendedColorZones"),
+    (512, "StateExtendedColorZones"),
+    (701, "GetDeviceChain"),
+    (702, "StateDeviceChain"),
+    (703, "SetUserPosition"),
+    (707, "Get64"),
+    (711, "State64"),
+    (715, "Set64"),
+    (718, "GetTileEffect"),
+    (719, "SetTileEffect"),
+    (720, "StateTileEffect"),
+    (816, "GetRPower"),
+    (818, "StateRPower"),
+];
+
+// How a payload field is laid out on the wire.
+#[derive(Clone, Copy)]
+enum Kind {
+    U8,
+    U16,
+    U32,
+    F32,
+    /// Milliseconds, as a u32.
+    Millis,
+    /// Nanoseconds, as a u64.
+    Nanos,
+    Hsbk,
+    /// A run of colours.
+    Hsbks(usize),
+    /// A string padded with zeros.
+    Label(usize),
+    Bytes(usize),
+    /// Bytes that should be zero.
+    Reserved(usize),
+}
+
+impl Kind {
+    fn len(self) -> usize {
+        match self {
+            Kind::U8 => 1,
+            Kind::U16 => 2,
+            Kind::U32 | Kind::F32 | Kind::Millis => 4,
+            Kind::Nanos | Kind::Hsbk => 8,
+            Kind::Hsbks(n) => 8 * n,
+            Kind::Label(n) | Kind::Bytes(n) | Kind::Reserved(n) => n,
+        }
+    }
+}
+
+type Layout = &'static [(&'static str, Kind)];
+
+const COLLECTION: Layout = &[
+    ("id", Kind::Bytes(16)),
+    ("label", Kind::Label(32)),
+    ("updated_at", Kind::Nanos),
+];
+
+// The payload layouts of the messages decoded field by field.
+fn layout(message_type: u16) -> Option<Layout> {
+    let layout: Layout = match message_type {
+        2 | 12 | 14 | 16 | 18 | 20 | 23 | 32 | 34 | 38 | 45 | 48 | 51 | 101 | 116 | 120 | 507
+        | 511 | 701 => &[],
+        3 => &[("service", Kind::U8), ("port", Kind::U32)],
+        15 | 19 => &[
+            ("build", Kind::Nanos),
+            ("reserved", Kind::Reserved(8)),
+            ("version_minor", Kind::U16),
+            ("version_major", Kind::U16),
+        ],
+        17 => &[
+            ("signal", Kind::F32),
+            ("reserved", Kind::Reserved(4)),
+            ("reserved", Kind::Reserved(4)),
+            ("reserved", Kind::Reserved(2)),
+        ],
+        21 | 22 | 118 => &[("level", Kind::U16)],
+        24 | 25 => &[("label", Kind::Label(32))],
+        33 => &[
+            ("vendor", Kind::U32),
+            ("product", Kind::U32),
+            ("reserved", Kind::Reserved(4)),
+        ],
+        35 => &[
+            ("time", Kind::Nanos),
+            ("uptime", Kind::Nanos),
+            ("downtime", Kind::Nanos),
+        ],
+        49 | 50 | 52 | 53 => COLLECTION,
+        58 | 59 => &[("echoing", Kind::Bytes(64))],
+        102 => &[
+            ("reserved", Kind::Reserved(1)),
+            ("colour", Kind::Hsbk),
+            ("duration", Kind::Millis),
+        ],
+        107 => &[
+            ("colour", Kind::Hsbk),
+            ("reserved", Kind::Reserved(2)),
+            ("power", Kind::U16),
+            ("label", Kind::Label(32)),
+            ("reserved", Kind::Reserved(8)),
+        ],
+        117 => &[("level", Kind::U16), ("duration", Kind::Millis)],
+        121 | 122 => &[("brightness", Kind::U16)],
+        501 => &[
+            ("start_index", Kind::U8),
+            ("end_index", Kind::U8),
+            ("colour", Kind::Hsbk),
+            ("duration", Kind::Millis),
+            ("apply", Kind::U8),
+        ],
+        502 => &[("start_index", Kind::U8), ("end_index", Kind::U8)],
+        503 => &[
+            ("count", Kind::U8),
+            ("index", Kind::U8),
+            ("colour", Kind::Hsbk),
+        ],
+        506 => &[
+            ("count", Kind::U8),
+            ("index", Kind::U8),
+            ("colours", Kind::Hsbks(8)),
+        ],
+        510 => &[
+            ("duration", Kind::Millis),
+            ("apply", Kind::U8),
+            ("index", Kind::U16),
+            ("colours_count", Kind::U8),
+            ("colours", Kind::Hsbks(82)),
+        ],
+        512 => &[
+            ("count", Kind::U16),
+            ("index", Kind::U16),
+            ("colours_count", Kind::U8),
+            ("colours", Kind::Hsbks(82)),
+        ],
+        707 => &[
+            ("tile_index", Kind::U8),
+            ("length", Kind::U8),
+            ("reserved", Kind::Reserved(1)),
+            ("x", Kind::U8),
+            ("y", Kind::U8),
+            ("width", Kind::U8),
+        ],
+        711 => &[
+            ("tile_index", Kind::U8),
+            ("reserved", Kind::Reserved(1)),
+            ("x", Kind::U8),
+            ("y", Kind::U8),
+            ("width", Kind::U8),
+            ("colours", Kind::Hsbks(64)),
+        ],
+        715 => &[
+            ("tile_index", Kind::U8),
+            ("length", Kind::U8),
+            ("reserved", Kind::Reserved(1)),
+            ("x", Kind::U8),
+            ("y", Kind::U8),
+            ("width", Kind::U8),
+            ("duration", Kind::Millis),
+            ("colours", Kind::Hsbks(64)),
+        ],
+        718 => &[("reserved", Kind::Reserved(2))],
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Decodes a message field by field: its header, and its payload when the
+/// message type's layout is known. Other payloads are decoded as a reply would
+/// be, or left as hex.
+///
+/// Fails only if the bytes are too short to hold a header.
+pub fn decode(bytes: &[u8]) -> Result<Decoded, io::Error> {
+    let header = match Header::decode(bytes) {
+        Some(h) => h,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} bytes is too short for a 36 byte header", bytes.len()),
+            ))
+        }
+    };
+    let Frame {
+        size,
+        origin,
+        tagged,
+        addressable,
+        protocol,
+        source,
+    } = header.frame;
+    let address = header.frame_address;
+    let message_type = header.protocol_header.message_type;
+
+    let frame = vec![
+        field(0, 2, "size", size).warn_if(
+            usize::from(size) != bytes.len(),
+            &format!("the message is {} bytes", bytes.len()),
+        ),
+        field(2, 2, "protocol", protocol).warn_if(protocol != 1024, "should be 1024"),
+        field(2, 2, "addressable", addressable).warn_if(!addressable, "should be set"),
+        field(2, 2, "tagged", tagged),
+        field(2, 2, "origin", origin).warn_if(origin != 0, "should be 0"),
+        field(4, 4, "source", format!("{:#010x} ({})", source, source)),
+    ];
+
+    let frame_address = vec![
+        field(8, 8, "target", hex(&address.target, ":")),
+        reserved(16, &bytes[16..22]),
+        field(22, 1, "res_required", address.res_required),
+        field(22, 1, "ack_required", address.ack_required),
+        field(
+            22,
+            1,
+            "reserved",
+            format!("{:#04x}", address.reserved_2 << 2),
+        )
+        .warn_if(address.reserved_2 != 0, "reserved bits are not zero"),
+        field(23, 1, "sequence", address.sequence),
+    ];
+
+    let name = message_name(message_type).unwrap_or("unknown");
+    let protocol_header = vec![
+        reserved(24, &bytes[24..32]),
+        field(32, 2, "type", format!("{} {}", message_type, name))
+            .warn_if(name == "unknown", "unknown message type"),
+        reserved(34, &bytes[34..36]),
+    ];
+
+    Ok(Decoded {
+        message_type,
+        sections: vec![
+            Section {
+                name: "Frame",
+                fields: frame,
+            },
+            Section {
+                name: "Frame address",
+                fields: frame_address,
+            },
+            Section {
+                name: "Protocol header",
+                fields: protocol_header,
+            },
+            Section {
+                name: "Payload",
+                fields: decode_payload(message_type, bytes),
+            },
+        ],
+    })
+}
+
+fn decode_payload(message_type: u16, bytes: &[u8]) -> Vec<Field> {
+    let layout = match layout(message_type) {
+        Some(l) => l,
+        None => {
+            // Not laid out here, but perhaps a reply the crate parses.
+            let mut fields = vec![];
+            let resp = response::parse_response(ResponseData(bytes.to_vec()));
+            match resp.payload {
+                Payload::None(_) => {}
+                p => fields.push(field(36, bytes.len() - 36, "decoded", format!("{:?}", p))),
+            }
+            if bytes.len() > 36 {
+                fields.push(field(36, bytes.len() - 36, "bytes", hex(&bytes[36..], " ")));
+            }
+            return fields;
+        }
+    };
+
+    let mut fields = vec![];
+    let mut offset = 36;
+    for &(name, kind) in layout {
+        let len = kind.len();
+        if offset + len > bytes.len() {
+            fields.push(
+                field(
+                    offset,
+                    bytes.len() - offset,
+                    name,
+                    hex(&bytes[offset..], " "),
+                )
+                .warn_if(true, &format!("truncated, expected {} bytes", len)),
+            );
+            return fields;
+        }
+        let b = &bytes[offset..offset + len];
+        match kind {
+            Kind::Hsbks(n) => {
+                for i in 0..n {
+                    let c = hsbk(&b[i * 8..i * 8 + 8]);
+                    fields.push(field(
+                        offset + i * 8,
+                        8,
+                        &format!("{}[{}]", name, i),
+                        show_hsbk(&c),
+                    ));
+                }
+            }
+            Kind::Reserved(_) => fields.push(reserved(offset, b)),
+            _ => fields.push(field(offset, len, name, value(kind, b))),
+        }
+        offset += len;
+    }
+    if offset < bytes.len() {
+        fields.push(
+            field(
+                offset,
+                bytes.len() - offset,
+                "trailing",
+                hex(&bytes[offset..], " "),
+            )
+            .warn_if(true, "more bytes than the message holds"),
+        );
+    }
+    fields
+}
+
+fn value(kind: Kind, b: &[u8]) -> String {
+    let u32_le = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let u64_le = |b: &[u8]| {
+        let mut a = [0u8; 8];
+        a.copy_from_slice(&b[..8]);
+        u64::from_le_bytes(a)
+    };
+    match kind {
+        Kind::U8 => b[0].to_string(),
+        Kind::U16 => u16::from_le_bytes([b[0], b[1]]).to_string(),
+        Kind::U32 => u32_le(b).to_string(),
+        Kind::F32 => f32::from_bits(u32_le(b)).to_string(),
+        Kind::Millis => format!("{} ms", u32_le(b)),
+        Kind::Nanos => format!("{} ns", u64_le(b)),
+        Kind::Hsbk => show_hsbk(&hsbk(b)),
+        Kind::Label(_) => format!("{:?}", String::from_utf8_lossy(b).trim_end_matches('\0')),
+        Kind::Bytes(_) | Kind::Reserved(_) | Kind::Hsbks(_) => hex(b, " "),
+    }
+}
+
+fn hsbk(b: &[u8]) -> Hsbk {
+    let word = |i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+    Hsbk {
+        hue: word(0),
+        saturation: word(2),
+        brightness: word(4),
+        kelvin: word(6),
+    }
+}
+
+fn show_hsbk(c: &Hsbk) -> String {
+    format!(
+        "hue {:.1} saturation {:.1}% brightness {:.1}% kelvin {}",
+        c.hue_degrees(),
+        c.saturation_percent(),
+        c.brightness_percent(),
+        c.kelvin
+    )
+}
+
+fn field<T: ToString>(offset: usize, len: usize, name: &str, value: T) -> Field {
+    Field {
+        offset,
+        len,
+        name: name.to_string(),
+        value: value.to_string(),
+        warning: None,
+    }
+}
+
+fn reserved(offset: usize, b: &[u8]) -> Field {
+    field(offset, b.len(), "reserved", hex(b, " "))
+        .warn_if(b.iter().any(|&x| x != 0), "reserved bytes are not zero")
+}
+
+impl Field {
+    fn warn_if(mut self, cond: bool, warning: &str) -> Field {
+        if cond {
+            self.warning = Some(warning.to_string());
+        }
+        self
+    }
+}
+
+fn hex(b: &[u8], sep: &str) -> String {
+    let parts: Vec<String> = b.iter().map(|x| format!("{:02x}", x)).collect();
+    parts.join(sep)
+}
+
+/// Parses bytes written in hex, ignoring whitespace, colons, commas and 0x
+/// prefixes, so output from Wireshark, tcpdump or the logs can be pasted in.
+pub fn parse_hex(s: &str) -> Result<Vec<u8>, io::Error> {
+    let digits: String = s
+        .split(|c: char| c.is_whitespace() || c == ':' || c == ',')
+        .map(|p| p.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected pairs of hex digits",
+        ));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+/// Writes a breakdown of the message, a line per field, with the bytes each
+/// was read from and any warnings.
+impl fmt::Display for Decoded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({})",
+            message_name(self.message_type).unwrap_or("Unknown message"),
+            self.message_type
+        )?;
+        for section in &self.sections {
+            writeln!(f, "  {}", section.name)?;
+            let mut last: Option<(usize, usize)> = None;
+            for field in &section.fields {
+                // Bit fields sharing bytes show the byte range only once.
+                let range = if last == Some((field.offset, field.len)) {
+                    String::new()
+                } else if field.len == 1 {
+                    format!("[{}]", field.offset)
+                } else {
+                    format!("[{}..{}]", field.offset, field.offset + field.len)
+                };
+                last = Some((field.offset, field.len));
+                writeln!(f, "    {:<10} {:<14} {}", range, field.name, field.value)?;
+                if let Some(ref w) = field.warning {
+                    writeln!(f, "    {:<10} ! {} {}", "", field.name, w)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, message_name, parse_hex};
+    use colour::Hsbk;
+    use messages;
+    use request::{Frame, FrameAddress, Header, ProtocolHeader, Request, RequestBin};
+
+    #[test]
+    fn test_decode_set_color() {
+        let hsbk = Hsbk::from_degrees(120.0, 100.0, 50.0, 3500);
+        let msg = RequestBin::from(Request::new(
+            Header::new(
+                Frame::new(0, false, true, 1024, 321),
+                FrameAddress::new([0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0], [0; 6], 0, true, false, 9),
+                ProtocolHeader::new(0, 102, 0),
+            ),
+            messages::set_colour_payload(&hsbk, 1000),
+        ));
+
+        let decoded = decode(&msg.0).unwrap();
+        assert_eq!(decoded.message_type, 102);
+        assert!(decoded.warnings().is_empty(), "{}", decoded);
+
+        let text = decoded.to_string();
+        for line in &[
+            "SetColor (102)",
+            "    [0..2]     size           49",
+            "               addressable    true",
+            "    [4..8]     source         0x00000141 (321)",
+            "    [8..16]    target         d0:73:d5:01:02:03:00:00",
+            "               ack_required   true",
+            "    [23]       sequence       9",
+            "    [37..45]   colour         hue 120.0 saturation 100.0% brightness 50.0% kelvin 3500",
+            "    [45..49]   duration       1000 ms",
+        ] {
+            assert!(text.lines().any(|l| l == *line), "missing {:?}\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn test_decode_warnings() {
+        let mut msg = vec![0u8; 38];
+        msg[0] = 40; // Two bytes more than there are.
+        msg[3] = 0x14; // Protocol 1024, addressable.
+        msg[22] = 0x80; // A reserved bit.
+        msg[32] = 22;
+
+        let decoded = decode(&msg).unwrap();
+        let warnings: Vec<(&str, &str)> = decoded
+            .warnings()
+            .iter()
+            .map(|f| (f.name.as_str(), f.warning.as_ref().unwrap().as_str()))
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                ("size", "the message is 38 bytes"),
+                ("reserved", "reserved bits are not zero"),
+            ]
+        );
+
+        assert!(decode(&msg[..20]).is_err());
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(
+            parse_hex("24 00 0x14:34, ff").unwrap(),
+            vec![0x24, 0, 0x14, 0x34, 0xFF]
+        );
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert_eq!(message_name(107), Some("LightState"));
+        assert_eq!(message_name(9999), None);
+    }
+}
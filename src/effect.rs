@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use colour::Hsbk;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The most colours in a tile effect's palette.
+pub const MAX_PALETTE: usize = 16;
+
+/// Which way a multizone MOVE effect travels along the strip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Direction {
+    Right = 0,
+    Left = 1,
+}
+
+/// An effect run by a multizone light's own firmware.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum MultiZoneEffect {
+    Off,
+    /// Moves the current zone colours along the strip, wrapping round.
+    Move(Direction),
+    /// An effect this crate doesn't know, by its type number.
+    Unknown(u8),
+}
+
+impl MultiZoneEffect {
+    pub fn code(&self) -> u8 {
+        match *self {
+            MultiZoneEffect::Off => 0,
+            MultiZoneEffect::Move(_) => 1,
+            MultiZoneEffect::Unknown(code) => code,
+        }
+    }
+}
+
+/// An effect run by a matrix light's own firmware.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum TileEffect {
+    Off,
+    /// Blends the palette colours in shapes flowing across the tiles.
+    Morph,
+    /// Flickers like flames rising up the tiles. Ignores the palette.
+    Flame,
+    /// An effect this crate doesn't know, by its type number.
+    Unknown(u8),
+}
+
+impl TileEffect {
+    pub fn code(&self) -> u8 {
+        match *self {
+            TileEffect::Off => 0,
+            TileEffect::Morph => 2,
+            TileEffect::Flame => 3,
+            TileEffect::Unknown(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u8) -> TileEffect {
+        match code {
+            0 => TileEffect::Off,
+            2 => TileEffect::Morph,
+            3 => TileEffect::Flame,
+            _ => TileEffect::Unknown(code),
+        }
+    }
+}
+
+/// A multizone effect, as set with SetMultiZoneEffect and reported in
+/// StateMultiZoneEffect.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiZoneEffectSettings {
+    /// Identifies this run of the effect. Devices report it back, so a caller
+    /// can tell whether the effect it started is still the one running.
+    pub instance_id: u32,
+    pub effect: MultiZoneEffect,
+    /// How long one cycle of the effect takes.
+    pub speed: Duration,
+    /// How long the effect runs for, or None to run until changed.
+    pub duration: Option<Duration>,
+}
+
+/// A tile effect, as set with SetTileEffect and reported in StateTileEffect.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileEffectSettings {
+    /// Identifies this run of the effect, see `MultiZoneEffectSettings`.
+    pub instance_id: u32,
+    pub effect: TileEffect,
+    /// How long one cycle of the effect takes.
+    pub speed: Duration,
+    /// How long the effect runs for, or None to run until changed.
+    pub duration: Option<Duration>,
+    /// Up to 16 colours for the effect to use.
+    pub palette: Vec<Hsbk>,
+}
+
+// Durations are sent in nanoseconds, where 0 means forever.
+pub(crate) fn duration_to_nanos(duration: Option<Duration>) -> u64 {
+    duration.map_or(0, |d| d.as_nanos().clamp(1, u128::from(u64::MAX)) as u64)
+}
+
+pub(crate) fn nanos_to_duration(nanos: u64) -> Option<Duration> {
+    match nanos {
+        0 => None,
+        n => Some(Duration::from_nanos(n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{duration_to_nanos, nanos_to_duration, TileEffect};
+    use std::time::Duration;
+
+    #[test]
+    fn test_duration_nanos() {
+        assert_eq!(duration_to_nanos(None), 0);
+        assert_eq!(duration_to_nanos(Some(Duration::ZERO)), 1);
+        assert_eq!(
+            duration_to_nanos(Some(Duration::from_secs(2))),
+            2_000_000_000
+        );
+        assert_eq!(nanos_to_duration(0), None);
+        assert_eq!(nanos_to_duration(5), Some(Duration::from_nanos(5)));
+    }
+
+    #[test]
+    fn test_tile_effect_codes() {
+        for code in 0..8 {
+            assert_eq!(TileEffect::from_code(code).code(), code);
+        }
+        assert_eq!(TileEffect::from_code(3), TileEffect::Flame);
+    }
+}
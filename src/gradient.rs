@@ -0,0 +1,155 @@
+use colour::{self, Hsbk};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The colour space in which to blend between two colours.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Interpolation {
+    /// Blends hue, saturation and brightness separately, taking the shorter way
+    /// round the hue circle. Keeps colours saturated, so red to green passes
+    /// through yellow.
+    Hsv,
+    /// Blends in Oklab, where equal steps look equally different. Red to green
+    /// passes through a muted orange, without a bright yellow band.
+    Oklab,
+}
+
+/// The colour a fraction t, from 0 to 1, of the way from a to b. Kelvin is
+/// always blended linearly.
+pub fn mix(a: &Hsbk, b: &Hsbk, t: f64, interpolation: Interpolation) -> Hsbk {
+    if t <= 0.0 {
+        return *a;
+    }
+    if t >= 1.0 {
+        return *b;
+    }
+
+    let kelvin = lerp(f64::from(a.kelvin), f64::from(b.kelvin), t).round() as u16;
+    match interpolation {
+        Interpolation::Hsv => Hsbk {
+            hue: mix_hue(a, b, t),
+            saturation: lerp(f64::from(a.saturation), f64::from(b.saturation), t).round() as u16,
+            brightness: lerp(f64::from(a.brightness), f64::from(b.brightness), t).round() as u16,
+            kelvin,
+        },
+        Interpolation::Oklab => {
+            let from = colour::rgb_to_oklab(a.to_rgb());
+            let to = colour::rgb_to_oklab(b.to_rgb());
+            let lab = colour::Oklab {
+                l: lerp(from.l, to.l, t),
+                a: lerp(from.a, to.a, t),
+                b: lerp(from.b, to.b, t),
+            };
+            Hsbk::from_rgb(colour::oklab_to_rgb(lab), kelvin)
+        }
+    }
+}
+
+/// Samples n colours, evenly spaced along a gradient through the stops, which
+/// are also evenly spaced. The first and last colours are the first and last
+/// stops. Ready to send as zones with `Light::set_zones` or
+/// `Light::set_extended_zones`.
+pub fn gradient(stops: &[Hsbk], n: usize, interpolation: Interpolation) -> Vec<Hsbk> {
+    match (stops.len(), n) {
+        (0, _) | (_, 0) => return vec![],
+        (1, _) => return vec![stops[0]; n],
+        (_, 1) => return vec![stops[0]],
+        _ => {}
+    }
+
+    let segments = (stops.len() - 1) as f64;
+    (0..n)
+        .map(|i| {
+            let position = i as f64 / (n - 1) as f64 * segments;
+            let segment = (position.floor() as usize).min(stops.len() - 2);
+            let t = position - segment as f64;
+            mix(&stops[segment], &stops[segment + 1], t, interpolation)
+        })
+        .collect()
+}
+
+// Hue takes the shorter way round. An unsaturated end has no meaningful hue, so
+// the other end's hue is kept, rather than sweeping through unrelated colours.
+fn mix_hue(a: &Hsbk, b: &Hsbk, t: f64) -> u16 {
+    match (a.saturation, b.saturation) {
+        (0, 0) | (_, 0) => return a.hue,
+        (0, _) => return b.hue,
+        _ => {}
+    }
+
+    let diff = b.hue.wrapping_sub(a.hue) as i16;
+    let hue = f64::from(a.hue) + f64::from(diff) * t;
+    (hue.round() as i64).rem_euclid(65536) as u16
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{gradient, mix, Interpolation};
+    use colour::Hsbk;
+
+    fn hue(degrees: f64) -> Hsbk {
+        Hsbk::from_degrees(degrees, 100.0, 100.0, 3500)
+    }
+
+    #[test]
+    fn test_mix_hsv_takes_shortest_hue_path() {
+        let c = mix(&hue(350.0), &hue(10.0), 0.5, Interpolation::Hsv);
+        assert!(c.hue_degrees() < 0.1 || c.hue_degrees() > 359.9, "{:?}", c);
+
+        let c = mix(&hue(10.0), &hue(350.0), 0.25, Interpolation::Hsv);
+        assert!((c.hue_degrees() - 5.0).abs() < 0.01, "{:?}", c);
+
+        let c = mix(&hue(0.0), &hue(120.0), 0.5, Interpolation::Hsv);
+        assert!((c.hue_degrees() - 60.0).abs() < 0.01, "{:?}", c);
+    }
+
+    #[test]
+    fn test_mix_hsv_keeps_hue_towards_white() {
+        let white = Hsbk::from_degrees(0.0, 0.0, 100.0, 3500);
+        let c = mix(&hue(240.0), &white, 0.5, Interpolation::Hsv);
+        assert!((c.hue_degrees() - 240.0).abs() < 0.01, "{:?}", c);
+        assert!((c.saturation_percent() - 50.0).abs() < 0.01, "{:?}", c);
+    }
+
+    #[test]
+    fn test_mix_oklab() {
+        let red = hue(0.0);
+        let blue = hue(240.0);
+        assert_eq!(mix(&red, &blue, 0.0, Interpolation::Oklab), red);
+        assert_eq!(mix(&red, &blue, 1.0, Interpolation::Oklab), blue);
+
+        // Halfway from red to blue is a purple, not a grey.
+        let c = mix(&red, &blue, 0.5, Interpolation::Oklab);
+        assert!(
+            c.hue_degrees() > 270.0 && c.hue_degrees() < 330.0,
+            "{:?}",
+            c
+        );
+        assert!(c.saturation_percent() > 40.0, "{:?}", c);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let stops = [hue(0.0), hue(120.0), hue(240.0)];
+        let zones = gradient(&stops, 5, Interpolation::Hsv);
+
+        assert_eq!(zones.len(), 5);
+        assert_eq!(zones[0], stops[0]);
+        assert_eq!(zones[2], stops[1]);
+        assert_eq!(zones[4], stops[2]);
+        assert!((zones[1].hue_degrees() - 60.0).abs() < 0.01);
+
+        assert_eq!(gradient(&stops, 0, Interpolation::Hsv), vec![]);
+        assert_eq!(gradient(&stops, 1, Interpolation::Oklab), vec![stops[0]]);
+        assert_eq!(gradient(&stops[..1], 3, Interpolation::Oklab).len(), 3);
+    }
+}
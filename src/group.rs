@@ -0,0 +1,363 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::thread;
+
+use client::Client;
+use colour::Hsbk;
+use light::Light;
+use messages;
+use registry::RegisteredDevice;
+use request::Payload;
+use response::Response;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How a group command reaches its lights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Delivery {
+    /// A single tagged broadcast to the subnet. Note that this reaches every
+    /// device on the subnet, not only the lights in the group.
+    Broadcast(Ipv4Addr),
+    /// A message to each light in the group, sent in parallel.
+    Unicast,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Outcome {
+    Succeeded,
+    Failed(String),
+    TimedOut,
+}
+
+/// The outcome of a group command for one light.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LightResult {
+    pub socket_addr: SocketAddr,
+    pub target: [u8; 8],
+    pub outcome: Outcome,
+}
+
+/// The outcome of a group command for each light in the group.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GroupReport {
+    pub results: Vec<LightResult>,
+}
+
+impl GroupReport {
+    pub fn succeeded(&self) -> Vec<&LightResult> {
+        self.with_outcome(|o| *o == Outcome::Succeeded)
+    }
+
+    pub fn failed(&self) -> Vec<&LightResult> {
+        self.with_outcome(|o| matches!(*o, Outcome::Failed(_)))
+    }
+
+    pub fn timed_out(&self) -> Vec<&LightResult> {
+        self.with_outcome(|o| *o == Outcome::TimedOut)
+    }
+
+    pub fn all_succeeded(&self) -> bool {
+        self.results.iter().all(|r| r.outcome == Outcome::Succeeded)
+    }
+
+    fn with_outcome<F: Fn(&Outcome) -> bool>(&self, f: F) -> Vec<&LightResult> {
+        self.results.iter().filter(|r| f(&r.outcome)).collect()
+    }
+}
+
+/// A set of lights, controlled together through a shared client.
+#[derive(Clone)]
+pub struct LightGroup {
+    client: Arc<Client>,
+    lights: Vec<Light>,
+}
+
+impl LightGroup {
+    pub fn new(client: Arc<Client>, lights: Vec<Light>) -> LightGroup {
+        LightGroup { client, lights }
+    }
+
+    pub fn from_registered(client: Arc<Client>, devices: &[RegisteredDevice]) -> LightGroup {
+        let lights = devices
+            .iter()
+            .map(|d| Light::from_registered(client.clone(), d))
+            .collect();
+        LightGroup::new(client, lights)
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Turns every light on or off, over the duration in milliseconds.
+    pub fn set_power(&self, on: bool, duration: u32, delivery: Delivery) -> GroupReport {
+        let level = if on { 65535 } else { 0 };
+        self.apply(
+            117,
+            &messages::set_light_power_payload(level, duration),
+            delivery,
+        )
+    }
+
+    /// Changes every light's colour, over the duration in milliseconds.
+    ///
+    /// Kelvin is clamped to each light's product range when known. A broadcast
+    /// reaches every light at once, so it's clamped to each range in turn,
+    /// landing in the range they share when there is one.
+    pub fn set_color(&self, hsbk: &Hsbk, duration: u32, delivery: Delivery) -> GroupReport {
+        match delivery {
+            Delivery::Broadcast(subnet) => {
+                let hsbk = self.lights.iter().fold(*hsbk, |c, l| l.clamp(&c));
+                let payload = messages::set_colour_payload(&hsbk, duration);
+                self.apply_broadcast(subnet, 102, &payload)
+            }
+            Delivery::Unicast => self.apply_unicast(102, |l| {
+                messages::set_colour_payload(&l.clamp(hsbk), duration)
+            }),
+        }
+    }
+
+    fn apply(&self, message_type: u16, payload: &Payload, delivery: Delivery) -> GroupReport {
+        match delivery {
+            Delivery::Broadcast(subnet) => self.apply_broadcast(subnet, message_type, payload),
+            Delivery::Unicast => self.apply_unicast(message_type, |_| Payload(payload.0.clone())),
+        }
+    }
+
+    // Sends each light the payload made for it.
+    fn apply_unicast<F>(&self, message_type: u16, payload: F) -> GroupReport
+    where
+        F: Fn(&Light) -> Payload + Sync,
+    {
+        let payload = &payload;
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .lights
+                .iter()
+                .map(|l| {
+                    s.spawn(move || {
+                        let result =
+                            self.client
+                                .set(l.socket_addr(), l.target(), message_type, payload(l));
+                        LightResult {
+                            socket_addr: l.socket_addr(),
+                            target: l.target(),
+                            outcome: outcome(result),
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("group worker panicked"))
+                .collect()
+        });
+
+        GroupReport { results }
+    }
+
+    // Broadcasts, then rebroadcasts while any light has yet to acknowledge.
+    fn apply_broadcast(
+        &self,
+        subnet: Ipv4Addr,
+        message_type: u16,
+        payload: &Payload,
+    ) -> GroupReport {
+        let mut acks: Vec<(SocketAddr, Response)> = vec![];
+        let mut error: Option<io::Error> = None;
+
+        for _ in 0..self.client.retries() + 1 {
+            match self.client.broadcast_set(
+                subnet,
+                message_type,
+                Payload(payload.0.clone()),
+                self.client.timeout(),
+            ) {
+                Ok(mut v) => acks.append(&mut v),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+
+            if report(&self.lights, &acks, None).all_succeeded() {
+                break;
+            }
+        }
+
+        report(&self.lights, &acks, error.as_ref())
+    }
+}
+
+pub(crate) fn outcome<T>(result: Result<T, io::Error>) -> Outcome {
+    match result {
+        Ok(_) => Outcome::Succeeded,
+        Err(ref e) if e.kind() == io::ErrorKind::TimedOut => Outcome::TimedOut,
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+// Matches broadcast acknowledgements to the lights that sent them. Lights that
+// didn't acknowledge failed if the broadcast couldn't be sent, else timed out.
+fn report(
+    lights: &[Light],
+    acks: &[(SocketAddr, Response)],
+    error: Option<&io::Error>,
+) -> GroupReport {
+    let results = lights
+        .iter()
+        .map(|l| {
+            let acked = acks.iter().any(|(_, resp)| resp.target == l.target());
+            let outcome = match (acked, error) {
+                (true, _) => Outcome::Succeeded,
+                (false, Some(e)) => Outcome::Failed(e.to_string()),
+                (false, None) => Outcome::TimedOut,
+            };
+            LightResult {
+                socket_addr: l.socket_addr(),
+                target: l.target(),
+                outcome,
+            }
+        })
+        .collect();
+
+    GroupReport { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, Delivery, LightGroup, Outcome};
+    use client::Client;
+    use colour::Hsbk;
+    use light::Light;
+    use products;
+    use response::{parse_response, ResponseData};
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn ack(mac_last: u8) -> ResponseData {
+        let mut data = vec![0u8; 36];
+        data[0] = 36;
+        data[8..14].clone_from_slice(&[0xD0, 0x73, 0xD5, 1, 2, mac_last]);
+        data[32] = 45;
+        ResponseData(data)
+    }
+
+    #[test]
+    fn test_report_matches_acks_to_lights() {
+        let client = Arc::new(Client::new().unwrap());
+        let addr = "192.168.1.10:56700".parse().unwrap();
+        let lights = vec![
+            Light::new(client.clone(), addr, [0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0]),
+            Light::new(client.clone(), addr, [0xD0, 0x73, 0xD5, 1, 2, 4, 0, 0]),
+        ];
+        let acks = vec![(addr, parse_response(ack(4)))];
+
+        let r = report(&lights, &acks, None);
+        assert_eq!(r.results[0].outcome, Outcome::TimedOut);
+        assert_eq!(r.results[1].outcome, Outcome::Succeeded);
+        assert!(!r.all_succeeded());
+        assert_eq!(r.timed_out().len(), 1);
+    }
+
+    #[test]
+    fn test_unicast_report() {
+        // One stand-in device acknowledges, the other never replies.
+        let acking = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let acking_addr = acking.local_addr().unwrap();
+        let silent_addr = silent.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 1024];
+            let (_, from) = acking.recv_from(&mut buf).unwrap();
+            let mut reply = buf[0..36].to_vec();
+            reply[0] = 36;
+            reply[32] = 45;
+            reply[33] = 0;
+            acking.send_to(&reply, from).unwrap();
+        });
+
+        let client = Arc::new(
+            Client::new()
+                .unwrap()
+                .with_timeout(Duration::from_millis(200))
+                .with_retries(0),
+        );
+        let group = LightGroup::new(
+            client.clone(),
+            vec![
+                Light::new(client.clone(), acking_addr, [0; 8]),
+                Light::new(client.clone(), silent_addr, [0; 8]),
+            ],
+        );
+
+        let r = group.set_power(true, 0, Delivery::Unicast);
+        responder.join().unwrap();
+        drop(silent);
+
+        assert_eq!(r.results[0].outcome, Outcome::Succeeded);
+        assert_eq!(r.results[1].outcome, Outcome::TimedOut);
+        assert_eq!(r.succeeded().len(), 1);
+    }
+
+    #[test]
+    fn test_set_color_clamps_kelvin_per_product() {
+        // Two stand-in devices, each acknowledging a SetColor and handing back
+        // the kelvin it was sent.
+        let device = || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let addr = socket.local_addr().unwrap();
+            let responder = thread::spawn(move || {
+                let mut buf = [0; 1024];
+                let (_, from) = socket.recv_from(&mut buf).unwrap();
+                let mut reply = buf[0..36].to_vec();
+                reply[0] = 36;
+                reply[32] = 45;
+                reply[33] = 0;
+                socket.send_to(&reply, from).unwrap();
+                u16::from_le_bytes([buf[43], buf[44]])
+            });
+            (addr, responder)
+        };
+        let (warm_addr, warm) = device();
+        let (white_addr, white) = device();
+
+        let client = Arc::new(Client::new().unwrap());
+        let group = LightGroup::new(
+            client.clone(),
+            vec![
+                // A Mini White to Warm, 1500-4000K, and a White 800, 2700-6500K.
+                Light::new(client.clone(), warm_addr, [0; 8]).with_product(products::lookup(1, 50)),
+                Light::new(client.clone(), white_addr, [0; 8])
+                    .with_product(products::lookup(1, 10)),
+            ],
+        );
+        let daylight = Hsbk {
+            hue: 0,
+            saturation: 0,
+            brightness: 65535,
+            kelvin: 9000,
+        };
+
+        assert!(group
+            .set_color(&daylight, 0, Delivery::Unicast)
+            .all_succeeded());
+        assert_eq!(warm.join().unwrap(), 4000);
+        assert_eq!(white.join().unwrap(), 6500);
+    }
+}
@@ -0,0 +1,608 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use colour::Hsbk;
+use group::{self, Outcome};
+use json::Json;
+use light::Light;
+use registry::{DeviceRegistry, RegisteredDevice};
+use selector::Selector;
+
+/// Requests with more header or body than this are refused.
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// An HTTP request, as read from a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpRequest {
+    /// Reads one HTTP/1.x request.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<HttpRequest, io::Error> {
+        let mut header_bytes = 0;
+        let mut line = String::new();
+        let mut read_line = |line: &mut String| -> Result<(), io::Error> {
+            line.clear();
+            header_bytes += reader.take(MAX_HEADER_BYTES as u64).read_line(line)?;
+            if header_bytes >= MAX_HEADER_BYTES {
+                return Err(bad_request("header too large"));
+            }
+            Ok(())
+        };
+
+        read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(p), Some(v)) if v.starts_with("HTTP/1.") => {
+                (m.to_string(), p.to_string())
+            }
+            _ => return Err(bad_request("bad request line")),
+        };
+
+        let mut headers: Vec<(String, String)> = vec![];
+        loop {
+            read_line(&mut line)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            match header.find(':') {
+                Some(i) => headers.push((
+                    header[..i].trim().to_lowercase(),
+                    header[i + 1..].trim().to_string(),
+                )),
+                None => return Err(bad_request("bad header")),
+            }
+        }
+
+        let mut request = HttpRequest {
+            method,
+            path,
+            headers,
+            body: String::new(),
+        };
+        let length = match request.header("content-length") {
+            Some(l) => l
+                .parse::<usize>()
+                .map_err(|_| bad_request("bad content-length"))?,
+            None => 0,
+        };
+        if length > MAX_BODY_BYTES {
+            return Err(bad_request("body too large"));
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        request.body = String::from_utf8(body).map_err(|_| bad_request("body is not UTF-8"))?;
+
+        Ok(request)
+    }
+
+    /// The value of the header, whose name is matched ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.0.eq_ignore_ascii_case(name))
+            .map(|h| h.1.as_str())
+    }
+}
+
+/// A JSON response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    body: Json,
+}
+
+impl HttpResponse {
+    /// The body, as JSON text.
+    pub fn body(&self) -> String {
+        self.body.to_string()
+    }
+
+    fn error(status: u16, msg: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            body: Json::object(vec![("error", Json::from(msg))]),
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        write_response(
+            writer,
+            self.status,
+            "application/json",
+            &self.body.to_string(),
+        )
+    }
+}
+
+/// Writes a response, closing the connection after it.
+pub(crate) fn write_response<W: Write>(
+    writer: &mut W,
+    status: u16,
+    content_type: &str,
+    body: &str,
+) -> Result<(), io::Error> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason(status),
+        content_type,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+/// A local version of the LIFX HTTP API, controlling lights over the LAN.
+///
+/// ```text
+/// GET  /v1/lights/:selector          List the lights
+/// PUT  /v1/lights/:selector/state    Change power, color, brightness
+/// POST /v1/lights/:selector/toggle   Turn off if any are on, else turn on
+/// ```
+///
+/// Lights are listed from the registry, so are as fresh as its last refresh.
+/// Changes go straight to the lights.
+pub struct HttpApi {
+    registry: Arc<DeviceRegistry>,
+    token: Option<String>,
+}
+
+impl HttpApi {
+    pub fn new(registry: Arc<DeviceRegistry>) -> HttpApi {
+        HttpApi {
+            registry,
+            token: None,
+        }
+    }
+
+    /// Requires every request to carry the token, as `Authorization: Bearer <token>`.
+    pub fn with_token(mut self, token: &str) -> HttpApi {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        if let Some(ref token) = self.token {
+            let bearer = request
+                .header("authorization")
+                .and_then(|a| a.strip_prefix("Bearer "));
+            if bearer != Some(token.as_str()) {
+                return HttpResponse::error(401, "missing or wrong bearer token");
+            }
+        }
+
+        let path = request.path.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let (selector, action) = match segments[..] {
+            ["v1", "lights", selector] => (selector, ""),
+            ["v1", "lights", selector, action] => (selector, action),
+            _ => return HttpResponse::error(404, "not found"),
+        };
+
+        let selector: Selector = match percent_decode(selector).map(|s| s.parse()) {
+            Some(Ok(s)) => s,
+            Some(Err(e)) => return HttpResponse::error(422, &e),
+            None => return HttpResponse::error(422, "bad percent encoding in selector"),
+        };
+        let method = request.method.as_str();
+        match (method, action) {
+            ("GET", "") | ("PUT", "state") | ("POST", "toggle") => {}
+            (_, "") | (_, "state") | (_, "toggle") => {
+                return HttpResponse::error(405, "method not allowed")
+            }
+            _ => return HttpResponse::error(404, "not found"),
+        }
+
+        let devices = selector.select(&self.registry.devices());
+        if devices.is_empty() {
+            return HttpResponse::error(404, &format!("no lights match {}", selector));
+        }
+
+        match action {
+            "state" => match parse_state(&request.body) {
+                Ok(change) => self.set_state(&devices, &change),
+                Err(e) => HttpResponse::error(422, &e),
+            },
+            "toggle" => match parse_duration(&request.body) {
+                Ok(duration) => self.toggle(&devices, duration),
+                Err(e) => HttpResponse::error(422, &e),
+            },
+            _ => HttpResponse {
+                status: 200,
+                body: Json::Array(devices.iter().map(light_json).collect()),
+            },
+        }
+    }
+
+    fn set_state(&self, devices: &[RegisteredDevice], change: &StateChange) -> HttpResponse {
+        let client = self.registry.client();
+        results(devices, |d| {
+            let light = Light::from_registered(client.clone(), d);
+            apply(&light, d, change)
+        })
+    }
+
+    fn toggle(&self, devices: &[RegisteredDevice], duration: u32) -> HttpResponse {
+        let client = self.registry.client();
+        let lights: Vec<Light> = devices
+            .iter()
+            .map(|d| Light::from_registered(client.clone(), d))
+            .collect();
+
+        // Lights that don't answer count as off.
+        let any_on = thread::scope(|s| {
+            let handles: Vec<_> = lights.iter().map(|l| s.spawn(move || l.power())).collect();
+            handles
+                .into_iter()
+                .any(|h| h.join().ok().and_then(|p| p.ok()) == Some(true))
+        });
+
+        results(devices, |d| {
+            let light = Light::from_registered(client.clone(), d);
+            light.set_power(!any_on, duration)
+        })
+    }
+}
+
+/// Serves the API on the listener, a thread per connection. Connections that
+/// fail to be accepted are logged and skipped.
+pub fn serve(api: Arc<HttpApi>, listener: TcpListener) -> Result<(), io::Error> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                // Such as running out of file descriptors, which a moment may ease.
+                warn!("accepting a connection failed: {}", e);
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        };
+        let api = api.clone();
+        thread::spawn(move || {
+            let _ = handle_connection(&api, stream);
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(api: &HttpApi, mut stream: TcpStream) -> Result<(), io::Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let request = HttpRequest::read(&mut BufReader::new(stream.try_clone()?));
+    let response = match request {
+        Ok(ref r) => api.handle(r),
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => {
+            HttpResponse::error(400, &e.to_string())
+        }
+        Err(e) => return Err(e),
+    };
+    response.write(&mut stream)
+}
+
+/// A change requested with PUT .../state.
+#[derive(Debug, Clone, PartialEq)]
+struct StateChange {
+    power: Option<bool>,
+    colour: Option<Hsbk>,
+    /// From 0 to 1, overriding the colour's brightness, or the light's own.
+    brightness: Option<f64>,
+    duration: u32,
+}
+
+fn parse_state(body: &str) -> Result<StateChange, String> {
+    let json = Json::parse(body).map_err(|e| e.to_string())?;
+    let power = match json.get("power") {
+        None => None,
+        Some(p) => match p.as_str() {
+            Some("on") => Some(true),
+            Some("off") => Some(false),
+            _ => return Err("power must be \"on\" or \"off\"".to_string()),
+        },
+    };
+    let colour = match json.get("color") {
+        None => None,
+        Some(c) => match c.as_str().map(|c| c.parse::<Hsbk>()) {
+            Some(Ok(c)) => Some(c),
+            Some(Err(e)) => return Err(e.to_string()),
+            None => return Err("color must be a string".to_string()),
+        },
+    };
+    let brightness = match json.get("brightness") {
+        None => None,
+        Some(b) => match b.as_f64() {
+            Some(b) if (0.0..=1.0).contains(&b) => Some(b),
+            _ => return Err("brightness must be from 0 to 1".to_string()),
+        },
+    };
+
+    Ok(StateChange {
+        power,
+        colour,
+        brightness,
+        duration: parse_duration(body)?,
+    })
+}
+
+// The duration field, in seconds as in the LIFX HTTP API, as milliseconds. The
+// protocol carries milliseconds in a u32, so at most about 49 days.
+fn parse_duration(body: &str) -> Result<u32, String> {
+    if body.trim().is_empty() {
+        return Ok(0);
+    }
+    let json = Json::parse(body).map_err(|e| e.to_string())?;
+    match json.get("duration") {
+        None => Ok(0),
+        Some(d) => match d.as_f64().map(|d| (d * 1000.0).round()) {
+            Some(ms) if (0.0..=f64::from(u32::MAX)).contains(&ms) => Ok(ms as u32),
+            Some(ms) if ms > 0.0 => Err(format!(
+                "duration must be at most {} seconds",
+                f64::from(u32::MAX) / 1000.0
+            )),
+            _ => Err("duration must be a number of seconds".to_string()),
+        },
+    }
+}
+
+// Colour changes before power, so a light turned on fades in to the new colour.
+fn apply(light: &Light, device: &RegisteredDevice, change: &StateChange) -> Result<(), io::Error> {
+    if change.colour.is_some() || change.brightness.is_some() {
+        let mut colour = match (change.colour, &device.state) {
+            (Some(c), _) => c,
+            (None, Some(s)) => s.hsbk,
+            (None, None) => light.color()?,
+        };
+        if let Some(b) = change.brightness {
+            colour.brightness = (b * 65535.0).round() as u16;
+        }
+        light.set_color(&colour, change.duration)?;
+    }
+    if let Some(on) = change.power {
+        light.set_power(on, change.duration)?;
+    }
+    Ok(())
+}
+
+// Runs f on every device in parallel, and reports each outcome as the LIFX HTTP
+// API does, with status 207.
+fn results<F>(devices: &[RegisteredDevice], f: F) -> HttpResponse
+where
+    F: Fn(&RegisteredDevice) -> Result<(), io::Error> + Sync,
+{
+    let outcomes: Vec<Outcome> = thread::scope(|s| {
+        let f = &f;
+        let handles: Vec<_> = devices
+            .iter()
+            .map(|d| s.spawn(move || group::outcome(f(d))))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("http worker panicked"))
+            .collect()
+    });
+
+    let results = devices
+        .iter()
+        .zip(outcomes)
+        .map(|(d, outcome)| {
+            let (status, error) = match outcome {
+                Outcome::Succeeded => ("ok", None),
+                Outcome::TimedOut => ("timed_out", None),
+                Outcome::Failed(e) => ("error", Some(e)),
+            };
+            Json::object(vec![
+                ("id", Json::from(id(d))),
+                ("label", Json::from(d.label.clone())),
+                ("status", Json::from(status)),
+                ("error", Json::from(error)),
+            ])
+        })
+        .collect();
+
+    HttpResponse {
+        status: 207,
+        body: Json::object(vec![("results", Json::Array(results))]),
+    }
+}
+
+// A light, in the shape of the LIFX HTTP API's list lights response.
+fn light_json(d: &RegisteredDevice) -> Json {
+    let state = d.state.as_ref();
+    let product = d.product_info().map(|p| {
+        Json::object(vec![
+            ("name", Json::from(p.name)),
+            (
+                "capabilities",
+                Json::object(vec![
+                    ("has_color", Json::from(p.colour)),
+                    ("has_variable_color_temp", Json::from(true)),
+                    ("has_ir", Json::from(p.infrared)),
+                    ("has_multizone", Json::from(p.multizone)),
+                    ("has_matrix", Json::from(p.matrix)),
+                    ("min_kelvin", Json::from(p.min_kelvin)),
+                    ("max_kelvin", Json::from(p.max_kelvin)),
+                ]),
+            ),
+        ])
+    });
+    let named = |name: &Option<String>| {
+        name.as_ref()
+            .map(|n| Json::object(vec![("name", Json::from(n.as_str()))]))
+    };
+    let seconds_since_seen = SystemTime::now()
+        .duration_since(d.last_seen)
+        .map_or(0.0, |s| s.as_secs() as f64);
+
+    Json::object(vec![
+        ("id", Json::from(id(d))),
+        ("label", Json::from(d.label.clone())),
+        ("connected", Json::from(d.online)),
+        (
+            "power",
+            Json::from(state.map(|s| if s.power != 0 { "on" } else { "off" })),
+        ),
+        (
+            "color",
+            Json::from(state.map(|s| {
+                Json::object(vec![
+                    ("hue", Json::from(s.hsbk.hue_degrees())),
+                    (
+                        "saturation",
+                        Json::from(s.hsbk.saturation_percent() / 100.0),
+                    ),
+                    ("kelvin", Json::from(s.hsbk.kelvin)),
+                ])
+            })),
+        ),
+        (
+            "brightness",
+            Json::from(state.map(|s| s.hsbk.brightness_percent() / 100.0)),
+        ),
+        ("group", Json::from(named(&d.group))),
+        ("location", Json::from(named(&d.location))),
+        ("product", Json::from(product)),
+        ("seconds_since_seen", Json::from(seconds_since_seen)),
+    ])
+}
+
+// The MAC address as the LIFX HTTP API writes ids, in lower case without colons.
+fn id(d: &RegisteredDevice) -> String {
+    d.mac_address[..17].replace(':', "").to_lowercase()
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded: Vec<u8> = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        207 => "Multi-Status",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Entity",
+        _ => "",
+    }
+}
+
+fn bad_request(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_state, percent_decode, HttpApi, HttpRequest, StateChange};
+    use client::Client;
+    use colour::Hsbk;
+    use registry::DeviceRegistry;
+    use std::io::BufReader;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+
+    fn request(method: &str, path: &str) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = "PUT /v1/lights/all/state HTTP/1.1\r\nHost: x\r\nContent-Length: 16\r\n\r\n{\"power\": \"on\"}\n";
+        let r = HttpRequest::read(&mut BufReader::new(raw.as_bytes())).unwrap();
+
+        assert_eq!(r.method, "PUT");
+        assert_eq!(r.path, "/v1/lights/all/state");
+        assert_eq!(r.header("Content-Length"), Some("16"));
+        assert_eq!(r.body, "{\"power\": \"on\"}\n");
+
+        let raw = "GET /\r\n\r\n";
+        assert!(HttpRequest::read(&mut BufReader::new(raw.as_bytes())).is_err());
+    }
+
+    #[test]
+    fn test_parse_state() {
+        let change =
+            parse_state(r#"{"power": "on", "color": "red", "brightness": 0.5, "duration": 1.5}"#);
+        assert_eq!(
+            change,
+            Ok(StateChange {
+                power: Some(true),
+                colour: Some("red".parse::<Hsbk>().unwrap()),
+                brightness: Some(0.5),
+                duration: 1500,
+            })
+        );
+
+        assert_eq!(
+            parse_state(r#"{"duration": 4294967.295}"#).map(|c| c.duration),
+            Ok(u32::MAX)
+        );
+        assert!(parse_state(r#"{"duration": 4294968}"#).is_err());
+        assert!(parse_state(r#"{"power": "dim"}"#).is_err());
+        assert!(parse_state(r#"{"brightness": 2}"#).is_err());
+        assert!(parse_state(r#"{"color": "purpel"}"#).is_err());
+        assert!(parse_state("{").is_err());
+    }
+
+    #[test]
+    fn test_handle_errors() {
+        let client = Arc::new(Client::new().unwrap());
+        let registry = Arc::new(DeviceRegistry::new(client, Ipv4Addr::new(127, 0, 0, 255)));
+        let api = HttpApi::new(registry.clone());
+
+        let status = |r: &HttpRequest| api.handle(r).status;
+        assert_eq!(status(&request("GET", "/v1/lights/all")), 404);
+        assert_eq!(status(&request("GET", "/v1/lights/label:Desk%20Lamp")), 404);
+        assert_eq!(status(&request("DELETE", "/v1/lights/all")), 405);
+        assert_eq!(status(&request("GET", "/v1/lights/all/state")), 405);
+        assert_eq!(status(&request("GET", "/v1/scenes")), 404);
+        assert_eq!(status(&request("GET", "/v1/lights/colour:red")), 422);
+
+        let api = HttpApi::new(registry).with_token("secret");
+        let mut r = request("GET", "/v1/lights/all");
+        assert_eq!(api.handle(&r).status, 401);
+        r.headers
+            .push(("authorization".to_string(), "Bearer secret".to_string()));
+        assert_eq!(api.handle(&r).status, 404);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(
+            percent_decode("label:Desk%20Lamp"),
+            Some("label:Desk Lamp".to_string())
+        );
+        assert_eq!(percent_decode("%zz"), None);
+    }
+}
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::io;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A JSON value, for the command line tools and HTTP API. Object keys keep the
+/// order they were added, or parsed, in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object, from (key, value) pairs.
+    pub fn object<K: Into<String>>(fields: Vec<(K, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Parses a JSON document.
+    pub fn parse(s: &str) -> Result<Json, io::Error> {
+        let mut chars = s.chars().peekable();
+        let value = parse_value(&mut chars, 0)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(invalid(&format!("unexpected {:?} after the value", c))),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The value of the key, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<u16> for Json {
+    fn from(n: u16) -> Json {
+        Json::Number(f64::from(n))
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Number(f64::from(n))
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(o: Option<T>) -> Json {
+        o.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(v: Vec<T>) -> Json {
+        Json::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Writes compact JSON.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // JSON has no representation of NaN or infinity.
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// Nesting deeper than this is refused, rather than overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Result<Json, io::Error> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("nested too deeply"));
+    }
+
+    skip_whitespace(chars);
+    match chars.peek().cloned() {
+        Some('{') => {
+            chars.next();
+            let mut fields: Vec<(String, Json)> = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                if chars.next() != Some('"') {
+                    return Err(invalid("expected a string key"));
+                }
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next() != Some(':') {
+                    return Err(invalid("expected ':' after a key"));
+                }
+                fields.push((key, parse_value(chars, depth + 1)?));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err(invalid("expected ',' or '}' in an object")),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut items: Vec<Json> = vec![];
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, depth + 1)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Ok(Json::Array(items)),
+                    _ => return Err(invalid("expected ',' or ']' in an array")),
+                }
+            }
+        }
+        Some('"') => {
+            chars.next();
+            parse_string(chars).map(Json::String)
+        }
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            number
+                .parse()
+                .map(Json::Number)
+                .map_err(|_| invalid(&format!("bad number {:?}", number)))
+        }
+        Some(_) => {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_alphabetic() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            match word.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(invalid(&format!("unexpected {:?}", word))),
+            }
+        }
+        None => Err(invalid("unexpected end of input")),
+    }
+}
+
+// Parses the rest of a string, after the opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, io::Error> {
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .map_err(|_| invalid(&format!("bad escape \\u{}", hex)))?;
+                    // Surrogate pairs aren't combined, so are replaced.
+                    s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                _ => return Err(invalid("bad escape in a string")),
+            },
+            Some(c) => s.push(c),
+            None => return Err(invalid("unterminated string")),
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid JSON: {}", msg))
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    #[test]
+    fn test_write_json() {
+        let v = Json::object(vec![
+            ("label", Json::from("Desk \"lamp\"\n")),
+            ("power", Json::from(true)),
+            ("hue", Json::from(120.5)),
+            ("group", Json::from(None::<String>)),
+            ("zones", Json::from(vec![1u16, 2])),
+        ]);
+
+        assert_eq!(
+            v.to_string(),
+            r#"{"label":"Desk \"lamp\"\n","power":true,"hue":120.5,"group":null,"zones":[1,2]}"#
+        );
+        assert_eq!(v.get("power"), Some(&Json::Bool(true)));
+        assert_eq!(Json::Number(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let v = Json::parse(
+            r#" {"power": "on", "brightness": 0.5, "fast": false,
+                "tags": ["a", null, -1.5e2], "label": "Desk \"lamp\" \u00e9"} "#,
+        )
+        .unwrap();
+
+        assert_eq!(v.get("power").and_then(Json::as_str), Some("on"));
+        assert_eq!(v.get("brightness").and_then(Json::as_f64), Some(0.5));
+        assert_eq!(v.get("fast").and_then(Json::as_bool), Some(false));
+        assert_eq!(
+            v.get("tags"),
+            Some(&Json::Array(vec![
+                Json::from("a"),
+                Json::Null,
+                Json::Number(-150.0)
+            ]))
+        );
+        assert_eq!(
+            v.get("label").and_then(Json::as_str),
+            Some("Desk \"lamp\" \u{e9}")
+        );
+        assert_eq!(Json::parse(&v.to_string()).unwrap(), v);
+    }
+
+    #[test]
+    fn test_parse_json_errors() {
+        let tests = vec![
+            ("", "invalid JSON: unexpected end of input"),
+            ("{\"a\" 1}", "invalid JSON: expected ':' after a key"),
+            ("[1, 2", "invalid JSON: expected ',' or ']' in an array"),
+            ("\"abc", "invalid JSON: unterminated string"),
+            ("nul", "invalid JSON: unexpected \"nul\""),
+            ("1 2", "invalid JSON: unexpected '2' after the value"),
+        ];
+        for (s, want) in tests {
+            assert_eq!(Json::parse(s).unwrap_err().to_string(), want, "{}", s);
+        }
+
+        let deep = "[".repeat(100) + &"]".repeat(100);
+        assert!(Json::parse(&deep).is_err());
+    }
+}
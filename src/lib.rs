@@ -1,8 +1,75 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 
+// Injected without std, but otherwise needs naming for the codec's `use core::...`.
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod circadian;
+#[cfg(feature = "std")]
+pub mod client;
+pub mod codec;
+#[cfg(feature = "std")]
 pub mod colour;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
 pub mod convert;
+#[cfg(feature = "std")]
+pub mod css;
+#[cfg(feature = "std")]
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod effect;
+#[cfg(feature = "std")]
+pub mod gradient;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "std")]
+pub mod http;
+// For the binaries' output; not part of the supported API.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod light;
+#[cfg(feature = "std")]
 pub mod messages;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "std")]
 pub mod network;
+#[cfg(feature = "std")]
+pub mod products;
+#[cfg(feature = "std")]
+pub mod ratelimit;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
 pub mod request;
+#[cfg(feature = "std")]
 pub mod response;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod schedule;
+#[cfg(feature = "std")]
+pub mod selector;
+#[cfg(feature = "std")]
+pub mod sun;
+#[cfg(feature = "std")]
+mod toml;
@@ -0,0 +1,391 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use client::Client;
+use colour::{Hsbk, Kelvin};
+use effect::{MultiZoneEffectSettings, TileEffectSettings};
+use messages::{self, ZoneApply};
+use products::{self, Product};
+use registry::RegisteredDevice;
+use request;
+use response::{Payload, Response, TileInfo};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The state of a light, as reported by the light itself.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LightState {
+    pub hsbk: Hsbk,
+    pub power: bool,
+    pub label: String,
+}
+
+/// A handle to a single light, sending every message through a shared client.
+///
+/// Each method waits for the light to reply, or to acknowledge a change, and
+/// returns the decoded value rather than the raw response.
+///
+/// When the light's product is known, colours sent to it have their kelvin
+/// clamped to the product's range.
+#[derive(Clone)]
+pub struct Light {
+    client: Arc<Client>,
+    socket_addr: SocketAddr,
+    target: [u8; 8],
+    product: Option<&'static Product>,
+}
+
+impl Light {
+    pub fn new(client: Arc<Client>, socket_addr: SocketAddr, target: [u8; 8]) -> Light {
+        Light {
+            client,
+            socket_addr,
+            target,
+            product: None,
+        }
+    }
+
+    pub fn from_registered(client: Arc<Client>, device: &RegisteredDevice) -> Light {
+        Light::new(client, device.socket_addr, device.target).with_product(device.product_info())
+    }
+
+    /// The light's product, whose kelvin range colours are clamped to.
+    pub fn with_product(mut self, product: Option<&'static Product>) -> Light {
+        self.product = product;
+        self
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.socket_addr
+    }
+
+    pub fn target(&self) -> [u8; 8] {
+        self.target
+    }
+
+    pub fn client(&self) -> &Arc<Client> {
+        &self.client
+    }
+
+    /// The product given by `with_product` or the registry, without asking the
+    /// light.
+    pub fn known_product(&self) -> Option<&'static Product> {
+        self.product
+    }
+
+    /// The colour as the light would show it, with kelvin clamped to the
+    /// product's range when known.
+    pub fn clamp(&self, hsbk: &Hsbk) -> Hsbk {
+        match self.product {
+            Some(p) => Hsbk {
+                kelvin: Kelvin::for_product(hsbk.kelvin, Some(p)).value(),
+                ..*hsbk
+            },
+            None => *hsbk,
+        }
+    }
+
+    /// Whether the light is on.
+    pub fn power(&self) -> Result<bool, io::Error> {
+        match self.get(20)?.payload {
+            Payload::StatePower(p) => Ok(p.level != 0),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// Turns the light on or off, over the duration in milliseconds.
+    pub fn set_power(&self, on: bool, duration: u32) -> Result<(), io::Error> {
+        let level = if on { 65535 } else { 0 };
+        self.set(117, messages::set_light_power_payload(level, duration))
+    }
+
+    /// The light's current colour.
+    pub fn color(&self) -> Result<Hsbk, io::Error> {
+        self.state().map(|s| s.hsbk)
+    }
+
+    /// Changes the light's colour, over the duration in milliseconds.
+    pub fn set_color(&self, hsbk: &Hsbk, duration: u32) -> Result<(), io::Error> {
+        self.set(
+            102,
+            messages::set_colour_payload(&self.clamp(hsbk), duration),
+        )
+    }
+
+    pub fn label(&self) -> Result<String, io::Error> {
+        match self.get(23)?.payload {
+            Payload::StateLabel(p) => Ok(p.label),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    pub fn set_label(&self, label: &str) -> Result<(), io::Error> {
+        self.set(24, messages::set_label_payload(label))
+    }
+
+    /// The round trip time of an EchoRequest, which the light answers without
+    /// doing anything else.
+    pub fn ping(&self) -> Result<Duration, io::Error> {
+        let start = Instant::now();
+        self.get_with(58, request::Payload(vec![0; 64]))?;
+        Ok(start.elapsed())
+    }
+
+    /// The Wi-Fi signal strength the light receives, in dBm.
+    pub fn wifi_signal(&self) -> Result<f64, io::Error> {
+        match self.get(16)?.payload {
+            Payload::StateWifiInfo(p) => Ok(p.rssi()),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// How long the light has been running since it last started.
+    pub fn uptime(&self) -> Result<Duration, io::Error> {
+        match self.get(34)?.payload {
+            Payload::StateInfo(p) => Ok(p.uptime),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// The light's colour, power and label, in a single request.
+    pub fn state(&self) -> Result<LightState, io::Error> {
+        match self.get(101)?.payload {
+            Payload::State(p) => Ok(LightState {
+                hsbk: p.hsbk,
+                power: p.power != 0,
+                label: p.label,
+            }),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// The product details, if the light reports a product this crate knows.
+    pub fn product(&self) -> Result<Option<&'static Product>, io::Error> {
+        match self.get(32)?.payload {
+            Payload::StateVersion(p) => Ok(products::lookup(p.vendor, p.product)),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// The colour of each zone of a multizone light.
+    pub fn zones(&self) -> Result<Vec<Hsbk>, io::Error> {
+        // Zones are fetched eight at a time, the most a StateMultiZone reply holds.
+        let mut zones: Vec<Hsbk> = vec![];
+        let mut count = 8;
+        while zones.len() < count {
+            let start = zones.len() as u8;
+            let payload = messages::get_colour_zones_payload(start, start.saturating_add(7));
+            match self.get_with(502, payload)?.payload {
+                Payload::StateMultiZone(p) => {
+                    count = p.count as usize;
+                    let remaining = count.saturating_sub(zones.len());
+                    zones.extend(p.colours.into_iter().take(remaining));
+                }
+                Payload::StateZone(p) => {
+                    count = p.count as usize;
+                    zones.extend(p.colours);
+                }
+                p => return Err(unexpected(p)),
+            }
+        }
+
+        Ok(zones)
+    }
+
+    /// Sets the colour of each zone of a multizone light, from the first zone,
+    /// over the duration in milliseconds.
+    ///
+    /// Runs of zones of the same colour are set with a single message, and the
+    /// change is applied once every zone has been set.
+    pub fn set_zones(&self, colours: &[Hsbk], duration: u32) -> Result<(), io::Error> {
+        let runs = zone_runs(&self.clamp_all(colours));
+        for (i, &(start, end, colour)) in runs.iter().enumerate() {
+            let apply = if i + 1 == runs.len() {
+                ZoneApply::Apply
+            } else {
+                ZoneApply::NoApply
+            };
+            let payload = messages::set_colour_zones_payload(start, end, &colour, duration, apply);
+            self.set(501, payload)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the colour of up to 82 zones from index in a single message, on
+    /// multizone lights with firmware supporting extended zones.
+    pub fn set_extended_zones(
+        &self,
+        index: u16,
+        colours: &[Hsbk],
+        duration: u32,
+    ) -> Result<(), io::Error> {
+        let payload = messages::set_extended_colour_zones_payload(
+            duration,
+            ZoneApply::Apply,
+            index,
+            &self.clamp_all(colours),
+        );
+        self.set(510, payload)
+    }
+
+    /// The size of each tile in a matrix light's chain.
+    pub fn tiles(&self) -> Result<Vec<TileInfo>, io::Error> {
+        match self.get(701)?.payload {
+            Payload::StateDeviceChain(p) => Ok(p.tiles),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// The colour of each pixel of a tile, row by row.
+    pub fn tile_colours(&self, tile_index: u8, width: u8) -> Result<Vec<Hsbk>, io::Error> {
+        match self
+            .get_with(707, messages::get_64_payload(tile_index, width))?
+            .payload
+        {
+            Payload::State64(p) => Ok(p.colours),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// Sets the colour of each pixel of a tile, row by row, over the duration in
+    /// milliseconds.
+    pub fn set_tile_colours(
+        &self,
+        tile_index: u8,
+        width: u8,
+        colours: &[Hsbk],
+        duration: u32,
+    ) -> Result<(), io::Error> {
+        self.set(
+            715,
+            messages::set_64_payload(tile_index, width, duration, &self.clamp_all(colours)),
+        )
+    }
+
+    /// The effect a multizone light's firmware is running, if any.
+    pub fn multizone_effect(&self) -> Result<MultiZoneEffectSettings, io::Error> {
+        match self.get(507)?.payload {
+            Payload::StateMultiZoneEffect(p) => Ok(p),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// Starts, or with `MultiZoneEffect::Off` stops, an effect run by a multizone
+    /// light's firmware, which needs no further messages.
+    pub fn set_multizone_effect(
+        &self,
+        settings: &MultiZoneEffectSettings,
+    ) -> Result<(), io::Error> {
+        self.set(508, messages::set_multizone_effect_payload(settings))
+    }
+
+    /// The effect a matrix light's firmware is running, if any.
+    pub fn tile_effect(&self) -> Result<TileEffectSettings, io::Error> {
+        match self
+            .get_with(718, messages::get_tile_effect_payload())?
+            .payload
+        {
+            Payload::StateTileEffect(p) => Ok(p),
+            p => Err(unexpected(p)),
+        }
+    }
+
+    /// Starts, or with `TileEffect::Off` stops, an effect run by a matrix light's
+    /// firmware, which needs no further messages.
+    pub fn set_tile_effect(&self, settings: &TileEffectSettings) -> Result<(), io::Error> {
+        self.set(719, messages::set_tile_effect_payload(settings))
+    }
+
+    fn get_with(
+        &self,
+        message_type: u16,
+        payload: request::Payload,
+    ) -> Result<Response, io::Error> {
+        self.client
+            .get_with(self.socket_addr, self.target, message_type, payload)
+    }
+
+    fn get(&self, message_type: u16) -> Result<Response, io::Error> {
+        self.client.get(self.socket_addr, self.target, message_type)
+    }
+
+    pub(crate) fn clamp_all(&self, colours: &[Hsbk]) -> Vec<Hsbk> {
+        colours.iter().map(|c| self.clamp(c)).collect()
+    }
+
+    fn set(&self, message_type: u16, payload: request::Payload) -> Result<(), io::Error> {
+        self.client
+            .set(self.socket_addr, self.target, message_type, payload)
+            .map(|_| ())
+    }
+}
+
+// Groups consecutive zones of the same colour, as (start, end, colour).
+fn zone_runs(colours: &[Hsbk]) -> Vec<(u8, u8, Hsbk)> {
+    let mut runs: Vec<(u8, u8, Hsbk)> = vec![];
+    for (i, c) in colours.iter().enumerate().take(256) {
+        match runs.last_mut() {
+            Some(run) if run.2 == *c => run.1 = i as u8,
+            _ => runs.push((i as u8, i as u8, *c)),
+        }
+    }
+    runs
+}
+
+fn unexpected(payload: Payload) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected reply: {:?}", payload),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zone_runs, Light};
+    use client::Client;
+    use colour::Hsbk;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_label() {
+        // A stand-in device, answering one GetLabel with StateLabel.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let mut buf = [0; 1024];
+            let (_, from) = device.recv_from(&mut buf).unwrap();
+            let mut reply = buf[0..36].to_vec();
+            reply[0] = 68;
+            reply[32] = 25;
+            let mut label = b"Desk".to_vec();
+            label.resize(32, 0);
+            reply.extend_from_slice(&label);
+            device.send_to(&reply, from).unwrap();
+            buf[32]
+        });
+
+        let light = Light::new(Arc::new(Client::new().unwrap()), device_addr, [0; 8]);
+        assert_eq!(light.label().unwrap(), "Desk");
+        assert_eq!(responder.join().unwrap(), 23);
+    }
+
+    #[test]
+    fn test_zone_runs() {
+        let red = Hsbk {
+            hue: 0,
+            saturation: 65535,
+            brightness: 65535,
+            kelvin: 3500,
+        };
+        let blue = Hsbk { hue: 43690, ..red };
+
+        let runs = zone_runs(&[red, red, blue, red]);
+        assert_eq!(runs, vec![(0, 1, red), (2, 2, blue), (3, 3, red)]);
+    }
+}
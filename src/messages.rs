@@ -1,7 +1,8 @@
 use std::io;
 use std::net::Ipv4Addr;
 
-use colour;
+use colour::{self, Hsbk, Kelvin};
+use effect::{self, MultiZoneEffect, MultiZoneEffectSettings, TileEffectSettings, MAX_PALETTE};
 use network;
 use request::Frame;
 use request::FrameAddress;
@@ -10,6 +11,8 @@ use request::Payload;
 use request::ProtocolHeader;
 use request::Request;
 use request::RequestBin;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Finds devices on the network.
 pub fn get_service(subnet: Ipv4Addr) -> Result<network::Device, io::Error> {
@@ -24,16 +27,10 @@ pub fn get_service(subnet: Ipv4Addr) -> Result<network::Device, io::Error> {
 
     let msg_bin = RequestBin::from(msg);
 
-    match network::Network::send_discover_devices(msg_bin, subnet) {
-        Ok(r) => {
-            network::print_debug("good send");
-            Ok(r)
-        }
-        Err(e) => {
-            network::print_debug(&format!("bad send: {}", e));
-            Err(e)
-        }
-    }
+    network::Network::send_discover_devices(msg_bin, subnet).map_err(|e| {
+        warn!("GetService failed: {}", e);
+        e
+    })
 }
 
 /// Gets the power state of the specified device.
@@ -49,16 +46,10 @@ pub fn get_device_power_state(device: &network::Device) -> Result<network::Devic
 
     let msg_bin = RequestBin::from(msg);
 
-    match device.send_get_device_power_state(msg_bin) {
-        Ok(r) => {
-            network::print_debug("good send");
-            Ok(r)
-        }
-        Err(e) => {
-            network::print_debug(&format!("bad send: {}", e));
-            Err(e)
-        }
-    }
+    device.send_get_device_power_state(msg_bin).map_err(|e| {
+        warn!("GetPower failed: {}", e);
+        e
+    })
 }
 
 pub fn set_device_on(device: &network::Device) -> Result<network::Device, io::Error> {
@@ -70,6 +61,7 @@ pub fn set_device_off(device: &network::Device) -> Result<network::Device, io::E
 }
 
 /// Sets the power state of the specified device.
+#[allow(clippy::mixed_attributes_style)]
 fn set_device_power_state(
     device: &network::Device,
     power_level: u16,
@@ -79,10 +71,10 @@ fn set_device_power_state(
     //! vec![0x00, 0x00];
     //! ```
 
-    let reserved = vec![0x00];
+    let reserved = [0x00];
     let p = RequestBin::u16_to_u8_array(power_level).to_vec();
 
-    let payload_bytes = vec![&reserved[..], &p[..]].concat();
+    let payload_bytes = [&reserved[..], &p[..]].concat();
 
     let msg = Request::new(
         Header::new(
@@ -95,16 +87,10 @@ fn set_device_power_state(
 
     let msg_bin = RequestBin::from(msg);
 
-    match device.send_set_device_power_state(msg_bin) {
-        Ok(r) => {
-            network::print_debug("good send");
-            Ok(r)
-        }
-        Err(e) => {
-            network::print_debug(&format!("bad send: {}", e));
-            Err(e)
-        }
-    }
+    device.send_set_device_power_state(msg_bin).map_err(|e| {
+        warn!("SetPower failed: {}", e);
+        e
+    })
 }
 
 /// Gets the state of the specified device.
@@ -120,19 +106,15 @@ pub fn get_device_state(device: &network::Device) -> Result<network::Device, io:
 
     let msg_bin = RequestBin::from(msg);
 
-    match device.send_get_device_state(msg_bin) {
-        Ok(r) => {
-            network::print_debug("good send");
-            Ok(r)
-        }
-        Err(e) => {
-            network::print_debug(&format!("bad send: {}", e));
-            Err(e)
-        }
-    }
+    device.send_get_device_state(msg_bin).map_err(|e| {
+        warn!("Get failed: {}", e);
+        e
+    })
 }
 
-/// Sets the state of the specified device.
+/// Sets the state of the specified device. Kelvin is clamped to the range LIFX
+/// lights accept, see `colour::Kelvin`.
+#[allow(clippy::mixed_attributes_style)]
 pub fn set_device_state(
     device: &network::Device,
     hsb: &colour::Hsb,
@@ -144,14 +126,33 @@ pub fn set_device_state(
     //! vec![0x00, 0xF7, 0x77, 0xFF, 0x0F, 0x4F, 0xFF, 0xA0, 0xAA, 0x00, 0x00, 0x03, 0xe8];
     //! ```
 
-    let reserved = vec![0x00];
-    let hue = colour::hue_degrees_to_word(hsb.hue).to_vec();
-    let sat = colour::saturation_percent_to_word(hsb.saturation).to_vec();
-    let bri = colour::brightness_percent_to_word(hsb.brightness).to_vec();
-    let kel = RequestBin::u16_to_u8_array(kelvin).to_vec();
+    let msg = Request::new(
+        Header::new(
+            Frame::new(0, false, true, 1024, 321),
+            FrameAddress::new([0; 8], [0; 6], 0, true, false, 156),
+            ProtocolHeader::new(0, 102, 0),
+        ),
+        set_colour_payload(&Hsbk::from_hsb(hsb, Kelvin::new(kelvin).value()), duration),
+    );
+
+    let msg_bin = RequestBin::from(msg);
+
+    device.send_set_device_state(msg_bin).map_err(|e| {
+        warn!("SetColor failed: {}", e);
+        e
+    })
+}
+
+/// Builds the payload of SetColor (102).
+pub fn set_colour_payload(hsbk: &Hsbk, duration: u32) -> Payload {
+    let reserved = [0x00];
+    let hue = RequestBin::u16_to_u8_array(hsbk.hue).to_vec();
+    let sat = RequestBin::u16_to_u8_array(hsbk.saturation).to_vec();
+    let bri = RequestBin::u16_to_u8_array(hsbk.brightness).to_vec();
+    let kel = RequestBin::u16_to_u8_array(hsbk.kelvin).to_vec();
     let dur = RequestBin::u32_to_u8_array(duration).to_vec();
 
-    let payload_bytes = vec![
+    let payload_bytes = [
         &reserved[..],
         &hue[..],
         &sat[..],
@@ -161,25 +162,183 @@ pub fn set_device_state(
     ]
     .concat();
 
-    let msg = Request::new(
-        Header::new(
-            Frame::new(0, false, true, 1024, 321),
-            FrameAddress::new([0; 8], [0; 6], 0, true, false, 156),
-            ProtocolHeader::new(0, 102, 0),
-        ),
-        Payload(payload_bytes),
-    );
+    Payload(payload_bytes)
+}
 
-    let msg_bin = RequestBin::from(msg);
+/// Builds the payload of the light's SetPower (117), which unlike the device's
+/// SetPower (21) takes a transition duration.
+pub fn set_light_power_payload(level: u16, duration: u32) -> Payload {
+    Payload([&wire_u16(level)[..], &wire_u32(duration)[..]].concat())
+}
 
-    match device.send_set_device_state(msg_bin) {
-        Ok(r) => {
-            network::print_debug("good send");
-            Ok(r)
-        }
-        Err(e) => {
-            network::print_debug(&format!("bad send: {}", e));
-            Err(e)
-        }
+/// Builds the payload of SetLabel (24), truncating the label to 32 bytes.
+pub fn set_label_payload(label: &str) -> Payload {
+    let mut end = label.len().min(32);
+    while !label.is_char_boundary(end) {
+        end -= 1;
     }
+
+    let mut payload_bytes = label.as_bytes()[..end].to_vec();
+    payload_bytes.resize(32, 0);
+
+    Payload(payload_bytes)
+}
+
+/// Whether a zone change is applied immediately, or held until a later change
+/// that is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ZoneApply {
+    NoApply = 0,
+    Apply = 1,
+    ApplyOnly = 2,
+}
+
+/// Builds the payload of GetColorZones (502), for zones start to end inclusive.
+pub fn get_colour_zones_payload(start: u8, end: u8) -> Payload {
+    Payload(vec![start, end])
+}
+
+/// Builds the payload of SetColorZones (501), for zones start to end inclusive.
+pub fn set_colour_zones_payload(
+    start: u8,
+    end: u8,
+    hsbk: &Hsbk,
+    duration: u32,
+    apply: ZoneApply,
+) -> Payload {
+    let payload_bytes = [
+        &[start, end][..],
+        &wire_hsbk(hsbk)[..],
+        &wire_u32(duration)[..],
+        &[apply as u8][..],
+    ]
+    .concat();
+
+    Payload(payload_bytes)
+}
+
+/// Builds the payload of SetExtendedColorZones (510), for up to 82 zones from index.
+pub fn set_extended_colour_zones_payload(
+    duration: u32,
+    apply: ZoneApply,
+    index: u16,
+    colours: &[Hsbk],
+) -> Payload {
+    let count = colours.len().min(82);
+    let mut payload_bytes = [
+        &wire_u32(duration)[..],
+        &[apply as u8][..],
+        &wire_u16(index)[..],
+        &[count as u8][..],
+    ]
+    .concat();
+    for i in 0..82 {
+        let c = colours.get(i).cloned().unwrap_or_default();
+        payload_bytes.extend_from_slice(&wire_hsbk(&c));
+    }
+
+    Payload(payload_bytes)
+}
+
+/// Builds the payload of Get64 (707), for the whole of the tile at index.
+pub fn get_64_payload(tile_index: u8, width: u8) -> Payload {
+    Payload(vec![tile_index, 1, 0, 0, 0, width])
+}
+
+/// Builds the payload of Set64 (715), for the whole of the tile at index.
+pub fn set_64_payload(tile_index: u8, width: u8, duration: u32, colours: &[Hsbk]) -> Payload {
+    let mut payload_bytes = [
+        &[tile_index, 1, 0, 0, 0, width][..],
+        &wire_u32(duration)[..],
+    ]
+    .concat();
+    for i in 0..64 {
+        let c = colours.get(i).cloned().unwrap_or_default();
+        payload_bytes.extend_from_slice(&wire_hsbk(&c));
+    }
+
+    Payload(payload_bytes)
+}
+
+/// Builds the payload of SetMultiZoneEffect (508).
+pub fn set_multizone_effect_payload(settings: &MultiZoneEffectSettings) -> Payload {
+    // The direction of MOVE is the second of eight u32 parameters.
+    let mut parameters = [0u8; 32];
+    if let MultiZoneEffect::Move(direction) = settings.effect {
+        parameters[4..8].copy_from_slice(&wire_u32(direction as u32));
+    }
+
+    Payload(
+        [
+            &wire_u32(settings.instance_id)[..],
+            &[settings.effect.code(), 0, 0][..],
+            &wire_u32(settings.speed.as_millis() as u32)[..],
+            &wire_u64(effect::duration_to_nanos(settings.duration))[..],
+            &[0; 8][..],
+            &parameters[..],
+        ]
+        .concat(),
+    )
+}
+
+/// Builds the payload of GetTileEffect (718).
+pub fn get_tile_effect_payload() -> Payload {
+    Payload(vec![0, 0])
+}
+
+/// Builds the payload of SetTileEffect (719), with up to 16 palette colours.
+pub fn set_tile_effect_payload(settings: &TileEffectSettings) -> Payload {
+    let count = settings.palette.len().min(MAX_PALETTE);
+    let mut payload_bytes = [
+        &[0, 0][..],
+        &wire_u32(settings.instance_id)[..],
+        &[settings.effect.code()][..],
+        &wire_u32(settings.speed.as_millis() as u32)[..],
+        &wire_u64(effect::duration_to_nanos(settings.duration))[..],
+        &[0; 8][..],
+        &[0; 32][..],
+        &[count as u8][..],
+    ]
+    .concat();
+    for i in 0..MAX_PALETTE {
+        let c = settings.palette.get(i).cloned().unwrap_or_default();
+        payload_bytes.extend_from_slice(&wire_hsbk(&c));
+    }
+
+    Payload(payload_bytes)
+}
+
+// Payloads other than SetColor (102) and SetPower (21) are built in wire order,
+// which is little endian.
+fn wire_u16(x: u16) -> [u8; 2] {
+    let mut b = RequestBin::u16_to_u8_array(x);
+    b.reverse();
+    b
+}
+
+fn wire_u32(x: u32) -> [u8; 4] {
+    let mut b = RequestBin::u32_to_u8_array(x);
+    b.reverse();
+    b
+}
+
+fn wire_u64(x: u64) -> [u8; 8] {
+    let mut b = RequestBin::u64_to_u8_array(x);
+    b.reverse();
+    b
+}
+
+fn wire_hsbk(hsbk: &Hsbk) -> Vec<u8> {
+    [
+        &wire_u16(hsbk.hue)[..],
+        &wire_u16(hsbk.saturation)[..],
+        &wire_u16(hsbk.brightness)[..],
+        &wire_u16(hsbk.kelvin)[..],
+    ]
+    .concat()
 }
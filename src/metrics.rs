@@ -0,0 +1,377 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use client::RequestStats;
+use colour::Hsbk;
+use convert;
+use http::{self, HttpRequest};
+use light::Light;
+use registry::{DeviceRegistry, RegisteredDevice};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// What is known of a device, as of its latest poll.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceMetrics {
+    pub mac_address: String,
+    pub label: String,
+    pub group: String,
+    pub product: String,
+    /// Whether the device answered its latest poll.
+    pub up: bool,
+    pub power: Option<bool>,
+    pub hsbk: Option<Hsbk>,
+    /// In dBm.
+    pub wifi_signal: Option<f64>,
+    pub uptime: Option<Duration>,
+    pub stats: RequestStats,
+}
+
+/// Polls every device in a registry, and renders what it finds in the
+/// Prometheus text format:
+///
+/// ```text
+/// lifx_up{mac="d0:73:d5:01:02:03",label="Desk",group="Office",product="LIFX A19"} 1
+/// lifx_power{...} 1
+/// lifx_hue_degrees{...} 120
+/// lifx_wifi_signal_dbm{...} -52
+/// lifx_request_retries_total{...} 3
+/// ```
+///
+/// Power and colour come from the registry, which should be refreshing itself,
+/// see `DeviceRegistry::start`. Wi-Fi signal and uptime are polled by the
+/// exporter, and request counts come from the registry's client.
+pub struct Exporter {
+    registry: Arc<DeviceRegistry>,
+    metrics: Arc<Mutex<Vec<DeviceMetrics>>>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Exporter {
+    pub fn new(registry: Arc<DeviceRegistry>) -> Exporter {
+        Exporter {
+            registry,
+            metrics: Arc::new(Mutex::new(vec![])),
+            running: Arc::new(AtomicBool::new(false)),
+            worker: None,
+        }
+    }
+
+    /// Polls every known device, replacing the metrics from the last poll.
+    pub fn poll(&self) {
+        *self.metrics.lock().unwrap() = poll(&self.registry);
+    }
+
+    /// Starts polling on a background thread, every interval.
+    pub fn start(&mut self, interval: Duration) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let registry = self.registry.clone();
+        let metrics = self.metrics.clone();
+        let running = self.running.clone();
+
+        self.worker = Some(thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                *metrics.lock().unwrap() = poll(&registry);
+
+                // Sleep in short steps, so that stopping is not held up.
+                let next = Instant::now() + interval;
+                while running.load(Ordering::SeqCst) && Instant::now() < next {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }));
+    }
+
+    /// Stops the background polling, waiting for any poll in progress.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    /// The metrics from the latest poll.
+    pub fn metrics(&self) -> Vec<DeviceMetrics> {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// The metrics from the latest poll, in the Prometheus text format.
+    pub fn render(&self) -> String {
+        render(&self.metrics())
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Serves the exporter's metrics at GET /metrics, handling each connection on
+/// its own thread. Connections that fail to be accepted are logged and skipped.
+pub fn serve(exporter: Arc<Exporter>, listener: TcpListener) -> Result<(), io::Error> {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                // Such as running out of file descriptors, which a moment may ease.
+                warn!("accepting a connection failed: {}", e);
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+        };
+        let exporter = exporter.clone();
+        thread::spawn(move || {
+            let _ = handle_connection(&exporter, stream);
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(exporter: &Exporter, mut stream: TcpStream) -> Result<(), io::Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let request = HttpRequest::read(&mut BufReader::new(stream.try_clone()?));
+    let (status, body) = match request {
+        Ok(ref r) if r.path != "/metrics" => (404, "not found\n".to_string()),
+        Ok(ref r) if r.method != "GET" => (405, "method not allowed\n".to_string()),
+        Ok(_) => (200, exporter.render()),
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => (400, format!("{}\n", e)),
+        Err(e) => return Err(e),
+    };
+    http::write_response(
+        &mut stream,
+        status,
+        "text/plain; version=0.0.4; charset=utf-8",
+        &body,
+    )
+}
+
+// Polls every known device in parallel, so one slow device doesn't stall the
+// rest. Offline devices aren't asked anything, but are still reported.
+fn poll(registry: &DeviceRegistry) -> Vec<DeviceMetrics> {
+    let client = registry.client();
+    let devices = registry.devices();
+    thread::scope(|s| {
+        let handles: Vec<_> = devices
+            .iter()
+            .map(|d| s.spawn(move || (d.target, poll_device(registry, d))))
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().ok())
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .map(|(target, mut m)| {
+        // Read last, so that the counts include this poll's own requests.
+        m.stats = client.stats(target);
+        m
+    })
+    .collect()
+}
+
+fn poll_device(registry: &DeviceRegistry, d: &RegisteredDevice) -> DeviceMetrics {
+    let mut metrics = DeviceMetrics {
+        mac_address: convert::target_to_mac(d.target)[..17].to_lowercase(),
+        label: d.label.clone().unwrap_or_default(),
+        group: d.group.clone().unwrap_or_default(),
+        product: d.product_info().map_or("", |p| p.name).to_string(),
+        up: d.online,
+        power: d.state.as_ref().map(|s| s.power != 0),
+        hsbk: d.state.as_ref().map(|s| s.hsbk),
+        wifi_signal: None,
+        uptime: None,
+        stats: RequestStats::default(),
+    };
+    if d.online {
+        let light = Light::from_registered(registry.client().clone(), d);
+        metrics.wifi_signal = light.wifi_signal().ok();
+        metrics.uptime = light.uptime().ok();
+        metrics.up = metrics.wifi_signal.is_some() || metrics.uptime.is_some();
+    }
+    metrics
+}
+
+type Sample = fn(&DeviceMetrics) -> Option<f64>;
+
+// Each metric's name, type, help, and how it's read from a device's metrics.
+const METRICS: [(&str, &str, &str, Sample); 15] = [
+    (
+        "lifx_up",
+        "gauge",
+        "Whether the device answered its latest poll.",
+        |m| Some(if m.up { 1.0 } else { 0.0 }),
+    ),
+    ("lifx_power", "gauge", "Whether the light is on.", |m| {
+        m.power.map(|p| if p { 1.0 } else { 0.0 })
+    }),
+    (
+        "lifx_hue_degrees",
+        "gauge",
+        "The light's hue, from 0 to 360.",
+        |m| m.hsbk.map(|c| c.hue_degrees()),
+    ),
+    (
+        "lifx_saturation_ratio",
+        "gauge",
+        "The light's saturation, from 0 to 1.",
+        |m| m.hsbk.map(|c| f64::from(c.saturation) / 65535.0),
+    ),
+    (
+        "lifx_brightness_ratio",
+        "gauge",
+        "The light's brightness, from 0 to 1.",
+        |m| m.hsbk.map(|c| f64::from(c.brightness) / 65535.0),
+    ),
+    (
+        "lifx_kelvin",
+        "gauge",
+        "The light's colour temperature.",
+        |m| m.hsbk.map(|c| f64::from(c.kelvin)),
+    ),
+    (
+        "lifx_wifi_signal_dbm",
+        "gauge",
+        "The Wi-Fi signal strength the device receives.",
+        |m| m.wifi_signal,
+    ),
+    (
+        "lifx_uptime_seconds",
+        "gauge",
+        "How long the device has been running.",
+        |m| m.uptime.map(|u| u.as_secs_f64()),
+    ),
+    (
+        "lifx_last_request_latency_seconds",
+        "gauge",
+        "The round trip time of the latest answered request.",
+        |m| m.stats.last_latency.map(|l| l.as_secs_f64()),
+    ),
+    (
+        "lifx_request_latency_seconds_sum",
+        "counter",
+        "The round trip times of every answered request, summed.",
+        |m| Some(m.stats.latency.as_secs_f64()),
+    ),
+    (
+        "lifx_request_latency_seconds_count",
+        "counter",
+        "The answered requests whose round trip times are summed.",
+        |m| Some(m.stats.replies as f64),
+    ),
+    ("lifx_replies_total", "counter", "Requests answered.", |m| {
+        Some(m.stats.replies as f64)
+    }),
+    ("lifx_requests_total", "counter", "Requests made.", |m| {
+        Some(m.stats.requests as f64)
+    }),
+    (
+        "lifx_request_retries_total",
+        "counter",
+        "Requests resent after going unanswered.",
+        |m| Some(m.stats.retries as f64),
+    ),
+    (
+        "lifx_request_timeouts_total",
+        "counter",
+        "Requests unanswered after every attempt.",
+        |m| Some(m.stats.timeouts as f64),
+    ),
+];
+
+/// Renders metrics in the Prometheus text format.
+pub fn render(metrics: &[DeviceMetrics]) -> String {
+    let mut out = String::new();
+    for &(name, kind, help, sample) in METRICS.iter() {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for m in metrics {
+            if let Some(value) = sample(m) {
+                let _ = writeln!(
+                    out,
+                    "{}{{mac=\"{}\",label=\"{}\",group=\"{}\",product=\"{}\"}} {}",
+                    name,
+                    escape(&m.mac_address),
+                    escape(&m.label),
+                    escape(&m.group),
+                    escape(&m.product),
+                    value
+                );
+            }
+        }
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, DeviceMetrics};
+    use client::RequestStats;
+    use colour::Hsbk;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let metrics = DeviceMetrics {
+            mac_address: "d0:73:d5:01:02:03".to_string(),
+            label: "Desk \"lamp\"".to_string(),
+            group: "Office".to_string(),
+            product: "LIFX A19".to_string(),
+            up: true,
+            power: Some(true),
+            hsbk: Some(Hsbk::from_degrees(0.0, 100.0, 50.0, 3500)),
+            wifi_signal: Some(-52.0),
+            uptime: None,
+            stats: RequestStats {
+                requests: 4,
+                replies: 3,
+                retries: 2,
+                timeouts: 1,
+                latency: Duration::from_millis(150),
+                last_latency: Some(Duration::from_millis(40)),
+            },
+        };
+
+        let text = render(&[metrics]);
+        let labels =
+            r#"{mac="d0:73:d5:01:02:03",label="Desk \"lamp\"",group="Office",product="LIFX A19"}"#;
+        for line in &[
+            "# TYPE lifx_power gauge".to_string(),
+            format!("lifx_power{} 1", labels),
+            format!("lifx_hue_degrees{} 0", labels),
+            format!("lifx_saturation_ratio{} 1", labels),
+            format!("lifx_kelvin{} 3500", labels),
+            format!("lifx_wifi_signal_dbm{} -52", labels),
+            format!("lifx_last_request_latency_seconds{} 0.04", labels),
+            format!("lifx_request_latency_seconds_sum{} 0.15", labels),
+            format!("lifx_request_latency_seconds_count{} 3", labels),
+            format!("lifx_request_retries_total{} 2", labels),
+            format!("lifx_request_timeouts_total{} 1", labels),
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {}\n{}",
+                line,
+                text
+            );
+        }
+        // Metrics the device hasn't reported are left out, rather than zero.
+        assert!(!text.contains("lifx_uptime_seconds{"));
+    }
+}
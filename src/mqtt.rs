@@ -0,0 +1,821 @@
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use colour::Hsbk;
+use convert;
+use json::Json;
+use light::Light;
+use registry::{DeviceRegistry, RegisteredDevice};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A packet received from the broker. Only the packets a bridge cares about
+/// are told apart.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub(crate) enum Packet {
+    ConnAck { return_code: u8 },
+    Publish { topic: String, payload: Vec<u8> },
+    SubAck,
+    PingResp,
+    Other(u8),
+}
+
+/// A minimal MQTT 3.1.1 client, publishing and subscribing at QoS 0.
+pub(crate) struct MqttClient {
+    stream: TcpStream,
+    keep_alive: Duration,
+    last_write: Instant,
+    packet_id: u16,
+}
+
+/// The credentials and last will of a connection.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConnectOptions {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// A retained message the broker publishes if the connection drops, as
+    /// (topic, message).
+    pub will: Option<(String, String)>,
+}
+
+impl MqttClient {
+    /// Connects to the broker at addr, as host:port, failing if it refuses.
+    pub fn connect(
+        addr: &str,
+        client_id: &str,
+        keep_alive: Duration,
+        options: &ConnectOptions,
+    ) -> Result<MqttClient, io::Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(read_timeout(keep_alive))?;
+        let mut client = MqttClient {
+            stream,
+            keep_alive,
+            last_write: Instant::now(),
+            packet_id: 0,
+        };
+
+        client.write(&connect_packet(client_id, keep_alive, options))?;
+        match client.read_packet()? {
+            Packet::ConnAck { return_code: 0 } => Ok(client),
+            Packet::ConnAck { return_code } => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused connection, code {}", return_code),
+            )),
+            p => Err(invalid(&format!("expected CONNACK, got {:?}", p))),
+        }
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), io::Error> {
+        self.write(&publish_packet(topic, payload, retain))
+    }
+
+    /// Subscribes to the topic filters, without waiting for the broker's SUBACK.
+    pub fn subscribe(&mut self, filters: &[String]) -> Result<(), io::Error> {
+        self.packet_id = self.packet_id.wrapping_add(1).max(1);
+        let packet = subscribe_packet(self.packet_id, filters);
+        self.write(&packet)
+    }
+
+    /// Waits up to timeout for a packet. None if none arrived.
+    pub fn poll(&mut self, timeout: Duration) -> Result<Option<Packet>, io::Error> {
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut first = [0u8; 1];
+        match self.stream.read(&mut first) {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "broker closed the connection",
+            )),
+            Ok(_) => {
+                // The rest of the packet follows promptly, once it has begun.
+                self.stream
+                    .set_read_timeout(read_timeout(self.keep_alive))?;
+                read_packet_body(&mut self.stream, first[0]).map(Some)
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pings the broker if nothing has been sent for half the keep alive period.
+    /// A keep alive of zero turns keep alive off.
+    pub fn keep_alive(&mut self) -> Result<(), io::Error> {
+        if self.keep_alive > Duration::ZERO && self.last_write.elapsed() >= self.keep_alive / 2 {
+            self.write(&[0xC0, 0])?;
+        }
+        Ok(())
+    }
+
+    pub fn disconnect(mut self) -> Result<(), io::Error> {
+        self.write(&[0xE0, 0])
+    }
+
+    fn read_packet(&mut self) -> Result<Packet, io::Error> {
+        let mut first = [0u8; 1];
+        self.stream.read_exact(&mut first)?;
+        read_packet_body(&mut self.stream, first[0])
+    }
+
+    fn write(&mut self, packet: &[u8]) -> Result<(), io::Error> {
+        self.stream.write_all(packet)?;
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+// How long to wait on the rest of a packet: the keep alive period, or without
+// limit when keep alive is off, as a zero timeout is an error.
+fn read_timeout(keep_alive: Duration) -> Option<Duration> {
+    if keep_alive == Duration::ZERO {
+        None
+    } else {
+        Some(keep_alive)
+    }
+}
+
+fn connect_packet(client_id: &str, keep_alive: Duration, options: &ConnectOptions) -> Vec<u8> {
+    let mut flags = 0x02; // Clean session.
+    let mut payload = string(client_id);
+    if let Some((ref topic, ref message)) = options.will {
+        flags |= 0x04 | 0x20; // Will, retained, at QoS 0.
+        payload.extend(string(topic));
+        payload.extend(string(message));
+    }
+    if let Some(ref username) = options.username {
+        flags |= 0x80;
+        payload.extend(string(username));
+    }
+    if let Some(ref password) = options.password {
+        flags |= 0x40;
+        payload.extend(string(password));
+    }
+
+    let keep_alive = keep_alive.as_secs().min(u64::from(u16::MAX)) as u16;
+    let mut body = string("MQTT");
+    body.extend_from_slice(&[4, flags]);
+    body.extend_from_slice(&keep_alive.to_be_bytes());
+    body.extend(payload);
+    packet(0x10, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = string(topic);
+    body.extend_from_slice(payload);
+    packet(if retain { 0x31 } else { 0x30 }, &body)
+}
+
+fn subscribe_packet(packet_id: u16, filters: &[String]) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    for f in filters {
+        body.extend(string(f));
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    // The remaining length, seven bits at a time, least significant first.
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn string(s: &str) -> Vec<u8> {
+    let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+    let mut v = (bytes.len() as u16).to_be_bytes().to_vec();
+    v.extend_from_slice(bytes);
+    v
+}
+
+fn read_packet_body<R: Read>(reader: &mut R, header: u8) -> Result<Packet, io::Error> {
+    let mut len = 0usize;
+    for shift in 0..4 {
+        let mut byte = [0u8; 1];
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7F) as usize) << (7 * shift);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        if shift == 3 {
+            return Err(invalid("remaining length too long"));
+        }
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body)?;
+
+    match header >> 4 {
+        2 if len >= 2 => Ok(Packet::ConnAck {
+            return_code: body[1],
+        }),
+        3 => {
+            if body.len() < 2 {
+                return Err(invalid("PUBLISH too short"));
+            }
+            let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+            // QoS 1 and 2 messages carry a packet id after the topic.
+            let id_len = if header & 0x06 != 0 { 2 } else { 0 };
+            if body.len() < 2 + topic_len + id_len {
+                return Err(invalid("PUBLISH too short"));
+            }
+            Ok(Packet::Publish {
+                topic: String::from_utf8_lossy(&body[2..2 + topic_len]).into_owned(),
+                payload: body[2 + topic_len + id_len..].to_vec(),
+            })
+        }
+        9 => Ok(Packet::SubAck),
+        13 => Ok(Packet::PingResp),
+        t => Ok(Packet::Other(t)),
+    }
+}
+
+/// Where the bridge connects, and the topics it uses.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BridgeConfig {
+    /// The broker, as host:port.
+    pub broker: String,
+    pub client_id: String,
+    pub connect: ConnectOptions,
+    /// Device topics are `<prefix>/<id>/state`, `<prefix>/<id>/set` and so on.
+    pub topic_prefix: String,
+    /// Home Assistant's discovery prefix, or None to not announce devices.
+    pub discovery_prefix: Option<String>,
+    /// How often every device's state is published.
+    pub state_interval: Duration,
+    pub keep_alive: Duration,
+}
+
+impl Default for BridgeConfig {
+    fn default() -> BridgeConfig {
+        BridgeConfig {
+            broker: "localhost:1883".to_string(),
+            client_id: "rustylifx".to_string(),
+            connect: ConnectOptions::default(),
+            topic_prefix: "lifx".to_string(),
+            discovery_prefix: Some("homeassistant".to_string()),
+            state_interval: Duration::from_secs(10),
+            keep_alive: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Bridges the devices in a registry to an MQTT broker.
+///
+/// Each device's state is published, retained, as JSON to `lifx/<id>/state`,
+/// in the shape Home Assistant's JSON light schema expects, and its
+/// availability to `lifx/<id>/availability`. Commands are taken from:
+///
+/// ```text
+/// lifx/<id>/set              {"state": "ON", "brightness": 128, "color": {"h": 120, "s": 100},
+///                             "color_temp": 2700, "transition": 1}
+/// lifx/<id>/set/power        on | off
+/// lifx/<id>/set/colour       any colour text, such as red or #ff8800
+/// lifx/<id>/set/brightness   0 to 100
+/// lifx/<id>/set/kelvin       1500 to 9000
+/// ```
+///
+/// Ids are MAC addresses in lower case without colons. Devices are announced
+/// to Home Assistant with retained discovery configs.
+pub struct MqttBridge {
+    registry: Arc<DeviceRegistry>,
+    config: BridgeConfig,
+}
+
+impl MqttBridge {
+    pub fn new(registry: Arc<DeviceRegistry>, config: BridgeConfig) -> MqttBridge {
+        MqttBridge { registry, config }
+    }
+
+    /// Connects and bridges until the connection fails. The registry should be
+    /// refreshing itself, see `DeviceRegistry::start`.
+    pub fn run(&self) -> Result<(), io::Error> {
+        let prefix = &self.config.topic_prefix;
+        let bridge_availability = format!("{}/bridge/availability", prefix);
+        let mut options = self.config.connect.clone();
+        options.will = Some((bridge_availability.clone(), "offline".to_string()));
+
+        let mut client = MqttClient::connect(
+            &self.config.broker,
+            &self.config.client_id,
+            self.config.keep_alive,
+            &options,
+        )?;
+        client.publish(&bridge_availability, b"online", true)?;
+        client.subscribe(&[format!("{}/+/set", prefix), format!("{}/+/set/+", prefix)])?;
+
+        let mut announced: HashSet<String> = HashSet::new();
+        let mut next_state = Instant::now();
+        loop {
+            if Instant::now() >= next_state {
+                for d in self.registry.devices() {
+                    if announced.insert(d.mac_address.clone()) {
+                        self.announce(&mut client, &d)?;
+                    }
+                    self.publish_state(
+                        &mut client,
+                        &d,
+                        d.state.as_ref().map(|s| (s.hsbk, s.power != 0)),
+                    )?;
+                }
+                next_state = Instant::now() + self.config.state_interval;
+            }
+
+            client.keep_alive()?;
+            let wait = next_state.saturating_duration_since(Instant::now());
+            if let Some(Packet::Publish { topic, payload }) =
+                client.poll(wait.min(Duration::from_secs(1)))?
+            {
+                self.command(&mut client, &topic, &String::from_utf8_lossy(&payload));
+            }
+        }
+    }
+
+    fn announce(&self, client: &mut MqttClient, d: &RegisteredDevice) -> Result<(), io::Error> {
+        let discovery_prefix = match self.config.discovery_prefix {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+        let topic = format!("{}/light/lifx_{}/config", discovery_prefix, id(d));
+        let config = discovery_config(&self.config.topic_prefix, d);
+        client.publish(&topic, config.to_string().as_bytes(), true)
+    }
+
+    fn publish_state(
+        &self,
+        client: &mut MqttClient,
+        d: &RegisteredDevice,
+        state: Option<(Hsbk, bool)>,
+    ) -> Result<(), io::Error> {
+        let base = format!("{}/{}", self.config.topic_prefix, id(d));
+        let availability = if d.online && state.is_some() {
+            "online"
+        } else {
+            "offline"
+        };
+        client.publish(
+            &format!("{}/availability", base),
+            availability.as_bytes(),
+            true,
+        )?;
+        if let Some((hsbk, power)) = state {
+            let state = state_json(&hsbk, power).to_string();
+            client.publish(&format!("{}/state", base), state.as_bytes(), true)?;
+        }
+        Ok(())
+    }
+
+    // Carries out a command, then publishes the device's new state. Commands that
+    // fail are logged and dropped, as there's no one to reply to.
+    fn command(&self, client: &mut MqttClient, topic: &str, payload: &str) {
+        let (device_id, field) = match command_topic(&self.config.topic_prefix, topic) {
+            Some(t) => t,
+            None => return,
+        };
+        let device = match self
+            .registry
+            .devices()
+            .into_iter()
+            .find(|d| id(d) == device_id)
+        {
+            Some(d) => d,
+            None => {
+                warn!("command for unknown device {} dropped", device_id);
+                return;
+            }
+        };
+
+        let light = Light::from_registered(self.registry.client().clone(), &device);
+        let current = match device.state {
+            Some(ref s) => s.hsbk,
+            None => match light.color() {
+                Ok(c) => c,
+                Err(e) => {
+                    warn!("command for {} dropped: {}", device_id, e);
+                    return;
+                }
+            },
+        };
+        let command = match parse_command(field, payload, current) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("bad command on {}: {}", topic, e);
+                return;
+            }
+        };
+
+        let applied = command
+            .colour
+            .map_or(Ok(()), |c| light.set_color(&c, command.duration))
+            .and_then(|_| {
+                command
+                    .power
+                    .map_or(Ok(()), |on| light.set_power(on, command.duration))
+            });
+        if let Err(e) = applied {
+            warn!("command for {} failed: {}", device_id, e);
+        } else if let Ok(s) = light.state() {
+            let _ = self.publish_state(client, &device, Some((s.hsbk, s.power)));
+        }
+    }
+}
+
+/// A change asked for on a command topic.
+#[derive(Debug, Clone, PartialEq)]
+struct Command {
+    power: Option<bool>,
+    colour: Option<Hsbk>,
+    duration: u32,
+}
+
+// Parses a command for the field, or the JSON command if None, as a change to
+// the current colour.
+// The device id and field of a command topic, prefix/<id>/set[/<field>]. The
+// prefix may have any number of levels.
+fn command_topic<'a>(prefix: &str, topic: &'a str) -> Option<(&'a str, Option<&'a str>)> {
+    let rest = topic
+        .strip_prefix(prefix.trim_end_matches('/'))?
+        .strip_prefix('/')?;
+    let parts: Vec<&str> = rest.split('/').collect();
+    match parts[..] {
+        [device_id, "set"] => Some((device_id, None)),
+        [device_id, "set", field] => Some((device_id, Some(field))),
+        _ => None,
+    }
+}
+
+fn parse_command(field: Option<&str>, payload: &str, current: Hsbk) -> Result<Command, String> {
+    let payload = payload.trim();
+    let mut command = Command {
+        power: None,
+        colour: None,
+        duration: 0,
+    };
+    let number = |s: &str, min: f64, max: f64| match s.parse::<f64>() {
+        Ok(n) if n >= min && n <= max => Ok(n),
+        _ => Err(format!(
+            "expected a number from {} to {}, got {:?}",
+            min, max, s
+        )),
+    };
+
+    match field {
+        Some("power") => {
+            command.power = match payload.to_lowercase().as_str() {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => return Err(format!("expected on or off, got {:?}", payload)),
+            }
+        }
+        Some("colour") | Some("color") => {
+            command.colour = Some(payload.parse::<Hsbk>().map_err(|e| e.to_string())?)
+        }
+        Some("brightness") => {
+            let b = number(payload, 0.0, 100.0)?;
+            command.colour = Some(Hsbk {
+                brightness: (b / 100.0 * 65535.0).round() as u16,
+                ..current
+            });
+        }
+        Some("kelvin") => {
+            let k = number(payload, 1500.0, 9000.0)?;
+            command.colour = Some(Hsbk {
+                saturation: 0,
+                kelvin: k as u16,
+                ..current
+            });
+        }
+        Some(f) => return Err(format!("unknown field {:?}", f)),
+        None => {
+            let json = Json::parse(payload).map_err(|e| e.to_string())?;
+            command.power = match json.get("state").and_then(Json::as_str) {
+                Some("ON") => Some(true),
+                Some("OFF") => Some(false),
+                Some(s) => return Err(format!("expected ON or OFF, got {:?}", s)),
+                None => None,
+            };
+
+            let mut colour = current;
+            let mut changed = false;
+            if let Some(c) = json.get("color") {
+                let h = c.get("h").and_then(Json::as_f64).ok_or("color needs h")?;
+                let s = c.get("s").and_then(Json::as_f64).ok_or("color needs s")?;
+                let hs = Hsbk::from_degrees(h, s, 0.0, 0);
+                colour.hue = hs.hue;
+                colour.saturation = hs.saturation;
+                changed = true;
+            }
+            if let Some(k) = json.get("color_temp").and_then(Json::as_f64) {
+                colour.saturation = 0;
+                colour.kelvin = number(&k.to_string(), 1500.0, 9000.0)? as u16;
+                changed = true;
+            }
+            if let Some(b) = json.get("brightness").and_then(Json::as_f64) {
+                colour.brightness =
+                    (number(&b.to_string(), 0.0, 255.0)? / 255.0 * 65535.0).round() as u16;
+                changed = true;
+            }
+            if changed {
+                command.colour = Some(colour);
+            }
+            if let Some(t) = json.get("transition").and_then(Json::as_f64) {
+                command.duration = (number(&t.to_string(), 0.0, 86400.0)? * 1000.0) as u32;
+            }
+        }
+    }
+
+    Ok(command)
+}
+
+// A Home Assistant MQTT discovery config, for a light with the JSON schema.
+fn discovery_config(prefix: &str, d: &RegisteredDevice) -> Json {
+    let base = format!("{}/{}", prefix, id(d));
+    let name = d.label.clone().unwrap_or_else(|| mac(d));
+    let product = d.product_info();
+    let (min_kelvin, max_kelvin) = product.map_or((1500, 9000), |p| (p.min_kelvin, p.max_kelvin));
+    let colour = product.is_none_or(|p| p.colour);
+    let modes = if colour {
+        vec!["hs", "color_temp"]
+    } else {
+        vec!["color_temp"]
+    };
+
+    Json::object(vec![
+        ("name", Json::Null),
+        ("unique_id", Json::from(format!("lifx_{}", id(d)))),
+        ("schema", Json::from("json")),
+        ("command_topic", Json::from(format!("{}/set", base))),
+        ("state_topic", Json::from(format!("{}/state", base))),
+        (
+            "availability",
+            Json::Array(vec![
+                Json::object(vec![(
+                    "topic",
+                    Json::from(format!("{}/bridge/availability", prefix)),
+                )]),
+                Json::object(vec![(
+                    "topic",
+                    Json::from(format!("{}/availability", base)),
+                )]),
+            ]),
+        ),
+        ("availability_mode", Json::from("all")),
+        ("brightness", Json::from(true)),
+        ("supported_color_modes", Json::from(modes)),
+        ("color_temp_kelvin", Json::from(true)),
+        ("min_kelvin", Json::from(min_kelvin)),
+        ("max_kelvin", Json::from(max_kelvin)),
+        (
+            "device",
+            Json::object(vec![
+                ("identifiers", Json::from(vec![format!("lifx_{}", id(d))])),
+                (
+                    "connections",
+                    Json::Array(vec![Json::from(vec!["mac".to_string(), mac(d)])]),
+                ),
+                ("name", Json::from(name)),
+                ("manufacturer", Json::from("LIFX")),
+                ("model", Json::from(product.map(|p| p.name))),
+            ]),
+        ),
+    ])
+}
+
+// A state, as Home Assistant's JSON light schema expects it.
+fn state_json(hsbk: &Hsbk, power: bool) -> Json {
+    let white = hsbk.saturation == 0;
+    Json::object(vec![
+        ("state", Json::from(if power { "ON" } else { "OFF" })),
+        (
+            "brightness",
+            Json::from((f64::from(hsbk.brightness) / 65535.0 * 255.0).round()),
+        ),
+        (
+            "color_mode",
+            Json::from(if white { "color_temp" } else { "hs" }),
+        ),
+        (
+            "color",
+            Json::object(vec![
+                ("h", Json::from(hsbk.hue_degrees())),
+                ("s", Json::from(hsbk.saturation_percent())),
+            ]),
+        ),
+        ("color_temp", Json::from(hsbk.kelvin)),
+    ])
+}
+
+// The MAC address in lower case without colons, as in an id: selector.
+fn id(d: &RegisteredDevice) -> String {
+    mac(d).replace(':', "")
+}
+
+fn mac(d: &RegisteredDevice) -> String {
+    convert::target_to_mac(d.target)[..17].to_lowercase()
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        command_topic, parse_command, publish_packet, read_packet_body, state_json,
+        subscribe_packet, Command, ConnectOptions, MqttClient, Packet,
+    };
+    use colour::Hsbk;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_packets() {
+        assert_eq!(
+            publish_packet("a/b", b"on", true),
+            vec![0x31, 7, 0, 3, b'a', b'/', b'b', b'o', b'n']
+        );
+        assert_eq!(
+            subscribe_packet(1, &["a/#".to_string()]),
+            vec![0x82, 8, 0, 1, 0, 3, b'a', b'/', b'#', 0]
+        );
+
+        // Remaining lengths over 127 take more than one byte.
+        let long = publish_packet("t", &[0; 200], false);
+        assert_eq!(&long[..3], &[0x30, 203 - 128 + 0x80, 1]);
+        let p = read_packet_body(&mut &long[1..], long[0]).unwrap();
+        assert_eq!(
+            p,
+            Packet::Publish {
+                topic: "t".to_string(),
+                payload: vec![0; 200]
+            }
+        );
+    }
+
+    #[test]
+    fn test_connect() {
+        // A stand-in broker, accepting the connection and echoing one publish.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+            stream
+                .write_all(&publish_packet("lifx/x/set/power", b"on", false))
+                .unwrap();
+            buf[..n].to_vec()
+        });
+
+        let options = ConnectOptions {
+            username: Some("user".to_string()),
+            password: Some("pass".to_string()),
+            will: Some((
+                "lifx/bridge/availability".to_string(),
+                "offline".to_string(),
+            )),
+        };
+        let mut client =
+            MqttClient::connect(&addr, "test", Duration::from_secs(5), &options).unwrap();
+        let packet = client.poll(Duration::from_secs(1)).unwrap();
+
+        let connect = broker.join().unwrap();
+        assert_eq!(connect[0], 0x10);
+        assert_eq!(&connect[2..9], &[0, 4, b'M', b'Q', b'T', b'T', 4]);
+        assert_eq!(connect[9], 0x80 | 0x40 | 0x20 | 0x04 | 0x02);
+        assert_eq!(
+            packet,
+            Some(Packet::Publish {
+                topic: "lifx/x/set/power".to_string(),
+                payload: b"on".to_vec()
+            })
+        );
+    }
+
+    #[test]
+    fn test_connect_without_keep_alive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).unwrap();
+            stream.write_all(&[0x20, 2, 0, 0]).unwrap();
+            buf[..n].to_vec()
+        });
+
+        let mut client =
+            MqttClient::connect(&addr, "test", Duration::ZERO, &ConnectOptions::default()).unwrap();
+        client.keep_alive().unwrap();
+        let connect = broker.join().unwrap();
+        assert_eq!(&connect[10..12], &[0, 0]);
+    }
+
+    #[test]
+    fn test_command_topic() {
+        assert_eq!(
+            command_topic("lifx", "lifx/d073d5010203/set"),
+            Some(("d073d5010203", None))
+        );
+        assert_eq!(
+            command_topic("home/lifx", "home/lifx/d073d5010203/set/power"),
+            Some(("d073d5010203", Some("power")))
+        );
+        assert_eq!(
+            command_topic("home/lifx/", "home/lifx/d073d5010203/set"),
+            Some(("d073d5010203", None))
+        );
+        assert_eq!(command_topic("home/lifx", "lifx/d073d5010203/set"), None);
+        assert_eq!(
+            command_topic("home/lifx", "home/lifxx/d073d5010203/set"),
+            None
+        );
+        assert_eq!(command_topic("lifx", "lifx/d073d5010203/state"), None);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let current = Hsbk::from_degrees(120.0, 100.0, 50.0, 3500);
+
+        assert_eq!(
+            parse_command(Some("power"), "OFF", current),
+            Ok(Command {
+                power: Some(false),
+                colour: None,
+                duration: 0
+            })
+        );
+        assert_eq!(
+            parse_command(Some("brightness"), "25", current)
+                .unwrap()
+                .colour,
+            Some(Hsbk::from_degrees(120.0, 100.0, 25.0, 3500))
+        );
+        assert_eq!(
+            parse_command(Some("kelvin"), "2700", current)
+                .unwrap()
+                .colour,
+            Some(Hsbk::from_degrees(120.0, 0.0, 50.0, 2700))
+        );
+        assert_eq!(
+            parse_command(Some("colour"), "red", current)
+                .unwrap()
+                .colour,
+            Some("red".parse().unwrap())
+        );
+
+        let command = parse_command(
+            None,
+            r#"{"state": "ON", "color": {"h": 240, "s": 50}, "brightness": 255, "transition": 2}"#,
+            current,
+        )
+        .unwrap();
+        assert_eq!(command.power, Some(true));
+        assert_eq!(
+            command.colour,
+            Some(Hsbk::from_degrees(240.0, 50.0, 100.0, 3500))
+        );
+        assert_eq!(command.duration, 2000);
+
+        assert!(parse_command(Some("power"), "maybe", current).is_err());
+        assert!(parse_command(Some("kelvin"), "100", current).is_err());
+        assert!(parse_command(None, r#"{"state": "DIM"}"#, current).is_err());
+    }
+
+    #[test]
+    fn test_state_json() {
+        let state = state_json(&Hsbk::from_degrees(0.0, 0.0, 100.0, 2700), true);
+        assert_eq!(
+            state.to_string(),
+            r#"{"state":"ON","brightness":255,"color_mode":"color_temp","color":{"h":0,"s":0},"color_temp":2700}"#
+        );
+    }
+}
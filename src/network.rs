@@ -2,14 +2,16 @@ use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
 use std::time::Duration;
 
+use capture::Direction;
 use request::RequestBin;
 use response::{self, Response};
-
-const DEBUG_ENABLED: bool = false;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub struct Network {}
 
 /// Represents a device on the network, as well as a response.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Device {
     pub socket_addr: SocketAddr,
     pub response: Option<Response>,
@@ -32,7 +34,7 @@ impl Network {
     }
 }
 
-fn ensure_ip_is_broadcast(subnet: Ipv4Addr) -> IpAddr {
+pub(crate) fn ensure_ip_is_broadcast(subnet: Ipv4Addr) -> IpAddr {
     let octets = subnet.octets();
     match octets[3] {
         255 => IpAddr::V4(subnet),
@@ -82,37 +84,24 @@ fn send(
     local_sock.set_broadcast(broadcast)?;
 
     let msg = &msg_bin.0;
-    display(msg);
-    print_debug("** sending...");
-    match local_sock.send_to(msg, device_socket_addr) {
-        Ok(v) => {
-            print_debug(&format!("** sent {} bytes.", v));
-        }
-        Err(e) => {
-            print_debug(&format!("** err sending: {}", e));
-            return Err(e);
-        }
-    };
+    if let Err(e) = local_sock.send_to(msg, device_socket_addr) {
+        warn!("sending to {} failed: {}", device_socket_addr, e);
+        return Err(e);
+    }
+    log_message(Direction::Sent, msg, device_socket_addr);
 
     // Read from the socket
-    print_debug("** reading...");
     let mut resp_buf = [0; 1024];
     let (sz, src_sock_addr) = match local_sock.recv_from(&mut resp_buf) {
-        Ok(v) => {
-            print_debug("** response read done\n");
-            v
-        }
+        Ok(v) => v,
         Err(e) => {
-            print_debug(&format!("** response read failed: {}", e));
+            debug!("no response from {}: {}", device_socket_addr, e);
             return Err(e);
         }
     };
 
     let resp_msg = &resp_buf[0..sz];
-    print_debug(&format!(
-        "Received from {} : \n{:?}",
-        src_sock_addr, resp_msg
-    ));
+    log_message(Direction::Received, resp_msg, src_sock_addr);
 
     let resp = response::parse_response(response::ResponseData(resp_msg.to_vec()));
     // let resp = response::parse_response(response::ResponseMessage(resp_msg.to_vec()));
@@ -125,20 +114,40 @@ fn send(
     Ok(device)
 }
 
-fn display(msg_bin: &[u8]) {
-    print_debug("---- Sending request: ----\n");
-    print_debug(&format!("Dec: {:?}\n", msg_bin));
-    print_debug("Bytes: \n");
-    for b in msg_bin.iter() {
-        print_debug(&format!("{:x} ", b));
+/// Logs a message sent or received: its type, sequence number and device at
+/// debug level, and the whole message in hex at trace level.
+pub(crate) fn log_message(direction: Direction, msg: &[u8], socket_addr: SocketAddr) {
+    if msg.len() < 36 {
+        debug!(
+            "{} {} bytes, {} {}",
+            direction.name(),
+            msg.len(),
+            to_or_from(direction),
+            socket_addr
+        );
+    } else if log_enabled!(log::Level::Debug) {
+        debug!(
+            "{} type {} seq {} {} {} ({})",
+            direction.name(),
+            u16::from_le_bytes([msg[32], msg[33]]),
+            msg[23],
+            to_or_from(direction),
+            socket_addr,
+            hex_dump(&msg[8..14]).replace(' ', ":")
+        );
     }
-    print_debug("\n----\n");
+    trace!("{}", hex_dump(msg));
 }
 
-pub fn print_debug(s: &str) {
-    if !DEBUG_ENABLED {
-        return;
+fn to_or_from(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Sent => "to",
+        Direction::Received => "from",
     }
+}
 
-    print!("{}", s);
+/// The bytes in hex, separated by spaces.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
 }
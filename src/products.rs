@@ -0,0 +1,124 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// The vendor id of LIFX products, as reported in StateVersion.
+pub const LIFX_VENDOR: u32 = 1;
+
+/// A known LIFX product, and the capabilities that matter when controlling it.
+///
+/// Products only implement `Serialize`: they live in a static table, borrowing
+/// their names from it, so a product is found again with `lookup` from its
+/// vendor and product ids rather than deserialized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Product {
+    pub pid: u32,
+    pub name: &'static str,
+    pub colour: bool,
+    pub infrared: bool,
+    pub multizone: bool,
+    pub matrix: bool,
+    pub min_kelvin: u16,
+    pub max_kelvin: u16,
+}
+
+const fn product(
+    pid: u32,
+    name: &'static str,
+    features: (bool, bool, bool, bool),
+    kelvin: (u16, u16),
+) -> Product {
+    Product {
+        pid,
+        name,
+        colour: features.0,
+        infrared: features.1,
+        multizone: features.2,
+        matrix: features.3,
+        min_kelvin: kelvin.0,
+        max_kelvin: kelvin.1,
+    }
+}
+
+// Features are (colour, infrared, multizone, matrix).
+const COLOUR: (bool, bool, bool, bool) = (true, false, false, false);
+const WHITE: (bool, bool, bool, bool) = (false, false, false, false);
+const NIGHT_VISION: (bool, bool, bool, bool) = (true, true, false, false);
+const STRIP: (bool, bool, bool, bool) = (true, false, true, false);
+const MATRIX: (bool, bool, bool, bool) = (true, false, false, true);
+
+static PRODUCTS: [Product; 43] = [
+    product(1, "LIFX Original 1000", COLOUR, (2500, 9000)),
+    product(3, "LIFX Color 650", COLOUR, (2500, 9000)),
+    product(10, "LIFX White 800 (Low Voltage)", WHITE, (2700, 6500)),
+    product(11, "LIFX White 800 (High Voltage)", WHITE, (2700, 6500)),
+    product(15, "LIFX Color 1000", COLOUR, (2500, 9000)),
+    product(18, "LIFX White 900 BR30 (Low Voltage)", WHITE, (2500, 9000)),
+    product(
+        19,
+        "LIFX White 900 BR30 (High Voltage)",
+        WHITE,
+        (2500, 9000),
+    ),
+    product(20, "LIFX Color 1000 BR30", COLOUR, (2500, 9000)),
+    product(22, "LIFX Color 1000", COLOUR, (2500, 9000)),
+    product(27, "LIFX A19", COLOUR, (2500, 9000)),
+    product(28, "LIFX BR30", COLOUR, (2500, 9000)),
+    product(29, "LIFX A19 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(30, "LIFX BR30 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(31, "LIFX Z", STRIP, (2500, 9000)),
+    product(32, "LIFX Z", STRIP, (2500, 9000)),
+    product(36, "LIFX Downlight", COLOUR, (2500, 9000)),
+    product(37, "LIFX Downlight", COLOUR, (2500, 9000)),
+    product(38, "LIFX Beam", STRIP, (2500, 9000)),
+    product(43, "LIFX A19", COLOUR, (2500, 9000)),
+    product(44, "LIFX BR30", COLOUR, (2500, 9000)),
+    product(45, "LIFX A19 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(46, "LIFX BR30 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(49, "LIFX Mini Color", COLOUR, (2500, 9000)),
+    product(50, "LIFX Mini White to Warm", WHITE, (1500, 4000)),
+    product(51, "LIFX Mini White", WHITE, (2700, 2700)),
+    product(52, "LIFX GU10", COLOUR, (2500, 9000)),
+    product(55, "LIFX Tile", MATRIX, (2500, 9000)),
+    product(57, "LIFX Candle", MATRIX, (1500, 9000)),
+    product(59, "LIFX Mini Color", COLOUR, (2500, 9000)),
+    product(60, "LIFX Mini White to Warm", WHITE, (1500, 4000)),
+    product(61, "LIFX Mini White", WHITE, (2700, 2700)),
+    product(62, "LIFX A19", COLOUR, (2500, 9000)),
+    product(63, "LIFX BR30", COLOUR, (2500, 9000)),
+    product(64, "LIFX A19 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(65, "LIFX BR30 Night Vision", NIGHT_VISION, (2500, 9000)),
+    product(66, "LIFX Mini White", WHITE, (2700, 2700)),
+    product(68, "LIFX Candle", MATRIX, (1500, 9000)),
+    product(81, "LIFX Candle White to Warm", WHITE, (2200, 6500)),
+    product(82, "LIFX Filament Clear", WHITE, (2100, 2100)),
+    product(85, "LIFX Filament Amber", WHITE, (2000, 2000)),
+    product(87, "LIFX Mini White", WHITE, (2700, 2700)),
+    product(91, "LIFX Color", COLOUR, (1500, 9000)),
+    product(97, "LIFX A19", COLOUR, (1500, 9000)),
+];
+
+/// Looks up a product by the vendor and product ids reported in StateVersion.
+pub fn lookup(vendor: u32, pid: u32) -> Option<&'static Product> {
+    if vendor != LIFX_VENDOR {
+        return None;
+    }
+
+    PRODUCTS.iter().find(|p| p.pid == pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use products::*;
+
+    #[test]
+    fn test_lookup() {
+        let p = lookup(LIFX_VENDOR, 31).unwrap();
+        assert_eq!(p.name, "LIFX Z");
+        assert!(p.multizone);
+        assert!(!p.matrix);
+
+        assert!(lookup(LIFX_VENDOR, 9999).is_none());
+        assert!(lookup(2, 31).is_none());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// LIFX devices drop messages sent faster than about 20 per second.
+pub const DEFAULT_MESSAGES_PER_SECOND: f64 = 20.0;
+pub const DEFAULT_BURST: u32 = 5;
+
+// How often buckets that have refilled are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket, refilled continuously at a fixed rate up to its capacity.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, refilled at the rate in tokens per second.
+    ///
+    /// Panics if the rate isn't a positive, finite number.
+    pub fn new(rate: f64, burst: u32, now: Instant) -> TokenBucket {
+        check_rate(rate);
+        let capacity = f64::from(burst.max(1));
+        TokenBucket {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// Takes a token if one is available, otherwise returns how long until one is.
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        if now > self.updated {
+            let elapsed = now.duration_since(self.updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+            self.updated = now;
+        }
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+    }
+
+    // Whether the bucket has refilled, and so is no different from a new one.
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.rate >= self.capacity
+    }
+}
+
+/// A token bucket per device, keyed by the device's target address.
+///
+/// Buckets of devices that have gone quiet long enough to refill are dropped,
+/// so the limiter doesn't grow with every device ever messaged.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: u32,
+    buckets: HashMap<[u8; 8], TokenBucket>,
+    pruned: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Panics if the rate isn't a positive, finite number.
+    pub fn new(messages_per_second: f64, burst: u32) -> RateLimiter {
+        check_rate(messages_per_second);
+        RateLimiter {
+            rate: messages_per_second,
+            burst,
+            buckets: HashMap::new(),
+            pruned: None,
+        }
+    }
+
+    pub fn messages_per_second(&self) -> f64 {
+        self.rate
+    }
+
+    /// Takes a token for the target if one is available, otherwise returns how
+    /// long until one is.
+    pub fn try_acquire(&mut self, target: [u8; 8], now: Instant) -> Result<(), Duration> {
+        if self
+            .pruned
+            .is_none_or(|at| now.saturating_duration_since(at) >= PRUNE_INTERVAL)
+        {
+            self.buckets.retain(|_, b| !b.is_full(now));
+            self.pruned = Some(now);
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets
+            .entry(target)
+            .or_insert_with(|| TokenBucket::new(rate, burst, now))
+            .try_take(now)
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new(DEFAULT_MESSAGES_PER_SECOND, DEFAULT_BURST)
+    }
+}
+
+fn check_rate(rate: f64) {
+    assert!(
+        rate.is_finite() && rate > 0.0,
+        "rate limit must be a positive number of messages per second, not {}",
+        rate
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use ratelimit::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket_burst_then_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(20.0, 2, start);
+
+        assert!(bucket.try_take(start).is_ok());
+        assert!(bucket.try_take(start).is_ok());
+        let wait = bucket.try_take(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(50));
+
+        assert!(bucket.try_take(start + Duration::from_millis(49)).is_err());
+        assert!(bucket.try_take(start + Duration::from_millis(50)).is_ok());
+    }
+
+    #[test]
+    fn test_token_bucket_caps_at_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(20.0, 2, start);
+        let later = start + Duration::from_secs(10);
+
+        assert!(bucket.try_take(later).is_ok());
+        assert!(bucket.try_take(later).is_ok());
+        assert!(bucket.try_take(later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_per_target() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(20.0, 1);
+        let a = [1, 0, 0, 0, 0, 0, 0, 0];
+        let b = [2, 0, 0, 0, 0, 0, 0, 0];
+
+        assert!(limiter.try_acquire(a, now).is_ok());
+        assert!(limiter.try_acquire(a, now).is_err());
+        assert!(limiter.try_acquire(b, now).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_prunes_refilled_buckets() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(20.0, 1);
+        for i in 0..100 {
+            assert!(limiter.try_acquire([i, 0, 0, 0, 0, 0, 0, 0], now).is_ok());
+        }
+        assert_eq!(limiter.buckets.len(), 100);
+
+        // Only the bucket still waiting to refill is kept.
+        let later = now + Duration::from_secs(60);
+        let a = [1, 0, 0, 0, 0, 0, 0, 0];
+        assert!(limiter.try_acquire(a, later).is_ok());
+        assert_eq!(limiter.buckets.len(), 1);
+        assert!(limiter.try_acquire(a, later).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "rate limit must be a positive number")]
+    fn test_rate_limiter_rejects_zero_rate() {
+        RateLimiter::new(0.0, 1);
+    }
+}
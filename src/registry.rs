@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use client::Client;
+use network;
+use products::{self, Product};
+use response::{Payload, Response, StatePayload};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How often, and how patiently, the registry looks for devices.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RegistryConfig {
+    pub poll_interval: Duration,
+    pub discovery_wait: Duration,
+    /// Consecutive failed polls after which a device is considered offline.
+    pub max_missed_polls: u32,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> RegistryConfig {
+        RegistryConfig {
+            poll_interval: Duration::new(30, 0),
+            discovery_wait: Duration::new(2, 0),
+            max_missed_polls: 3,
+        }
+    }
+}
+
+/// Everything known about a device, as of the last time it was seen.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RegisteredDevice {
+    pub mac_address: String,
+    pub target: [u8; 8],
+    pub socket_addr: SocketAddr,
+    pub label: Option<String>,
+    pub group: Option<String>,
+    pub location: Option<String>,
+    pub vendor: Option<u32>,
+    pub product: Option<u32>,
+    pub state: Option<StatePayload>,
+    pub last_seen: SystemTime,
+    pub missed_polls: u32,
+    pub online: bool,
+}
+
+impl RegisteredDevice {
+    pub fn new(mac_address: String, target: [u8; 8], socket_addr: SocketAddr) -> RegisteredDevice {
+        RegisteredDevice {
+            mac_address,
+            target,
+            socket_addr,
+            label: None,
+            group: None,
+            location: None,
+            vendor: None,
+            product: None,
+            state: None,
+            last_seen: SystemTime::now(),
+            missed_polls: 0,
+            online: true,
+        }
+    }
+
+    /// The product details, if the device reported a product this crate knows.
+    pub fn product_info(&self) -> Option<&'static Product> {
+        match (self.vendor, self.product) {
+            (Some(vendor), Some(pid)) => products::lookup(vendor, pid),
+            _ => None,
+        }
+    }
+
+    /// A device handle, for use with the functions in `messages`.
+    pub fn device(&self) -> network::Device {
+        network::Device {
+            socket_addr: self.socket_addr,
+            response: None,
+        }
+    }
+}
+
+type Devices = Arc<Mutex<HashMap<String, RegisteredDevice>>>;
+
+/// A cache of the devices on a subnet, keyed by MAC address.
+///
+/// The registry can be refreshed on demand, or left to refresh itself on a
+/// background thread. Devices that miss several polls in a row are marked
+/// offline rather than forgotten, and devices that reappear at a new address,
+/// after a DHCP lease change, keep their entry.
+pub struct DeviceRegistry {
+    client: Arc<Client>,
+    subnet: Ipv4Addr,
+    config: RegistryConfig,
+    devices: Devices,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl DeviceRegistry {
+    pub fn new(client: Arc<Client>, subnet: Ipv4Addr) -> DeviceRegistry {
+        DeviceRegistry::with_config(client, subnet, RegistryConfig::default())
+    }
+
+    pub fn with_config(
+        client: Arc<Client>,
+        subnet: Ipv4Addr,
+        config: RegistryConfig,
+    ) -> DeviceRegistry {
+        DeviceRegistry {
+            client,
+            subnet,
+            config,
+            devices: Arc::new(Mutex::new(HashMap::new())),
+            running: Arc::new(AtomicBool::new(false)),
+            worker: None,
+        }
+    }
+
+    /// Discovers devices, then polls every known device for its state.
+    pub fn refresh(&self) -> Result<(), io::Error> {
+        refresh(&self.client, self.subnet, &self.config, &self.devices)
+    }
+
+    /// Starts refreshing the registry on a background thread, every poll interval.
+    pub fn start(&mut self) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let client = self.client.clone();
+        let subnet = self.subnet;
+        let config = self.config.clone();
+        let devices = self.devices.clone();
+        let running = self.running.clone();
+
+        self.worker = Some(thread::spawn(move || {
+            while running.load(Ordering::SeqCst) {
+                if let Err(e) = refresh(&client, subnet, &config, &devices) {
+                    warn!("registry refresh failed: {}", e);
+                }
+
+                // Sleep in short steps, so that stopping is not held up.
+                let next = Instant::now() + config.poll_interval;
+                while running.load(Ordering::SeqCst) && Instant::now() < next {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }));
+    }
+
+    /// Stops the background refresh, waiting for any refresh in progress.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+
+    /// Adds devices known in advance, such as those with a fixed address in a
+    /// `Config`, so that they can be used without being discovered. Devices
+    /// already known are left as they are.
+    pub fn add(&self, devices: &[RegisteredDevice]) {
+        let mut known = self.devices.lock().unwrap();
+        for d in devices {
+            known
+                .entry(d.mac_address.to_uppercase())
+                .or_insert_with(|| d.clone());
+        }
+    }
+
+    pub fn client(&self) -> &Arc<Client> {
+        &self.client
+    }
+
+    /// All known devices, online or not.
+    pub fn devices(&self) -> Vec<RegisteredDevice> {
+        let devices = self.devices.lock().unwrap();
+        let mut all: Vec<RegisteredDevice> = devices.values().cloned().collect();
+        all.sort_by(|a, b| a.mac_address.cmp(&b.mac_address));
+        all
+    }
+
+    /// Known devices that answered their most recent polls.
+    pub fn online(&self) -> Vec<RegisteredDevice> {
+        self.devices().into_iter().filter(|d| d.online).collect()
+    }
+
+    pub fn get(&self, mac_address: &str) -> Option<RegisteredDevice> {
+        let devices = self.devices.lock().unwrap();
+        devices.get(&mac_address.to_uppercase()).cloned()
+    }
+
+    pub fn find_by_label(&self, label: &str) -> Option<RegisteredDevice> {
+        self.devices().into_iter().find(|d| match d.label {
+            Some(ref l) => l.eq_ignore_ascii_case(label),
+            None => false,
+        })
+    }
+
+    pub fn find_by_group(&self, group: &str) -> Vec<RegisteredDevice> {
+        self.devices()
+            .into_iter()
+            .filter(|d| match d.group {
+                Some(ref g) => g.eq_ignore_ascii_case(group),
+                None => false,
+            })
+            .collect()
+    }
+}
+
+impl Drop for DeviceRegistry {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn refresh(
+    client: &Client,
+    subnet: Ipv4Addr,
+    config: &RegistryConfig,
+    devices: &Mutex<HashMap<String, RegisteredDevice>>,
+) -> Result<(), io::Error> {
+    let found = client.discover(subnet, config.discovery_wait)?;
+    {
+        let mut devices = devices.lock().unwrap();
+        for d in found {
+            if let Some(resp) = d.response {
+                record_seen(&mut devices, &resp, d.socket_addr);
+            }
+        }
+    }
+
+    // Poll outside the lock, in parallel, so one slow device doesn't stall the rest.
+    let known: Vec<RegisteredDevice> = devices.lock().unwrap().values().cloned().collect();
+    let polled: Vec<(String, Option<RegisteredDevice>)> = thread::scope(|s| {
+        let handles: Vec<_> = known
+            .into_iter()
+            .map(|d| s.spawn(move || (d.mac_address.clone(), poll(client, d))))
+            .collect();
+        handles.into_iter().filter_map(|h| h.join().ok()).collect()
+    });
+
+    let mut devices = devices.lock().unwrap();
+    for (mac_address, result) in polled {
+        match result {
+            Some(d) => {
+                devices.insert(mac_address, d);
+            }
+            None => {
+                if let Some(d) = devices.get_mut(&mac_address) {
+                    record_missed(d, config.max_missed_polls);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Fetches the device's state, and any details not yet known. None if it didn't reply.
+fn poll(client: &Client, mut device: RegisteredDevice) -> Option<RegisteredDevice> {
+    let resp = client.get(device.socket_addr, device.target, 101).ok()?;
+    if let Payload::State(state) = resp.payload {
+        device.label = Some(state.label.clone());
+        device.state = Some(state);
+    }
+
+    if device.group.is_none() {
+        if let Ok(Response {
+            payload: Payload::StateGroup(p),
+            ..
+        }) = client.get(device.socket_addr, device.target, 51)
+        {
+            device.group = Some(p.label);
+        }
+    }
+
+    if device.location.is_none() {
+        if let Ok(Response {
+            payload: Payload::StateLocation(p),
+            ..
+        }) = client.get(device.socket_addr, device.target, 48)
+        {
+            device.location = Some(p.label);
+        }
+    }
+
+    if device.product.is_none() {
+        if let Ok(Response {
+            payload: Payload::StateVersion(p),
+            ..
+        }) = client.get(device.socket_addr, device.target, 32)
+        {
+            device.vendor = Some(p.vendor);
+            device.product = Some(p.product);
+        }
+    }
+
+    device.last_seen = SystemTime::now();
+    device.missed_polls = 0;
+    device.online = true;
+    Some(device)
+}
+
+// Records a device found by discovery, following it to a new address if it moved.
+fn record_seen(
+    devices: &mut HashMap<String, RegisteredDevice>,
+    resp: &Response,
+    socket_addr: SocketAddr,
+) {
+    let device = devices.entry(resp.mac_address.clone()).or_insert_with(|| {
+        RegisteredDevice::new(resp.mac_address.clone(), resp.target, socket_addr)
+    });
+
+    if device.socket_addr != socket_addr {
+        info!(
+            "{} moved from {} to {}",
+            device.mac_address, device.socket_addr, socket_addr
+        );
+        device.socket_addr = socket_addr;
+    }
+
+    device.last_seen = SystemTime::now();
+    device.missed_polls = 0;
+    device.online = true;
+}
+
+fn record_missed(device: &mut RegisteredDevice, max_missed_polls: u32) {
+    device.missed_polls += 1;
+    if device.missed_polls >= max_missed_polls {
+        device.online = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record_missed, record_seen, RegisteredDevice};
+    use response::{parse_response, ResponseData};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    fn state_service(mac: [u8; 6]) -> ResponseData {
+        let mut data = vec![0u8; 41];
+        data[0] = 41;
+        data[3] = 0x14;
+        data[8..14].clone_from_slice(&mac);
+        data[32] = 3;
+        data[36] = 1;
+        data[37] = 0x7C;
+        data[38] = 0xDD;
+        ResponseData(data)
+    }
+
+    #[test]
+    fn test_record_seen_follows_address_change() {
+        let mut devices = HashMap::new();
+        let resp = parse_response(state_service([0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03]));
+        let first: SocketAddr = "192.168.1.10:56700".parse().unwrap();
+        let second: SocketAddr = "192.168.1.23:56700".parse().unwrap();
+
+        record_seen(&mut devices, &resp, first);
+        devices.get_mut(&resp.mac_address).unwrap().label = Some("Desk".to_string());
+        record_seen(&mut devices, &resp, second);
+
+        assert_eq!(devices.len(), 1);
+        let d = &devices["D0:73:D5:01:02:03:00:00"];
+        assert_eq!(d.socket_addr, second);
+        assert_eq!(d.label, Some("Desk".to_string()));
+        assert_eq!(d.target, [0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03, 0, 0]);
+    }
+
+    #[test]
+    fn test_record_missed_marks_offline() {
+        let mut d = RegisteredDevice::new(
+            "D0:73:D5:01:02:03:00:00".to_string(),
+            [0; 8],
+            "192.168.1.10:56700".parse().unwrap(),
+        );
+
+        record_missed(&mut d, 2);
+        assert!(d.online);
+        record_missed(&mut d, 2);
+        assert!(!d.online);
+        assert_eq!(d.missed_polls, 2);
+    }
+}
@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 type Bit = bool;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Request {
     header: Header,
     payload: Payload,
@@ -15,109 +19,14 @@ impl Request {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Payload(pub Vec<u8>);
 
 // RequestBin newtype
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RequestBin(pub Vec<u8>);
 
-#[derive(Debug)]
-pub struct Header {
-    frame: Frame,
-    frame_address: FrameAddress,
-    protocol_header: ProtocolHeader,
-}
-
-impl Header {
-    pub fn new(
-        frame: Frame,
-        frame_address: FrameAddress,
-        protocol_header: ProtocolHeader,
-    ) -> Header {
-        Header {
-            frame,
-            frame_address,
-            protocol_header,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Frame {
-    // First 2 bytes
-    size: u16,
-
-    // Second two bytes
-    origin: u8,
-    // For discovery using Device::GetService use true and target all zeroes.
-    // For all other requests set to false and target to device MAC address.
-    tagged: bool,
-    addressable: bool, // Must be true
-    protocol: u16,     // Must be 1024
-
-    // Final 4 bytes
-    source: u32,
-}
-
-impl Frame {
-    pub fn new(origin: u8, tagged: bool, addressable: bool, protocol: u16, source: u32) -> Frame {
-        Frame {
-            size: 0,
-            origin,
-            tagged,
-            addressable,
-            protocol,
-            source,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct FrameAddress {
-    // MAC address (6 bytes) left-justified with two 0 bytes, or all 0s for all devices
-    target: [u8; 8],
-    reserved: [u8; 6],
-    reserved_2: u8,
-    ack_required: bool,
-    res_required: bool,
-    sequence: u8,
-}
-
-impl FrameAddress {
-    pub fn new(
-        target: [u8; 8],
-        reserved: [u8; 6],
-        reserved_2: u8,
-        ack_required: bool,
-        res_required: bool,
-        sequence: u8,
-    ) -> FrameAddress {
-        FrameAddress {
-            target,
-            reserved,
-            reserved_2,
-            ack_required,
-            res_required,
-            sequence,
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct ProtocolHeader {
-    reserved: u64,
-    message_type: u16,
-    reserved_2: u16,
-}
-
-impl ProtocolHeader {
-    pub fn new(reserved: u64, message_type: u16, reserved_2: u16) -> ProtocolHeader {
-        ProtocolHeader {
-            reserved,
-            message_type,
-            reserved_2,
-        }
-    }
-}
+pub use codec::{Frame, FrameAddress, Header, ProtocolHeader};
 
 // BitFrame is an intermediate representation.
 struct BitFrame {
@@ -137,7 +46,7 @@ struct BitFrame {
     source: u32,
 }
 
-impl<'a> From<&'a Frame> for BitFrame {
+impl From<&Frame> for BitFrame {
     fn from(f: &Frame) -> BitFrame {
         BitFrame {
             // First two bytes
@@ -191,7 +100,7 @@ struct BitFrameAddress {
     sequence: u8,
 }
 
-impl<'a> From<&'a FrameAddress> for BitFrameAddress {
+impl From<&FrameAddress> for BitFrameAddress {
     fn from(f: &FrameAddress) -> BitFrameAddress {
         BitFrameAddress {
             target: f.target,
@@ -238,7 +147,10 @@ impl From<Request> for RequestBin {
         msg_bin.extend_with_u32(msg.header.frame.source);
 
         // First, 8 bytes of FrameAddress
-        msg_bin.extend_with_u8_array_8(msg.header.frame_address.target);
+        // The target MAC address is sent in network order, so is not reversed.
+        msg_bin
+            .0
+            .extend_from_slice(&msg.header.frame_address.target);
 
         // Second, 6 bytes of FrameAddress
         msg_bin.extend_with_u8_array_6(msg.header.frame_address.reserved);
@@ -296,6 +208,13 @@ impl From<Request> for RequestBin {
             msg_bin.extend_with_u8_array_2(p_arr_p);
         }
 
+        // Payloads for all other messages are expected to be in wire order already.
+        if msg.header.protocol_header.message_type != 102
+            && msg.header.protocol_header.message_type != 21
+        {
+            msg_bin.0.extend_from_slice(&msg.payload.0);
+        }
+
         // Set message size in first 2 bytes of request, Frame.
         let mut p = RequestBin::u16_to_u8_array(msg_bin.0.len() as u16);
         p.reverse();
@@ -390,7 +309,7 @@ impl RequestBin {
         [b1, b2, b3, b4]
     }
 
-    fn u64_to_u8_array(x: u64) -> [u8; 8] {
+    pub fn u64_to_u8_array(x: u64) -> [u8; 8] {
         let b1: u8 = ((x >> 56) & 0xff) as u8;
         let b2: u8 = ((x >> 48) & 0xff) as u8;
         let b3: u8 = ((x >> 40) & 0xff) as u8;
@@ -402,3 +321,79 @@ impl RequestBin {
         [b1, b2, b3, b4, b5, b6, b7, b8]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use request::*;
+
+    #[test]
+    fn test_request_bin_header() {
+        let msg = Request::new(
+            Header::new(
+                Frame::new(0, false, true, 1024, 321),
+                FrameAddress::new([0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0], [0; 6], 0, true, false, 7),
+                ProtocolHeader::new(0, 23, 0),
+            ),
+            Payload(vec![]),
+        );
+        let b = RequestBin::from(msg).0;
+
+        assert_eq!(b.len(), 36);
+        assert_eq!(&b[0..4], &[36, 0, 0x00, 0x14]);
+        assert_eq!(&b[4..8], &[0x41, 0x01, 0, 0]);
+        assert_eq!(&b[8..16], &[0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0]);
+        assert_eq!(b[22], 0x02);
+        assert_eq!(b[23], 7);
+        assert_eq!(&b[32..34], &[23, 0]);
+    }
+
+    #[test]
+    fn test_request_bin_payload() {
+        let msg = Request::new(
+            Header::new(
+                Frame::new(0, false, true, 1024, 321),
+                FrameAddress::new([0; 8], [0; 6], 0, true, false, 0),
+                ProtocolHeader::new(0, 24, 0),
+            ),
+            Payload(vec![68, 101, 115, 107]),
+        );
+        let b = RequestBin::from(msg).0;
+
+        assert_eq!(b.len(), 40);
+        assert_eq!(&b[36..], &[68, 101, 115, 107]);
+    }
+
+    #[test]
+    fn test_header_decode_inverts_encode() {
+        let header = Header::new(
+            Frame::new(2, true, true, 1024, 0xDEAD_BEEF),
+            FrameAddress::new(
+                [0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0],
+                [1, 2, 3, 4, 5, 6],
+                0x2A,
+                true,
+                true,
+                200,
+            ),
+            ProtocolHeader::new(0x0102_0304_0506_0708, 512, 0xBEEF),
+        );
+        let b = RequestBin::from(Request::new(header, Payload(vec![9; 4]))).0;
+
+        let decoded = Header::decode(&b).unwrap();
+        assert_eq!(decoded.frame.size, 40);
+        assert_eq!(
+            decoded,
+            Header {
+                frame: Frame {
+                    size: 40,
+                    ..header.frame
+                },
+                ..header
+            }
+        );
+
+        let again = RequestBin::from(Request::new(decoded, Payload(vec![9; 4]))).0;
+        assert_eq!(again, b);
+        assert_eq!(Header::decode(&b[..35]), None);
+    }
+}
@@ -1,26 +1,52 @@
 #![allow(dead_code)]
 
 use std::str;
-
-#[derive(Debug)]
+use std::time::Duration;
+
+use colour::Hsbk;
+use effect::{
+    self, Direction, MultiZoneEffect, MultiZoneEffectSettings, TileEffect, TileEffectSettings,
+    MAX_PALETTE,
+};
+use request::Header;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Response {
+    /// The header as sent, bit for bit.
+    pub header: Header,
     pub size: u16,
     pub source: u32,
     pub mac_address: String,
-    pub firmware: String,
-    pub sequence_number: u16,
-    pub reserved_1: u32,
+    pub target: [u8; 8],
+    pub sequence_number: u8,
     pub message_type: u16,
-    pub reserved_2: u16,
     pub payload: Payload,
 }
 
+/// Parses a message of at least 36 bytes, the size of its header.
 pub fn parse_response(resp_msg: ResponseData) -> Response {
     let mut resp = parse_header(&resp_msg);
 
     let payload = match resp.message_type {
-        3 => parse_payload_3(&resp_msg),
-        107 => parse_payload_107(&resp_msg),
+        3 if has_payload(&resp_msg, 5) => parse_payload_3(&resp_msg),
+        22 if has_payload(&resp_msg, 2) => parse_payload_22(&resp_msg),
+        25 if has_payload(&resp_msg, 32) => parse_payload_25(&resp_msg),
+        17 if has_payload(&resp_msg, 14) => parse_payload_17(&resp_msg),
+        33 if has_payload(&resp_msg, 12) => parse_payload_33(&resp_msg),
+        35 if has_payload(&resp_msg, 24) => parse_payload_35(&resp_msg),
+        50 if has_payload(&resp_msg, 56) => parse_payload_50(&resp_msg),
+        53 if has_payload(&resp_msg, 56) => parse_payload_53(&resp_msg),
+        107 if has_payload(&resp_msg, 52) => parse_payload_107(&resp_msg),
+        503 if has_payload(&resp_msg, 10) => parse_payload_503(&resp_msg),
+        506 if has_payload(&resp_msg, 66) => parse_payload_506(&resp_msg),
+        509 if has_payload(&resp_msg, 59) => parse_payload_509(&resp_msg),
+        512 if has_payload(&resp_msg, 661) => parse_payload_512(&resp_msg),
+        702 if has_payload(&resp_msg, 882) => parse_payload_702(&resp_msg),
+        711 if has_payload(&resp_msg, 517) => parse_payload_711(&resp_msg),
+        720 if has_payload(&resp_msg, 187) => parse_payload_720(&resp_msg),
         _ => Payload::None(()),
     };
 
@@ -30,55 +56,147 @@ pub fn parse_response(resp_msg: ResponseData) -> Response {
 }
 
 fn parse_header(resp: &ResponseData) -> Response {
+    let header = Header::decode(&resp.0).expect("a response holds a 36 byte header");
     Response {
-        size: ResponseData::size(resp),
-        source: ResponseData::source(resp),
+        size: header.frame.size,
+        source: header.frame.source,
         mac_address: ResponseData::mac_address(resp),
-        firmware: ResponseData::firmware(resp),
-
-        // TODO: packed byte
-        sequence_number: ResponseData::sequence_number(resp),
-
-        // Message segment: protocol header
-        reserved_1: ResponseData::reserved_1(resp), // timestamp?
-        message_type: ResponseData::message_type(resp),
-        reserved_2: ResponseData::reserved_2(resp),
+        target: header.frame_address.target,
+        sequence_number: header.frame_address.sequence,
+        message_type: header.protocol_header.message_type,
+        header,
         payload: Payload::None(()),
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 pub enum Payload {
     None(()),
     StateService(StateServicePayload),
     StatePower(StatePowerPayload),
+    StateLabel(StateLabelPayload),
+    StateWifiInfo(StateWifiInfoPayload),
+    StateVersion(StateVersionPayload),
+    StateInfo(StateInfoPayload),
+    StateLocation(StateCollectionPayload),
+    StateGroup(StateCollectionPayload),
     State(StatePayload),
+    StateZone(StateZonesPayload),
+    StateMultiZone(StateZonesPayload),
+    StateExtendedColorZones(StateZonesPayload),
+    StateMultiZoneEffect(MultiZoneEffectSettings),
+    StateDeviceChain(StateDeviceChainPayload),
+    State64(State64Payload),
+    StateTileEffect(TileEffectSettings),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StateServicePayload {
     pub service: u16,
     pub port: u32,
     pub unknown: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StatePowerPayload {
     pub level: u16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateLabelPayload {
+    pub label: String,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateWifiInfoPayload {
+    /// The received signal strength, in milliwatts.
+    pub signal: f32,
+}
+
+impl StateWifiInfoPayload {
+    /// The received signal strength, in dBm.
+    pub fn rssi(&self) -> f64 {
+        (10.0 * f64::from(self.signal).log10()).round()
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateVersionPayload {
+    pub vendor: u32,
+    pub product: u32,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateInfoPayload {
+    /// The device's clock, in nanoseconds since the epoch.
+    pub time: u64,
+    pub uptime: Duration,
+    /// How long the device was off before it last started, roughly.
+    pub downtime: Duration,
+}
+
+/// The payload of both StateLocation and StateGroup, which share a layout.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateCollectionPayload {
+    pub id: String,
+    pub label: String,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StatePayload {
     pub body: String,
-    pub hsbk: PayloadHSBK,
+    pub hsbk: Hsbk,
+    pub power: u16,
+    pub label: String,
+}
+
+/// The payload of StateZone, StateMultiZone and StateExtendedColorZones, which
+/// each report the colours of a run of zones, starting at index.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateZonesPayload {
+    pub count: u16,
+    pub index: u16,
+    pub colours: Vec<Hsbk>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StateDeviceChainPayload {
+    pub start_index: u8,
+    pub tiles: Vec<TileInfo>,
 }
 
-#[derive(Debug)]
-pub struct PayloadHSBK {
-    pub hue: u16,
-    pub saturation: u16,
-    pub brightness: u16,
-    pub kelvin: u16,
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileInfo {
+    pub width: u8,
+    pub height: u8,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct State64Payload {
+    pub tile_index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub colours: Vec<Hsbk>,
 }
 
 fn parse_payload_3(resp: &ResponseData) -> Payload {
@@ -95,63 +213,160 @@ fn parse_payload_22(resp: &ResponseData) -> Payload {
     })
 }
 
+fn parse_payload_25(resp: &ResponseData) -> Payload {
+    Payload::StateLabel(StateLabelPayload {
+        label: as_label(extract(resp, 36, 32)),
+    })
+}
+
+fn parse_payload_17(resp: &ResponseData) -> Payload {
+    Payload::StateWifiInfo(StateWifiInfoPayload {
+        signal: f32::from_bits(ResponseData::u32_at(resp, 36)),
+    })
+}
+
+fn parse_payload_33(resp: &ResponseData) -> Payload {
+    Payload::StateVersion(StateVersionPayload {
+        vendor: ResponseData::u32_at(resp, 36),
+        product: ResponseData::u32_at(resp, 40),
+        version: ResponseData::u32_at(resp, 44),
+    })
+}
+
+fn parse_payload_35(resp: &ResponseData) -> Payload {
+    Payload::StateInfo(StateInfoPayload {
+        time: ResponseData::u64_at(resp, 36),
+        uptime: Duration::from_nanos(ResponseData::u64_at(resp, 44)),
+        downtime: Duration::from_nanos(ResponseData::u64_at(resp, 52)),
+    })
+}
+
+fn parse_payload_50(resp: &ResponseData) -> Payload {
+    Payload::StateLocation(parse_collection(resp))
+}
+
+fn parse_payload_53(resp: &ResponseData) -> Payload {
+    Payload::StateGroup(parse_collection(resp))
+}
+
+fn parse_collection(resp: &ResponseData) -> StateCollectionPayload {
+    StateCollectionPayload {
+        id: as_hex(extract(resp, 36, 16)),
+        label: as_label(extract(resp, 52, 32)),
+        updated_at: ResponseData::u64_at(resp, 84),
+    }
+}
+
 fn parse_payload_107(resp: &ResponseData) -> Payload {
     Payload::State(StatePayload {
         body: ResponseData::body(resp),
-        hsbk: PayloadHSBK {
+        hsbk: Hsbk {
             hue: ResponseData::hue(resp),
             saturation: ResponseData::saturation(resp),
             brightness: ResponseData::brightness(resp),
             kelvin: ResponseData::kelvin(resp),
         },
+        power: ResponseData::u16_at(resp, 46),
+        label: as_label(extract(resp, 48, 32)),
     })
 }
 
-pub struct ResponseData(pub Vec<u8>);
-// pub struct ResponseMessage(pub Vec<u8>);
+fn parse_payload_503(resp: &ResponseData) -> Payload {
+    Payload::StateZone(StateZonesPayload {
+        count: u16::from(resp.0[36]),
+        index: u16::from(resp.0[37]),
+        colours: vec![ResponseData::hsbk_at(resp, 38)],
+    })
+}
 
-impl ResponseData {
-    fn size(resp: &ResponseData) -> u16 {
-        let mut b = extract(resp, 0, 2);
-        b.reverse();
-        as_base10(b)
-    }
+fn parse_payload_506(resp: &ResponseData) -> Payload {
+    Payload::StateMultiZone(StateZonesPayload {
+        count: u16::from(resp.0[36]),
+        index: u16::from(resp.0[37]),
+        colours: (0..8)
+            .map(|i| ResponseData::hsbk_at(resp, 38 + i * 8))
+            .collect(),
+    })
+}
 
-    fn source(resp: &ResponseData) -> u32 {
-        let mut b = extract(resp, 4, 4);
-        b.reverse();
-        let bstr = as_boolean(b);
-        bitstr_to_u32(&bstr)
-    }
+fn parse_payload_509(resp: &ResponseData) -> Payload {
+    let effect = match resp.0[40] {
+        0 => MultiZoneEffect::Off,
+        1 if ResponseData::u32_at(resp, 67) == 1 => MultiZoneEffect::Move(Direction::Left),
+        1 => MultiZoneEffect::Move(Direction::Right),
+        code => MultiZoneEffect::Unknown(code),
+    };
+    Payload::StateMultiZoneEffect(MultiZoneEffectSettings {
+        instance_id: ResponseData::u32_at(resp, 36),
+        effect,
+        speed: Duration::from_millis(u64::from(ResponseData::u32_at(resp, 43))),
+        duration: effect::nanos_to_duration(ResponseData::u64_at(resp, 47)),
+    })
+}
 
-    fn mac_address(resp: &ResponseData) -> String {
-        as_hex(extract(resp, 8, 8))
-    }
+fn parse_payload_512(resp: &ResponseData) -> Payload {
+    let colours_count = (resp.0[40] as usize).min(82);
+    Payload::StateExtendedColorZones(StateZonesPayload {
+        count: ResponseData::u16_at(resp, 36),
+        index: ResponseData::u16_at(resp, 38),
+        colours: (0..colours_count)
+            .map(|i| ResponseData::hsbk_at(resp, 41 + i * 8))
+            .collect(),
+    })
+}
 
-    fn firmware(resp: &ResponseData) -> String {
-        as_ascii(extract(resp, 16, 6))
-    }
+fn parse_payload_702(resp: &ResponseData) -> Payload {
+    // Each of the 16 tile slots is 55 bytes: accel x, y and z (6), reserved (2),
+    // user x and y (8), then width and height at 16 and 17.
+    let tile_count = (resp.0[36 + 1 + 16 * 55] as usize).min(16);
+    Payload::StateDeviceChain(StateDeviceChainPayload {
+        start_index: resp.0[36],
+        tiles: (0..tile_count)
+            .map(|i| TileInfo {
+                width: resp.0[37 + i * 55 + 16],
+                height: resp.0[37 + i * 55 + 17],
+            })
+            .collect(),
+    })
+}
 
-    fn sequence_number(resp: &ResponseData) -> u16 {
-        as_base10(extract(resp, 23, 1))
-    }
+fn parse_payload_711(resp: &ResponseData) -> Payload {
+    Payload::State64(State64Payload {
+        tile_index: resp.0[36],
+        x: resp.0[38],
+        y: resp.0[39],
+        width: resp.0[40],
+        colours: (0..64)
+            .map(|i| ResponseData::hsbk_at(resp, 41 + i * 8))
+            .collect(),
+    })
+}
 
-    fn reserved_1(resp: &ResponseData) -> u32 {
-        let mut b = extract(resp, 24, 8);
-        b.reverse();
-        let bstr = as_boolean(b);
-        bitstr_to_u32(&bstr)
-    }
+fn parse_payload_720(resp: &ResponseData) -> Payload {
+    let palette_count = (resp.0[94] as usize).min(MAX_PALETTE);
+    Payload::StateTileEffect(TileEffectSettings {
+        instance_id: ResponseData::u32_at(resp, 37),
+        effect: TileEffect::from_code(resp.0[41]),
+        speed: Duration::from_millis(u64::from(ResponseData::u32_at(resp, 42))),
+        duration: effect::nanos_to_duration(ResponseData::u64_at(resp, 46)),
+        palette: (0..palette_count)
+            .map(|i| ResponseData::hsbk_at(resp, 95 + i * 8))
+            .collect(),
+    })
+}
 
-    fn message_type(resp: &ResponseData) -> u16 {
-        let mut b = extract(resp, 32, 2);
-        b.reverse();
-        as_base10(b)
-    }
+// Payloads are only parsed when the message is long enough to hold them.
+fn has_payload(resp: &ResponseData, len: usize) -> bool {
+    resp.0.len() >= 36 + len
+}
 
-    fn reserved_2(resp: &ResponseData) -> u16 {
-        let b = extract(resp, 34, 2);
-        as_base10(b) // TODO: may not be base10, but undocumented.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ResponseData(pub Vec<u8>);
+// pub struct ResponseMessage(pub Vec<u8>);
+
+impl ResponseData {
+    fn mac_address(resp: &ResponseData) -> String {
+        as_hex(extract(resp, 8, 8))
     }
 
     fn service(resp: &ResponseData) -> u16 {
@@ -166,7 +381,7 @@ impl ResponseData {
     }
 
     fn level(resp: &ResponseData) -> u16 {
-        as_base10(extract(resp, 36, 1))
+        ResponseData::u16_at(resp, 36)
     }
 
     fn unknown(resp: &ResponseData) -> String {
@@ -208,6 +423,36 @@ impl ResponseData {
         let bstr = as_boolean(b);
         bitstr_to_u16(&bstr)
     }
+
+    fn hsbk_at(resp: &ResponseData, start: usize) -> Hsbk {
+        Hsbk {
+            hue: ResponseData::u16_at(resp, start),
+            saturation: ResponseData::u16_at(resp, start + 2),
+            brightness: ResponseData::u16_at(resp, start + 4),
+            kelvin: ResponseData::u16_at(resp, start + 6),
+        }
+    }
+
+    fn u16_at(resp: &ResponseData, start: usize) -> u16 {
+        let mut b = extract(resp, start, 2);
+        b.reverse();
+        let bstr = as_boolean(b);
+        bitstr_to_u16(&bstr)
+    }
+
+    fn u32_at(resp: &ResponseData, start: usize) -> u32 {
+        let mut b = extract(resp, start, 4);
+        b.reverse();
+        let bstr = as_boolean(b);
+        bitstr_to_u32(&bstr)
+    }
+
+    fn u64_at(resp: &ResponseData, start: usize) -> u64 {
+        let mut b = extract(resp, start, 8);
+        b.reverse();
+        let bstr = as_boolean(b);
+        bitstr_to_u64(&bstr)
+    }
 }
 
 // impl ResponseMessage {
@@ -325,6 +570,12 @@ fn as_ascii(arr: Vec<u8>) -> String {
     str::from_utf8(&arr).unwrap().to_string()
 }
 
+// Labels are fixed width, padded with trailing nulls.
+fn as_label(arr: Vec<u8>) -> String {
+    let end = arr.iter().position(|&b| b == 0).unwrap_or(arr.len());
+    String::from_utf8_lossy(&arr[..end]).to_string()
+}
+
 fn as_boolean(v: Vec<u8>) -> String {
     let mut s = "".to_string();
     for b in v {
@@ -359,9 +610,38 @@ fn bitstr_to_u32(bits: &str) -> u32 {
         .fold(0, |acc, b| (acc << 1) + if *b == 48 { 0 } else { 1 })
 }
 
+fn bitstr_to_u64(bits: &str) -> u64 {
+    bits.as_bytes()
+        .iter()
+        .fold(0, |acc, b| (acc << 1) + if *b == 48 { 0 } else { 1 })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{as_ascii, as_base10, as_boolean, as_hex, bitstr_to_u32, extract, ResponseData};
+    use super::{
+        as_ascii, as_base10, as_boolean, as_hex, as_label, bitstr_to_u32, extract, parse_response,
+        Payload, ResponseData,
+    };
+    use colour::Hsbk;
+    use effect::{
+        Direction, MultiZoneEffect, MultiZoneEffectSettings, TileEffect, TileEffectSettings,
+    };
+    use messages;
+    use std::time::Duration;
+
+    // Builds a message with a header from device D0:73:D5:01:02:03.
+    fn message(message_type: u16, payload: &[u8]) -> ResponseData {
+        let size = 36 + payload.len();
+        let mut data = vec![0u8; 36];
+        data[0] = size as u8;
+        data[1] = (size >> 8) as u8;
+        data[3] = 0x14;
+        data[8..14].clone_from_slice(&[0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03]);
+        data[32] = message_type as u8;
+        data[33] = (message_type >> 8) as u8;
+        data.extend_from_slice(payload);
+        ResponseData(data)
+    }
 
     #[test]
     fn test_extract() {
@@ -396,4 +676,255 @@ mod tests {
             "D1:72:D6:14:E0:0E:00:00"
         );
     }
+
+    #[test]
+    fn test_as_label() {
+        assert_eq!(as_label(vec![68, 101, 115, 107, 0, 0, 0]), "Desk");
+        assert_eq!(as_label(vec![68, 101, 115, 107]), "Desk");
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut resp = message(22, &[0xFF, 0xFF]);
+        resp.0[4..8].clone_from_slice(&[0xEF, 0xBE, 0xAD, 0xDE]);
+        resp.0[16..22].clone_from_slice(&[0xFF, 0xFE, 0, 0, 0, 0]);
+        resp.0[22] = 0x03;
+        resp.0[23] = 200;
+        resp.0[24..32].clone_from_slice(&[8, 7, 6, 5, 4, 3, 2, 0xF1]);
+        resp.0[34..36].clone_from_slice(&[0xFF, 0xFF]);
+
+        let resp = parse_response(resp);
+        let header = &resp.header;
+        assert_eq!(header.frame.size, 38);
+        assert_eq!(header.frame.protocol, 1024);
+        assert!(header.frame.addressable);
+        assert!(!header.frame.tagged);
+        assert_eq!(header.frame.origin, 0);
+        assert_eq!(header.frame.source, 0xDEAD_BEEF);
+        assert_eq!(header.frame_address.reserved, [0, 0, 0, 0, 0xFE, 0xFF]);
+        assert!(header.frame_address.ack_required);
+        assert!(header.frame_address.res_required);
+        assert_eq!(header.protocol_header.reserved, 0xF102_0304_0506_0708);
+        assert_eq!(header.protocol_header.reserved_2, 0xFFFF);
+        assert_eq!(resp.source, 0xDEAD_BEEF);
+        assert_eq!(resp.sequence_number, 200);
+        assert_eq!(resp.message_type, 22);
+    }
+
+    #[test]
+    fn test_parse_state_power() {
+        let resp = parse_response(message(22, &[0xFF, 0xFF]));
+        assert_eq!(resp.target, [0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03, 0, 0]);
+        match resp.payload {
+            Payload::StatePower(p) => assert_eq!(p.level, 65535),
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state_version() {
+        let resp = parse_response(message(33, &[1, 0, 0, 0, 31, 0, 0, 0, 0x4D, 0, 2, 0]));
+        match resp.payload {
+            Payload::StateVersion(p) => {
+                assert_eq!(p.vendor, 1);
+                assert_eq!(p.product, 31);
+                assert_eq!(p.version, 0x0002_004D);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state_wifi_info_and_info() {
+        let mut payload = 0.0001f32.to_le_bytes().to_vec();
+        payload.resize(14, 0);
+        match parse_response(message(17, &payload)).payload {
+            Payload::StateWifiInfo(p) => assert_eq!(p.rssi(), -40.0),
+            p => panic!("unexpected payload: {:?}", p),
+        }
+
+        let mut payload = vec![0; 8];
+        payload.extend_from_slice(&90_000_000_000u64.to_le_bytes());
+        payload.extend_from_slice(&5_000_000_000u64.to_le_bytes());
+        match parse_response(message(35, &payload)).payload {
+            Payload::StateInfo(p) => {
+                assert_eq!(p.uptime, Duration::from_secs(90));
+                assert_eq!(p.downtime, Duration::from_secs(5));
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state_group() {
+        let mut payload = vec![0xAB; 16];
+        let mut label = b"Kitchen".to_vec();
+        label.resize(32, 0);
+        payload.extend_from_slice(&label);
+        payload.extend_from_slice(&[0x01, 0, 0, 0, 0, 0, 0, 0x10]);
+
+        let resp = parse_response(message(53, &payload));
+        match resp.payload {
+            Payload::StateGroup(p) => {
+                assert_eq!(p.label, "Kitchen");
+                assert_eq!(p.updated_at, 0x1000_0000_0000_0001);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state() {
+        let mut payload = vec![
+            0x55, 0x55, 0xFF, 0xFF, 0x00, 0x80, 0xAC, 0x0D, 0, 0, 0xFF, 0xFF,
+        ];
+        let mut label = b"Desk".to_vec();
+        label.resize(32, 0);
+        payload.extend_from_slice(&label);
+        payload.extend_from_slice(&[0; 8]);
+
+        let resp = parse_response(message(107, &payload));
+        match resp.payload {
+            Payload::State(p) => {
+                assert_eq!(p.hsbk.hue, 0x5555);
+                assert_eq!(p.hsbk.saturation, 0xFFFF);
+                assert_eq!(p.hsbk.brightness, 0x8000);
+                assert_eq!(p.hsbk.kelvin, 3500);
+                assert_eq!(p.power, 65535);
+                assert_eq!(p.label, "Desk");
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_short_payload() {
+        let resp = parse_response(message(107, &[0x55, 0x55]));
+        match resp.payload {
+            Payload::None(()) => (),
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_short_state_service() {
+        // A StateService a byte into its payload, from anyone on the network.
+        let data = message(3, &[1]);
+        assert_eq!(data.0.len(), 37);
+        let resp = parse_response(data);
+        match resp.payload {
+            Payload::None(()) => (),
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state_extended_color_zones() {
+        let mut payload = vec![16, 0, 8, 0, 2];
+        payload.extend_from_slice(&[0x55, 0x55, 0xFF, 0xFF, 0x00, 0x80, 0xAC, 0x0D]);
+        payload.extend_from_slice(&[0xAA, 0xAA, 0xFF, 0xFF, 0xFF, 0xFF, 0xAC, 0x0D]);
+        payload.resize(5 + 82 * 8, 0);
+
+        let resp = parse_response(message(512, &payload));
+        assert_eq!(resp.size, 697);
+        assert_eq!(resp.message_type, 512);
+        match resp.payload {
+            Payload::StateExtendedColorZones(p) => {
+                assert_eq!(p.count, 16);
+                assert_eq!(p.index, 8);
+                assert_eq!(p.colours.len(), 2);
+                assert_eq!(p.colours[1].hue, 0xAAAA);
+                assert_eq!(p.colours[1].brightness, 0xFFFF);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_state_device_chain() {
+        // Two tiles, each laid out as accel_meas_x, y and z (i16), reserved (2),
+        // user_x and user_y (f32), width, height, then the rest.
+        let tile = |width: u8, height: u8| {
+            let mut t = vec![];
+            for accel in &[100i16, -200, 300] {
+                t.extend_from_slice(&accel.to_le_bytes());
+            }
+            t.extend_from_slice(&[0, 0]);
+            t.extend_from_slice(&1.5f32.to_le_bytes());
+            t.extend_from_slice(&(-2.5f32).to_le_bytes());
+            t.push(width);
+            t.push(height);
+            t.resize(55, 0xFF);
+            t
+        };
+        let mut payload = vec![0u8];
+        payload.extend(tile(8, 8));
+        payload.extend(tile(5, 6));
+        payload.resize(881, 0);
+        payload.push(2);
+
+        let resp = parse_response(message(702, &payload));
+        match resp.payload {
+            Payload::StateDeviceChain(p) => {
+                assert_eq!(p.tiles.len(), 2);
+                assert_eq!(p.tiles[0].width, 8);
+                assert_eq!(p.tiles[1].width, 5);
+                assert_eq!(p.tiles[1].height, 6);
+            }
+            p => panic!("unexpected payload: {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_multizone_effect() {
+        let settings = MultiZoneEffectSettings {
+            instance_id: 0x0102_0304,
+            effect: MultiZoneEffect::Move(Direction::Left),
+            speed: Duration::from_millis(3000),
+            duration: Some(Duration::from_secs(60)),
+        };
+        // StateMultiZoneEffect has the same layout as SetMultiZoneEffect.
+        let payload = messages::set_multizone_effect_payload(&settings).0;
+        assert_eq!(payload.len(), 59);
+
+        match parse_response(message(509, &payload)).payload {
+            Payload::StateMultiZoneEffect(p) => assert_eq!(p, settings),
+            p => panic!("unexpected payload {:?}", p),
+        }
+    }
+
+    #[test]
+    fn test_parse_tile_effect() {
+        let settings = TileEffectSettings {
+            instance_id: 7,
+            effect: TileEffect::Morph,
+            speed: Duration::from_millis(2500),
+            duration: None,
+            palette: vec![Hsbk::from_degrees(0.0, 100.0, 100.0, 3500); 3],
+        };
+        // StateTileEffect has one reserved byte at the start, where
+        // SetTileEffect has two.
+        let payload = messages::set_tile_effect_payload(&settings).0;
+        assert_eq!(payload.len(), 188);
+
+        match parse_response(message(720, &payload[1..])).payload {
+            Payload::StateTileEffect(p) => assert_eq!(p, settings),
+            p => panic!("unexpected payload {:?}", p),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let resp = parse_response(message(22, &[0xFF, 0xFF]));
+        assert_eq!(
+            serde_json::to_value(&resp.payload).unwrap(),
+            serde_json::json!({"type": "state_power", "value": {"level": 65535}})
+        );
+
+        let json = serde_json::to_string(&resp).unwrap();
+        let parsed: super::Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.header, resp.header);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
 }
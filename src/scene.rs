@@ -0,0 +1,406 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use client::Client;
+use colour::Hsbk;
+use convert;
+use group::{self, GroupReport, LightResult};
+use light::Light;
+use messages;
+use registry::RegisteredDevice;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of the state of a set of lights, which can be restored later.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scene {
+    pub name: String,
+    pub lights: Vec<LightSnapshot>,
+}
+
+/// The state of one light. Zones are only captured from multizone lights, and
+/// tiles only from matrix lights.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LightSnapshot {
+    pub socket_addr: SocketAddr,
+    pub target: [u8; 8],
+    pub power: u16,
+    pub colour: Hsbk,
+    pub zones: Vec<Hsbk>,
+    pub tiles: Vec<TileSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TileSnapshot {
+    pub index: u8,
+    pub width: u8,
+    pub colours: Vec<Hsbk>,
+}
+
+impl Scene {
+    /// Captures the state of each light, failing if any light can't be captured.
+    pub fn capture(name: &str, lights: &[Light]) -> Result<Scene, io::Error> {
+        let snapshots: Vec<Result<LightSnapshot, io::Error>> = thread::scope(|s| {
+            let handles: Vec<_> = lights.iter().map(|l| s.spawn(move || capture(l))).collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("scene worker panicked"))
+                .collect()
+        });
+
+        Ok(Scene {
+            name: name.to_string(),
+            lights: snapshots.into_iter().collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Restores each light to its captured state, over the duration in milliseconds.
+    /// Colours are clamped to the products of the lights found in `devices`.
+    pub fn restore(
+        &self,
+        client: &Arc<Client>,
+        devices: &[RegisteredDevice],
+        duration: u32,
+    ) -> GroupReport {
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .lights
+                .iter()
+                .map(|snapshot| {
+                    s.spawn(move || {
+                        let light = match devices.iter().find(|d| d.target == snapshot.target) {
+                            Some(device) => Light::from_registered(client.clone(), device),
+                            None => {
+                                Light::new(client.clone(), snapshot.socket_addr, snapshot.target)
+                            }
+                        };
+                        LightResult {
+                            socket_addr: snapshot.socket_addr,
+                            target: snapshot.target,
+                            outcome: group::outcome(restore(&light, snapshot, duration)),
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("scene worker panicked"))
+                .collect()
+        });
+
+        GroupReport { results }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, io::Error> {
+        Scene::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a scene from the text format written by `save`:
+    ///
+    /// ```text
+    /// scene Evening
+    /// light D0:73:D5:01:02:03:00:00 192.168.1.10:56700 65535 21845,65535,32768,3500
+    /// zones 21845,65535,32768,3500 43690,65535,32768,3500
+    /// tile 0 8 21845,65535,32768,3500 ...
+    /// ```
+    ///
+    /// Zones and tiles belong to the light above them.
+    pub fn parse(s: &str) -> Result<Scene, io::Error> {
+        let mut scene = Scene {
+            name: String::new(),
+            lights: vec![],
+        };
+
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = match line.find(' ') {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            let err = |msg: &str| invalid(&format!("line {}: {}", n + 1, msg));
+
+            match keyword {
+                "scene" => scene.name = rest.to_string(),
+                "light" => {
+                    if fields.len() != 4 {
+                        return Err(err("expected mac, address, power and colour"));
+                    }
+                    scene.lights.push(LightSnapshot {
+                        target: convert::mac_to_target(fields[0])
+                            .ok_or_else(|| err("bad mac address"))?,
+                        socket_addr: fields[1].parse().map_err(|_| err("bad address"))?,
+                        power: fields[2].parse().map_err(|_| err("bad power"))?,
+                        colour: parse_hsbk(fields[3]).ok_or_else(|| err("bad colour"))?,
+                        zones: vec![],
+                        tiles: vec![],
+                    });
+                }
+                "zones" => {
+                    let zones = fields
+                        .iter()
+                        .map(|f| parse_hsbk(f))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| err("bad zone colour"))?;
+                    match scene.lights.last_mut() {
+                        Some(l) => l.zones = zones,
+                        None => return Err(err("zones before any light")),
+                    }
+                }
+                "tile" => {
+                    if fields.len() < 2 {
+                        return Err(err("expected index, width and colours"));
+                    }
+                    let tile = TileSnapshot {
+                        index: fields[0].parse().map_err(|_| err("bad tile index"))?,
+                        width: fields[1].parse().map_err(|_| err("bad tile width"))?,
+                        colours: fields[2..]
+                            .iter()
+                            .map(|f| parse_hsbk(f))
+                            .collect::<Option<Vec<_>>>()
+                            .ok_or_else(|| err("bad tile colour"))?,
+                    };
+                    match scene.lights.last_mut() {
+                        Some(l) => l.tiles.push(tile),
+                        None => return Err(err("tile before any light")),
+                    }
+                }
+                _ => return Err(err(&format!("unknown keyword {:?}", keyword))),
+            }
+        }
+
+        Ok(scene)
+    }
+}
+
+impl fmt::Display for Scene {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "scene {}", self.name)?;
+        for l in &self.lights {
+            writeln!(
+                f,
+                "light {} {} {} {}",
+                convert::target_to_mac(l.target),
+                l.socket_addr,
+                l.power,
+                format_hsbk(&l.colour)
+            )?;
+            if !l.zones.is_empty() {
+                writeln!(f, "zones {}", format_hsbks(&l.zones))?;
+            }
+            for t in &l.tiles {
+                writeln!(
+                    f,
+                    "tile {} {} {}",
+                    t.index,
+                    t.width,
+                    format_hsbks(&t.colours)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn capture(light: &Light) -> Result<LightSnapshot, io::Error> {
+    let state = light.state()?;
+    let product = light.product()?;
+
+    let zones = match product {
+        Some(p) if p.multizone => light.zones()?,
+        _ => vec![],
+    };
+
+    let mut tiles = vec![];
+    if let Some(p) = product {
+        if p.matrix {
+            for (i, t) in light.tiles()?.iter().enumerate() {
+                tiles.push(TileSnapshot {
+                    index: i as u8,
+                    width: t.width,
+                    colours: light.tile_colours(i as u8, t.width)?,
+                });
+            }
+        }
+    }
+
+    Ok(LightSnapshot {
+        socket_addr: light.socket_addr(),
+        target: light.target(),
+        power: if state.power { 65535 } else { 0 },
+        colour: state.hsbk,
+        zones,
+        tiles,
+    })
+}
+
+// Colours are restored before power, so a light that was off doesn't come on in
+// the wrong colour, and one that was on fades in to the right colour.
+fn restore(light: &Light, snapshot: &LightSnapshot, duration: u32) -> Result<(), io::Error> {
+    light.set_color(&snapshot.colour, duration)?;
+
+    if !snapshot.zones.is_empty() {
+        light.set_zones(&snapshot.zones, duration)?;
+    }
+    for t in &snapshot.tiles {
+        light.set_tile_colours(t.index, t.width, &t.colours, duration)?;
+    }
+
+    let power = messages::set_light_power_payload(snapshot.power, duration);
+    light
+        .client()
+        .set(light.socket_addr(), light.target(), 117, power)
+        .map(|_| ())
+}
+
+fn format_hsbk(c: &Hsbk) -> String {
+    format!("{},{},{},{}", c.hue, c.saturation, c.brightness, c.kelvin)
+}
+
+fn format_hsbks(colours: &[Hsbk]) -> String {
+    colours
+        .iter()
+        .map(format_hsbk)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn parse_hsbk(s: &str) -> Option<Hsbk> {
+    let parts = s
+        .split(',')
+        .map(|p| p.parse::<u16>().ok())
+        .collect::<Option<Vec<u16>>>()?;
+    if parts.len() != 4 {
+        return None;
+    }
+
+    Some(Hsbk {
+        hue: parts[0],
+        saturation: parts[1],
+        brightness: parts[2],
+        kelvin: parts[3],
+    })
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LightSnapshot, Scene, TileSnapshot};
+    use client::Client;
+    use colour::Hsbk;
+    use registry::RegisteredDevice;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn colour(hue: u16) -> Hsbk {
+        Hsbk {
+            hue,
+            saturation: 65535,
+            brightness: 32768,
+            kelvin: 3500,
+        }
+    }
+
+    #[test]
+    fn test_scene_text_round_trip() {
+        let scene = Scene {
+            name: "Before doorbell".to_string(),
+            lights: vec![
+                LightSnapshot {
+                    socket_addr: "192.168.1.10:56700".parse().unwrap(),
+                    target: [0xD0, 0x73, 0xD5, 1, 2, 3, 0, 0],
+                    power: 65535,
+                    colour: colour(21845),
+                    zones: vec![colour(0), colour(100), colour(200)],
+                    tiles: vec![],
+                },
+                LightSnapshot {
+                    socket_addr: "192.168.1.11:56700".parse().unwrap(),
+                    target: [0xD0, 0x73, 0xD5, 1, 2, 4, 0, 0],
+                    power: 0,
+                    colour: colour(43690),
+                    zones: vec![],
+                    tiles: vec![TileSnapshot {
+                        index: 0,
+                        width: 8,
+                        colours: (0..64).map(colour).collect(),
+                    }],
+                },
+            ],
+        };
+
+        let parsed = Scene::parse(&scene.to_string()).unwrap();
+        assert_eq!(parsed, scene);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&scene).unwrap();
+            assert_eq!(serde_json::from_str::<Scene>(&json).unwrap(), scene);
+        }
+    }
+
+    #[test]
+    fn test_scene_parse_errors() {
+        let err = Scene::parse("scene x\nzones 1,2,3,4").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: zones before any light");
+
+        let err = Scene::parse("light D0:73 192.168.1.10:56700 0 1,2,3,4").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: bad mac address");
+    }
+
+    #[test]
+    fn test_restore_clamps_kelvin() {
+        // A Mini White to Warm, which goes no cooler than 4000K.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device
+            .set_read_timeout(Some(Duration::from_millis(1000)))
+            .unwrap();
+        let target = [0xd0, 0x73, 0xd5, 1, 2, 3, 0, 0];
+        let mut registered =
+            RegisteredDevice::new(String::new(), target, device.local_addr().unwrap());
+        registered.vendor = Some(1);
+        registered.product = Some(50);
+        let scene = Scene {
+            name: "cool".to_string(),
+            lights: vec![LightSnapshot {
+                socket_addr: device.local_addr().unwrap(),
+                target,
+                power: 65535,
+                colour: Hsbk::new(0, 0, 65535, 9000),
+                zones: vec![],
+                tiles: vec![],
+            }],
+        };
+
+        let client = Arc::new(
+            Client::new()
+                .unwrap()
+                .with_timeout(Duration::from_millis(50))
+                .with_retries(0),
+        );
+        scene.restore(&client, &[registered], 0);
+        let mut buf = [0; 1024];
+        device.recv_from(&mut buf).unwrap();
+        assert_eq!(u16::from_le_bytes([buf[43], buf[44]]), 4000);
+    }
+}
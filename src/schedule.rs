@@ -0,0 +1,825 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use client::Client;
+use colour::Hsbk;
+use group::{Delivery, GroupReport, LightGroup};
+use light::Light;
+use registry::RegisteredDevice;
+use scene::Scene;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sun::Location;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const EVERY_DAY: u8 = 0x7f;
+
+// A job found due more than this long ago, after the machine slept or the
+// clock jumped forward, is skipped rather than run late.
+const MISSED: Duration = Duration::from_secs(300);
+
+/// When a job runs, in local time. Parsed from the first five fields of a
+/// crontab line, such as `30 6 * * mon-fri`, or from sunrise or sunset moved by
+/// an offset, on the days of the week given, such as `sunset-30m` or
+/// `sunrise+1h15m sat,sun`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum When {
+    Cron(Cron),
+    Sun {
+        event: SunEvent,
+        /// In minutes, negative for before the event.
+        offset: i32,
+        /// A bit for each day, from Sunday in bit 0.
+        weekdays: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// The minute, hour, day of month, month and day of week fields of a crontab
+/// line, each a set of bits. As in cron, when both days are restricted, a day
+/// matching either will do.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn matches_day(&self, month: u32, day: u32, weekday: u32) -> bool {
+        if self.months & 1 << month == 0 {
+            return false;
+        }
+        let day = self.days & 1 << day != 0;
+        let weekday = self.weekdays & 1 << weekday != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+impl When {
+    /// The first time after `after` that this matches, for local time
+    /// `utc_offset` minutes ahead of UTC. Sunrise and sunset need a location,
+    /// and are None without one, or if the sun neither rises nor sets for a year.
+    pub fn next_after(
+        &self,
+        after: SystemTime,
+        utc_offset: i32,
+        location: Option<&Location>,
+    ) -> Option<SystemTime> {
+        let offset = i64::from(utc_offset) * 60;
+        let after = unix_seconds(after);
+        let today = (after + offset).div_euclid(86400);
+
+        match *self {
+            When::Cron(ref cron) => {
+                // Long enough to reach the next 29th of February.
+                for day in today..today + 8 * 366 {
+                    let (_, month, day_of_month) = civil_from_days(day);
+                    if !cron.matches_day(month, day_of_month, weekday(day)) {
+                        continue;
+                    }
+                    for minute in 0..24 * 60 {
+                        if cron.hours & 1 << (minute / 60) == 0
+                            || cron.minutes & 1 << (minute % 60) == 0
+                        {
+                            continue;
+                        }
+                        let t = day * 86400 + minute as i64 * 60 - offset;
+                        if t > after {
+                            return Some(from_unix_seconds(t));
+                        }
+                    }
+                }
+                None
+            }
+            When::Sun {
+                event,
+                offset: minutes,
+                weekdays,
+            } => {
+                let location = location?;
+                // From yesterday, as an offset can carry an event into the next day.
+                for day in today - 1..today + 367 {
+                    if weekdays & 1 << weekday(day) == 0 {
+                        continue;
+                    }
+                    let at = match event {
+                        SunEvent::Sunrise => location.sunrise(day),
+                        SunEvent::Sunset => location.sunset(day),
+                    };
+                    if let Some(at) = at {
+                        let t = at + i64::from(minutes) * 60;
+                        if t > after {
+                            return Some(from_unix_seconds(t));
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+impl FromStr for When {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<When, String> {
+        let s = s.trim();
+        let bad = |e: String| format!("bad schedule {:?}: {}", s, e);
+        let lower = s.to_ascii_lowercase();
+
+        for &(name, event) in &[("sunrise", SunEvent::Sunrise), ("sunset", SunEvent::Sunset)] {
+            if !lower.starts_with(name) {
+                continue;
+            }
+            let mut rest = lower[name.len()..].splitn(2, char::is_whitespace);
+            let offset = parse_offset(rest.next().unwrap_or("")).map_err(bad)?;
+            let weekdays = match rest.next().map(str::trim) {
+                None | Some("") => EVERY_DAY,
+                Some(days) => weekdays(field(days, 0, 7, &WEEKDAYS).map_err(bad)?),
+            };
+            return Ok(When::Sun {
+                event,
+                offset,
+                weekdays,
+            });
+        }
+
+        let fields: Vec<&str> = lower.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(bad(
+                "expected minute, hour, day of month, month and day of week, or sunrise or sunset"
+                    .to_string(),
+            ));
+        }
+        Ok(When::Cron(Cron {
+            minutes: field(fields[0], 0, 59, &[]).map_err(bad)?,
+            hours: field(fields[1], 0, 23, &[]).map_err(bad)?,
+            days: field(fields[2], 1, 31, &[]).map_err(bad)?,
+            months: field(fields[3], 1, 12, &MONTHS).map_err(bad)?,
+            weekdays: u64::from(weekdays(field(fields[4], 0, 7, &WEEKDAYS).map_err(bad)?)),
+            // As in Vixie cron, a field starting with * doesn't count as
+            // restricting the day, even when stepped.
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        }))
+    }
+}
+
+impl fmt::Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            When::Cron(ref cron) => write!(
+                f,
+                "{} {} {} {} {}",
+                format_field(cron.minutes, 0, 59, &[]),
+                format_field(cron.hours, 0, 23, &[]),
+                format_field(cron.days, 1, 31, &[]),
+                format_field(cron.months, 1, 12, &MONTHS),
+                format_field(cron.weekdays, 0, 6, &WEEKDAYS)
+            ),
+            When::Sun {
+                event,
+                offset,
+                weekdays,
+            } => {
+                match event {
+                    SunEvent::Sunrise => write!(f, "sunrise")?,
+                    SunEvent::Sunset => write!(f, "sunset")?,
+                }
+                if offset != 0 {
+                    let sign = if offset < 0 { '-' } else { '+' };
+                    let (hours, minutes) = (offset.abs() / 60, offset.abs() % 60);
+                    match (hours, minutes) {
+                        (0, m) => write!(f, "{}{}m", sign, m)?,
+                        (h, 0) => write!(f, "{}{}h", sign, h)?,
+                        (h, m) => write!(f, "{}{}h{}m", sign, h, m)?,
+                    }
+                }
+                if weekdays != EVERY_DAY {
+                    write!(f, " {}", format_field(u64::from(weekdays), 0, 6, &WEEKDAYS))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// One crontab field, of comma separated values, ranges and steps, such as
+// `*/15`, `1-5` or `jan,jul`, as a bit for each value. Names stand for the
+// values from min up.
+fn field(s: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |v: &str| -> Result<u32, String> {
+        if let Some(i) = names.iter().position(|n| *n == v) {
+            return Ok(min + i as u32);
+        }
+        match v.parse::<u32>() {
+            Ok(n) if n >= min && n <= max => Ok(n),
+            _ => Err(format!("{:?} is not from {} to {}", v, min, max)),
+        }
+    };
+
+    let mut bits = 0u64;
+    for part in s.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(i) => match part[i + 1..].parse::<u32>() {
+                Ok(step) if step > 0 => (&part[..i], step),
+                _ => return Err(format!("bad step in {:?}", part)),
+            },
+            None => (part, 1),
+        };
+        let (first, last) = match range.find('-') {
+            _ if range == "*" => (min, max),
+            Some(i) => (value(&range[..i])?, value(&range[i + 1..])?),
+            // A step from a single value runs to the end.
+            None if step > 1 => (value(range)?, max),
+            None => {
+                let v = value(range)?;
+                (v, v)
+            }
+        };
+        if first > last {
+            return Err(format!("{:?} runs backwards", range));
+        }
+        for v in (first..=last).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+// Folds day 7 into day 0, as both are Sunday.
+fn weekdays(bits: u64) -> u8 {
+    ((bits | bits >> 7) & u64::from(EVERY_DAY)) as u8
+}
+
+// A field's bits, back as values and ranges, such as `mon-fri` or `0,30`.
+fn format_field(bits: u64, min: u32, max: u32, names: &[&str]) -> String {
+    let set = |v: u32| v <= max && bits & 1 << v != 0;
+    if (min..=max).all(set) {
+        return "*".to_string();
+    }
+    let name = |v: u32| match names.get((v - min) as usize) {
+        Some(name) => name.to_string(),
+        None => v.to_string(),
+    };
+
+    let mut parts = vec![];
+    let mut v = min;
+    while v <= max {
+        if !set(v) {
+            v += 1;
+            continue;
+        }
+        let mut last = v;
+        while set(last + 1) {
+            last += 1;
+        }
+        match last - v {
+            0 => parts.push(name(v)),
+            1 => parts.extend(vec![name(v), name(last)]),
+            _ => parts.push(format!("{}-{}", name(v), name(last))),
+        }
+        v = last + 1;
+    }
+    parts.join(",")
+}
+
+// An offset such as `-30m`, `+1h` or `+1h15m`, in minutes.
+fn parse_offset(s: &str) -> Result<i32, String> {
+    if s.is_empty() {
+        return Ok(0);
+    }
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(format!("bad offset {:?}, expected e.g. -30m or +1h", s)),
+    };
+    let mut minutes = 0;
+    let mut number = String::new();
+    for c in s[1..].chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' if !number.is_empty() => {
+                let n: i32 = number.parse().map_err(|_| format!("bad offset {:?}", s))?;
+                minutes += if c == 'h' { n * 60 } else { n };
+                number.clear();
+            }
+            _ => return Err(format!("bad offset {:?}, expected e.g. -30m or +1h", s)),
+        }
+    }
+    if !number.is_empty() || minutes > 24 * 60 {
+        return Err(format!("bad offset {:?}, expected e.g. -30m or +1h", s));
+    }
+    Ok(sign * minutes)
+}
+
+/// A local date and time, to the minute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    /// From Sunday as 0.
+    pub weekday: u32,
+}
+
+impl LocalTime {
+    /// The local time at t, for local time `utc_offset` minutes ahead of UTC.
+    pub fn at(t: SystemTime, utc_offset: i32) -> LocalTime {
+        let local = unix_seconds(t) + i64::from(utc_offset) * 60;
+        let days = local.div_euclid(86400);
+        let minutes = local.rem_euclid(86400) / 60;
+        let (year, month, day) = civil_from_days(days);
+        LocalTime {
+            year,
+            month,
+            day,
+            hour: (minutes / 60) as u32,
+            minute: (minutes % 60) as u32,
+            weekday: weekday(days),
+        }
+    }
+}
+
+impl fmt::Display for LocalTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute
+        )
+    }
+}
+
+fn unix_seconds(t: SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    }
+}
+
+fn from_unix_seconds(t: i64) -> SystemTime {
+    if t >= 0 {
+        UNIX_EPOCH + Duration::from_secs(t as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(t.unsigned_abs())
+    }
+}
+
+// The year, month and day of a day counted from 1970-01-01, in the proleptic
+// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// 1970-01-01 was a Thursday.
+fn weekday(days: i64) -> u32 {
+    (days + 4).rem_euclid(7) as u32
+}
+
+/// What a job does to its lights.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Action {
+    Power(bool),
+    Colour(Hsbk),
+    /// Turns the lights on from darkness, then brightens them to the colour
+    /// over the job's duration, for waking up to.
+    Wake(Hsbk),
+    /// Restores a scene, to the lights it was captured from.
+    Scene(Scene),
+}
+
+/// An action, and when to take it.
+#[derive(Clone)]
+pub struct Job {
+    pub name: String,
+    pub when: When,
+    /// Not used by scenes, which carry their own lights.
+    pub lights: Vec<Light>,
+    pub action: Action,
+    /// The transition time, in milliseconds.
+    pub duration: u32,
+}
+
+/// A set of jobs, run at their times by a `Scheduler`.
+///
+/// Times are in local time, a fixed number of minutes ahead of UTC, so the
+/// offset needs changing when the clocks do. Sunrise and sunset are worked out
+/// from the schedule's location.
+pub struct Schedule {
+    client: Arc<Client>,
+    location: Option<Location>,
+    utc_offset: i32,
+    devices: Vec<RegisteredDevice>,
+    jobs: Vec<Job>,
+}
+
+impl Schedule {
+    pub fn new(client: Arc<Client>) -> Schedule {
+        Schedule {
+            client,
+            location: None,
+            utc_offset: 0,
+            devices: vec![],
+            jobs: vec![],
+        }
+    }
+
+    pub fn with_location(mut self, location: Location) -> Schedule {
+        self.location = Some(location);
+        self
+    }
+
+    /// Sets local time, in minutes ahead of UTC. The offset is fixed, so it
+    /// has to be set again when daylight saving time starts or ends.
+    pub fn with_utc_offset(mut self, minutes: i32) -> Schedule {
+        self.utc_offset = minutes;
+        self
+    }
+
+    /// The known devices, whose products the colours of restored scenes are
+    /// clamped to.
+    pub fn with_devices(mut self, devices: Vec<RegisteredDevice>) -> Schedule {
+        self.devices = devices;
+        self
+    }
+
+    /// Adds a job, failing if it's set by the sun but the schedule has no location.
+    pub fn add(&mut self, job: Job) -> Result<(), io::Error> {
+        if let When::Sun { .. } = job.when {
+            if self.location.is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} runs at {}, which needs a location", job.name, job.when),
+                ));
+            }
+        }
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// When the job next runs after `after`.
+    pub fn next_run(&self, job: &Job, after: SystemTime) -> Option<SystemTime> {
+        job.when
+            .next_after(after, self.utc_offset, self.location.as_ref())
+    }
+
+    /// The local time at t, in the schedule's time zone.
+    pub fn local_time(&self, t: SystemTime) -> LocalTime {
+        LocalTime::at(t, self.utc_offset)
+    }
+
+    /// Runs a job now, whatever its time.
+    pub fn run(&self, job: &Job) -> GroupReport {
+        let group = LightGroup::new(self.client.clone(), job.lights.clone());
+        match job.action {
+            Action::Power(on) => group.set_power(on, job.duration, Delivery::Unicast),
+            Action::Colour(ref hsbk) => group.set_color(hsbk, job.duration, Delivery::Unicast),
+            Action::Wake(ref hsbk) => {
+                let dark = Hsbk {
+                    brightness: 0,
+                    ..*hsbk
+                };
+                let report = group.set_color(&dark, 0, Delivery::Unicast);
+                if !report.all_succeeded() {
+                    return report;
+                }
+                let report = group.set_power(true, 0, Delivery::Unicast);
+                if !report.all_succeeded() {
+                    return report;
+                }
+                group.set_color(hsbk, job.duration, Delivery::Unicast)
+            }
+            Action::Scene(ref scene) => scene.restore(&self.client, &self.devices, job.duration),
+        }
+    }
+
+    /// Starts running each job at its times, from a background thread.
+    pub fn start(self) -> Scheduler {
+        let running = Arc::new(AtomicBool::new(true));
+        let worker = {
+            let running = running.clone();
+            thread::spawn(move || run_jobs(&self, &running))
+        };
+        Scheduler {
+            running,
+            worker: Some(worker),
+        }
+    }
+}
+
+/// Runs a schedule's jobs from a background thread, until stopped or dropped.
+pub struct Scheduler {
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    /// Stops running jobs, waiting for any job in progress.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        self.join();
+    }
+
+    /// Runs jobs on the calling thread's time, never returning: only `stop`
+    /// ends the scheduler, and it can't be called while this waits. For a
+    /// program that does nothing but run a schedule.
+    pub fn join(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_jobs(schedule: &Schedule, running: &AtomicBool) {
+    let now = SystemTime::now();
+    let mut next: Vec<Option<SystemTime>> = schedule
+        .jobs
+        .iter()
+        .map(|job| schedule.next_run(job, now))
+        .collect();
+
+    while running.load(Ordering::SeqCst) {
+        let now = SystemTime::now();
+        for (job, next) in schedule.jobs.iter().zip(next.iter_mut()) {
+            let due = match *next {
+                Some(due) if due <= now => due,
+                _ => continue,
+            };
+            if now.duration_since(due).unwrap_or_default() > MISSED {
+                warn!(
+                    "{}: skipped the run due at {}",
+                    job.name,
+                    schedule.local_time(due)
+                );
+            } else {
+                let report = schedule.run(job);
+                if report.all_succeeded() {
+                    info!("{}: ran {:?}", job.name, job.action);
+                } else {
+                    warn!(
+                        "{}: {} of {} lights failed",
+                        job.name,
+                        report.results.len() - report.succeeded().len(),
+                        report.results.len()
+                    );
+                }
+            }
+            *next = schedule.next_run(job, now);
+        }
+
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Job, LocalTime, Schedule, SunEvent, When};
+    use client::Client;
+    use colour::Hsbk;
+    use light::Light;
+    use std::net::UdpSocket;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use sun::Location;
+
+    // 2024-06-21, a Friday, in days since 1970-01-01.
+    const SOLSTICE: u64 = 19895;
+
+    fn at(day: u64, hour: u64, minute: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(day * 86400 + hour * 3600 + minute * 60)
+    }
+
+    fn next(when: &str, after: SystemTime, utc_offset: i32) -> SystemTime {
+        let london = Location::new(51.5074, -0.1278);
+        when.parse::<When>()
+            .unwrap()
+            .next_after(after, utc_offset, Some(&london))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_when() {
+        let tests = vec![
+            ("30 6 * * mon-fri", "30 6 * * mon-fri"),
+            ("*/15 9-17 * * *", "0,15,30,45 9-17 * * *"),
+            ("0 8,9,10,12 * * 6,0", "0 8-10,12 * * sun,sat"),
+            ("0 0 1,15 jan,JUL 0", "0 0 1,15 jan,jul sun"),
+            ("5/20 0 * * 7", "5,25,45 0 * * sun"),
+            ("sunset", "sunset"),
+            ("Sunset-30m", "sunset-30m"),
+            ("sunrise+75m sat,sun", "sunrise+1h15m sun,sat"),
+            ("sunrise-2h 1-5", "sunrise-2h mon-fri"),
+        ];
+        for (s, want) in tests {
+            assert_eq!(s.parse::<When>().unwrap().to_string(), want, "{}", s);
+        }
+        assert_eq!(
+            "sunset-30m".parse::<When>(),
+            Ok(When::Sun {
+                event: SunEvent::Sunset,
+                offset: -30,
+                weekdays: 0x7f,
+            })
+        );
+
+        let errors =
+            vec![
+            (
+                "30 6 * *",
+                "bad schedule \"30 6 * *\": expected minute, hour, day of month, month and day \
+                 of week, or sunrise or sunset",
+            ),
+            ("60 6 * * *", "bad schedule \"60 6 * * *\": \"60\" is not from 0 to 59"),
+            ("0 6 * * fri-mon", "bad schedule \"0 6 * * fri-mon\": \"fri-mon\" runs backwards"),
+            ("*/0 6 * * *", "bad schedule \"*/0 6 * * *\": bad step in \"*/0\""),
+            (
+                "sunset30m",
+                "bad schedule \"sunset30m\": bad offset \"30m\", expected e.g. -30m or +1h",
+            ),
+            ("sunrise mon-xyz", "bad schedule \"sunrise mon-xyz\": \"xyz\" is not from 0 to 7"),
+        ];
+        for (s, want) in errors {
+            assert_eq!(s.parse::<When>(), Err(want.to_string()), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_next_cron() {
+        // Friday at noon; the weekday alarm next goes off on Monday.
+        let friday = at(SOLSTICE, 12, 0);
+        assert_eq!(next("30 6 * * mon-fri", friday, 0), at(SOLSTICE + 3, 6, 30));
+        assert_eq!(next("*/15 * * * *", friday, 0), at(SOLSTICE, 12, 15));
+        assert_eq!(next("0 12 * * *", friday, 0), at(SOLSTICE + 1, 12, 0));
+
+        // 06:30 an hour ahead of UTC is 05:30 UTC.
+        assert_eq!(next("30 6 * * *", friday, 60), at(SOLSTICE + 1, 5, 30));
+
+        // With both days given, either will do: the 1st of July, or a Sunday.
+        assert_eq!(next("0 0 1 * sun", friday, 0), at(SOLSTICE + 2, 0, 0));
+        assert_eq!(
+            next("0 0 1 * sun", at(SOLSTICE + 9, 0, 0), 0),
+            at(SOLSTICE + 10, 0, 0)
+        );
+        assert_eq!(next("0 0 1 * *", friday, 0), at(SOLSTICE + 10, 0, 0));
+
+        // A stepped * restricts alongside the other field, rather than either
+        // doing: an odd day that's a Monday is the 1st of July, not the 23rd of
+        // June, and a 1st on an even weekday the 1st of August, a Thursday.
+        assert_eq!(next("0 0 */2 * mon", friday, 0), at(SOLSTICE + 10, 0, 0));
+        assert_eq!(next("0 0 1 * */2", friday, 0), at(SOLSTICE + 41, 0, 0));
+
+        // 2028-02-29 is the next leap day.
+        let leap_day = next("0 9 29 feb *", friday, 0);
+        assert_eq!(LocalTime::at(leap_day, 0).to_string(), "2028-02-29 09:00");
+    }
+
+    #[test]
+    fn test_next_sun() {
+        // London's sunset on the solstice is at 20:21 UTC.
+        let friday = at(SOLSTICE, 12, 0);
+        let sunset = next("sunset", friday, 0);
+        assert_eq!(LocalTime::at(sunset, 60).to_string(), "2024-06-21 21:21");
+        assert_eq!(
+            next("sunset-30m", friday, 0),
+            sunset - Duration::from_secs(30 * 60)
+        );
+        let weekend = next("sunset sat,sun", friday, 0);
+        assert_eq!(LocalTime::at(weekend, 60).to_string(), "2024-06-22 21:21");
+
+        // Sunrise is past, so the next is tomorrow's.
+        let sunrise = next("sunrise", friday, 0);
+        assert_eq!(LocalTime::at(sunrise, 60).to_string(), "2024-06-22 04:43");
+
+        assert_eq!(
+            "sunset"
+                .parse::<When>()
+                .unwrap()
+                .next_after(friday, 0, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_local_time() {
+        let t = LocalTime::at(at(SOLSTICE, 23, 30), 60);
+        assert_eq!(t.to_string(), "2024-06-22 00:30");
+        assert_eq!(t.weekday, 6);
+        assert_eq!(
+            LocalTime::at(at(0, 0, 0), -300).to_string(),
+            "1969-12-31 19:00"
+        );
+    }
+
+    #[test]
+    fn test_wake() {
+        // A stand-in light, acknowledging each message and noting its type.
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let responder = thread::spawn(move || {
+            let mut messages = vec![];
+            let mut buf = [0; 1024];
+            for _ in 0..3 {
+                let (len, from) = device.recv_from(&mut buf).unwrap();
+                messages.push(buf[..len].to_vec());
+                let mut reply = buf[0..36].to_vec();
+                reply[0] = 36;
+                reply[32] = 45;
+                reply[33] = 0;
+                device.send_to(&reply, from).unwrap();
+            }
+            messages
+        });
+
+        let client = Arc::new(
+            Client::new()
+                .unwrap()
+                .with_timeout(Duration::from_millis(500))
+                .with_retries(0),
+        );
+        let mut schedule = Schedule::new(client.clone());
+        let warm = Hsbk::new(0, 0, 52428, 2700);
+        let job = Job {
+            name: "wake".to_string(),
+            when: "30 6 * * mon-fri".parse().unwrap(),
+            lights: vec![Light::new(client.clone(), device_addr, [0; 8])],
+            action: Action::Wake(warm),
+            duration: 1_800_000,
+        };
+        schedule.add(job.clone()).unwrap();
+        assert!(schedule.run(&job).all_succeeded());
+
+        // Dimmed to nothing, turned on, then brightened over half an hour.
+        let messages = responder.join().unwrap();
+        let types: Vec<u8> = messages.iter().map(|m| m[32]).collect();
+        assert_eq!(types, vec![102, 117, 102]);
+        assert_eq!(&messages[0][41..43], &[0, 0]);
+        assert_eq!(&messages[2][41..43], &52428u16.to_le_bytes());
+        assert_eq!(&messages[2][45..49], &1_800_000u32.to_le_bytes());
+
+        // Sunset needs a location.
+        let job = Job {
+            when: "sunset".parse().unwrap(),
+            ..job
+        };
+        assert!(schedule.add(job.clone()).is_err());
+        let mut schedule = Schedule::new(client).with_location(Location::new(51.5, 0.0));
+        assert!(schedule.add(job).is_ok());
+    }
+}
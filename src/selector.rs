@@ -0,0 +1,178 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use convert;
+use registry::RegisteredDevice;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Picks devices out of the registry, with the LIFX HTTP API's selector syntax.
+///
+/// ```text
+/// all
+/// label:Desk Lamp
+/// group:Office
+/// location:Home
+/// id:d073d5010203
+/// ip:192.168.1.10
+/// ```
+///
+/// A selector without a prefix is taken as an id or ip if it looks like one,
+/// otherwise as a label. Labels, groups and locations match ignoring case.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+pub enum Selector {
+    All,
+    Label(String),
+    Group(String),
+    Location(String),
+    /// A device's MAC address, with or without colons.
+    Id([u8; 8]),
+    Ip(IpAddr),
+}
+
+impl Selector {
+    pub fn matches(&self, device: &RegisteredDevice) -> bool {
+        let same = |a: &Option<String>, b: &str| match *a {
+            Some(ref a) => a.eq_ignore_ascii_case(b),
+            None => false,
+        };
+
+        match *self {
+            Selector::All => true,
+            Selector::Label(ref l) => same(&device.label, l),
+            Selector::Group(ref g) => same(&device.group, g),
+            Selector::Location(ref l) => same(&device.location, l),
+            Selector::Id(ref target) => device.target[..6] == target[..6],
+            Selector::Ip(ip) => device.socket_addr.ip() == ip,
+        }
+    }
+
+    /// The devices the selector matches, in the order given.
+    pub fn select(&self, devices: &[RegisteredDevice]) -> Vec<RegisteredDevice> {
+        devices
+            .iter()
+            .filter(|d| self.matches(d))
+            .cloned()
+            .collect()
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Selector, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty selector".to_string());
+        }
+        if s == "all" {
+            return Ok(Selector::All);
+        }
+
+        let (kind, value) = match s.find(':') {
+            // A MAC address has colons too, but only two hex digits before the first.
+            Some(i) if is_kind(&s[..i]) => (&s[..i], &s[i + 1..]),
+            _ => ("", s),
+        };
+
+        match kind {
+            "label" => Ok(Selector::Label(value.to_string())),
+            "group" => Ok(Selector::Group(value.to_string())),
+            "location" => Ok(Selector::Location(value.to_string())),
+            "id" => parse_id(value)
+                .map(Selector::Id)
+                .ok_or_else(|| format!("{:?} is not a MAC address", value)),
+            "ip" => value
+                .parse()
+                .map(Selector::Ip)
+                .map_err(|_| format!("{:?} is not an IP address", value)),
+            "" => Ok(parse_id(value)
+                .map(Selector::Id)
+                .or_else(|| value.parse().ok().map(Selector::Ip))
+                .unwrap_or_else(|| Selector::Label(value.to_string()))),
+            _ => Err(format!(
+                "unknown selector {:?}, expected all, label:, group:, location:, id: or ip:",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Selector::All => write!(f, "all"),
+            Selector::Label(ref l) => write!(f, "label:{}", l),
+            Selector::Group(ref g) => write!(f, "group:{}", g),
+            Selector::Location(ref l) => write!(f, "location:{}", l),
+            Selector::Id(target) => write!(
+                f,
+                "id:{}",
+                convert::target_to_mac(target)[..17]
+                    .replace(':', "")
+                    .to_lowercase()
+            ),
+            Selector::Ip(ip) => write!(f, "ip:{}", ip),
+        }
+    }
+}
+
+fn is_kind(s: &str) -> bool {
+    s == "id" || s == "ip" || (s.len() > 2 && s.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+// A MAC address, as 12 hex digits, or 6 or 8 colon separated hex bytes.
+pub(crate) fn parse_id(s: &str) -> Option<[u8; 8]> {
+    if s.len() == 12 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+        let mut target = [0u8; 8];
+        for (i, b) in target.iter_mut().take(6).enumerate() {
+            *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        return Some(target);
+    }
+    if s.contains(':') {
+        return convert::mac_to_target(s);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selector;
+
+    #[test]
+    fn test_parse_selector() {
+        let mac = [0xD0, 0x73, 0xD5, 0x01, 0x02, 0x03, 0, 0];
+        let tests = vec![
+            ("all", Selector::All),
+            ("label:Desk Lamp", Selector::Label("Desk Lamp".to_string())),
+            ("group:Office", Selector::Group("Office".to_string())),
+            ("location:Home", Selector::Location("Home".to_string())),
+            ("id:d073d5010203", Selector::Id(mac)),
+            ("D0:73:D5:01:02:03", Selector::Id(mac)),
+            (
+                "ip:192.168.1.10",
+                Selector::Ip("192.168.1.10".parse().unwrap()),
+            ),
+            (
+                "192.168.1.10",
+                Selector::Ip("192.168.1.10".parse().unwrap()),
+            ),
+            ("Kitchen", Selector::Label("Kitchen".to_string())),
+        ];
+
+        for (s, want) in tests {
+            assert_eq!(s.parse::<Selector>(), Ok(want), "{}", s);
+        }
+
+        assert!("id:d073".parse::<Selector>().is_err());
+        assert!("colour:red".parse::<Selector>().is_err());
+        assert_eq!(Selector::Id(mac).to_string(), "id:d073d5010203");
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A place on Earth, in degrees, with north and east positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// The sun's elevation at sunrise and sunset, allowing for refraction and the
+// size of its disc.
+const HORIZON: f64 = -0.833;
+
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Location {
+        Location {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Sunrise on the day, counted in days since 1970-01-01, in seconds since
+    /// the Unix epoch. None when the sun doesn't rise or set that day, as in a
+    /// polar summer or winter.
+    pub fn sunrise(&self, day: i64) -> Option<i64> {
+        self.horizon_crossing(day, -1.0)
+    }
+
+    /// Sunset on the day, as for `sunrise`.
+    pub fn sunset(&self, day: i64) -> Option<i64> {
+        self.horizon_crossing(day, 1.0)
+    }
+
+    /// When the sun is highest on the day, as for `sunrise`. Unlike sunrise and
+    /// sunset, there is one every day.
+    pub fn solar_noon(&self, day: i64) -> i64 {
+        let midnight = day as f64 * 86400.0;
+        let sun = Sun::at(midnight + 43200.0 - self.longitude * 240.0);
+        let minutes = 720.0 - 4.0 * self.longitude - sun.equation_of_time;
+        (midnight + minutes * 60.0).round() as i64
+    }
+
+    /// The sun's elevation above the horizon, in degrees, ignoring refraction.
+    pub fn elevation(&self, at: SystemTime) -> f64 {
+        let t = unix_seconds(at);
+        let sun = Sun::at(t);
+        let minutes = t.rem_euclid(86400.0) / 60.0;
+        let solar_time = (minutes + sun.equation_of_time + 4.0 * self.longitude).rem_euclid(1440.0);
+        let hour_angle = (solar_time / 4.0 - 180.0).to_radians();
+
+        let lat = self.latitude.to_radians();
+        let cos_zenith = lat.sin() * sun.declination.sin()
+            + lat.cos() * sun.declination.cos() * hour_angle.cos();
+        90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+    }
+
+    // Solar noon on the day, less (-1) or plus (1) the time it takes the sun to
+    // cross the horizon, found from the sun's position at noon.
+    fn horizon_crossing(&self, day: i64, direction: f64) -> Option<i64> {
+        let midnight = day as f64 * 86400.0;
+        let sun = Sun::at(midnight + 43200.0 - self.longitude * 240.0);
+
+        let lat = self.latitude.to_radians();
+        let cos_hour_angle = (HORIZON.to_radians().sin() - lat.sin() * sun.declination.sin())
+            / (lat.cos() * sun.declination.cos());
+        if !(-1.0..=1.0).contains(&cos_hour_angle) {
+            return None;
+        }
+        let hour_angle = cos_hour_angle.acos().to_degrees();
+
+        let minutes =
+            720.0 - 4.0 * self.longitude - sun.equation_of_time + direction * 4.0 * hour_angle;
+        Some((midnight + minutes * 60.0).round() as i64)
+    }
+}
+
+// Where the sun is, as NOAA's solar calculator works it out.
+struct Sun {
+    // In radians.
+    declination: f64,
+    // How far apparent solar time runs ahead of mean solar time, in minutes.
+    equation_of_time: f64,
+}
+
+impl Sun {
+    fn at(unix_seconds: f64) -> Sun {
+        let julian_day = unix_seconds / 86400.0 + 2440587.5;
+        let t = (julian_day - 2451545.0) / 36525.0;
+
+        let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+        let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+        let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+        let m = mean_anomaly.to_radians();
+        let centre = m.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+            + (2.0 * m).sin() * (0.019993 - 0.000101 * t)
+            + (3.0 * m).sin() * 0.000289;
+        let omega = (125.04 - 1934.136 * t).to_radians();
+        let apparent_longitude =
+            (mean_longitude + centre - 0.00569 - 0.00478 * omega.sin()).to_radians();
+
+        let mean_obliquity =
+            23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+        let obliquity = (mean_obliquity + 0.00256 * omega.cos()).to_radians();
+        let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+        let y = (obliquity / 2.0).tan().powi(2);
+        let l = mean_longitude.to_radians();
+        let equation_of_time = 4.0
+            * (y * (2.0 * l).sin() - 2.0 * eccentricity * m.sin()
+                + 4.0 * eccentricity * y * m.sin() * (2.0 * l).cos()
+                - 0.5 * y * y * (4.0 * l).sin()
+                - 1.25 * eccentricity * eccentricity * (2.0 * m).sin())
+            .to_degrees();
+
+        Sun {
+            declination,
+            equation_of_time,
+        }
+    }
+}
+
+fn unix_seconds(t: SystemTime) -> f64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Location;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // 2024-06-21 and 2024-03-20, in days since 1970-01-01.
+    const SOLSTICE: i64 = 19895;
+    const EQUINOX: i64 = 19802;
+
+    fn near(got: Option<i64>, want: i64) {
+        let got = got.expect("no sunrise or sunset");
+        assert!((got - want).abs() <= 120, "got {}, want {}", got, want);
+    }
+
+    #[test]
+    fn test_sunrise_sunset() {
+        // London: 03:43 and 20:21 UTC on the solstice.
+        let london = Location::new(51.5074, -0.1278);
+        let midnight = SOLSTICE * 86400;
+        near(london.sunrise(SOLSTICE), midnight + 3 * 3600 + 43 * 60);
+        near(london.sunset(SOLSTICE), midnight + 20 * 3600 + 21 * 60);
+
+        // San Francisco: 12:48, and 03:35 the next day, UTC.
+        let sf = Location::new(37.7749, -122.4194);
+        near(sf.sunrise(SOLSTICE), midnight + 12 * 3600 + 48 * 60);
+        near(sf.sunset(SOLSTICE), midnight + 27 * 3600 + 35 * 60);
+
+        // Sydney, in midwinter: 20:59 the day before, and 06:53, UTC.
+        let sydney = Location::new(-33.8688, 151.2093);
+        near(sydney.sunrise(SOLSTICE), midnight - 3 * 3600 - 60);
+        near(sydney.sunset(SOLSTICE), midnight + 6 * 3600 + 53 * 60);
+
+        // 12:02 UTC, the equation of time making noon a couple of minutes late.
+        near(
+            Some(london.solar_noon(SOLSTICE)),
+            midnight + 12 * 3600 + 2 * 60,
+        );
+
+        // The midnight sun, and the polar night.
+        let tromso = Location::new(69.6492, 18.9553);
+        assert_eq!(tromso.sunrise(SOLSTICE), None);
+        assert_eq!(tromso.sunset(SOLSTICE), None);
+        assert_eq!(tromso.sunrise(SOLSTICE + 183), None);
+    }
+
+    #[test]
+    fn test_elevation() {
+        let at = |day: i64, seconds: i64| {
+            UNIX_EPOCH + Duration::from_secs((day * 86400 + seconds) as u64)
+        };
+
+        // Overhead at noon on the equator at the equinox, and below the horizon at midnight.
+        let quito = Location::new(0.0, 0.0);
+        let noon = quito.elevation(at(EQUINOX, 12 * 3600 + 7 * 60));
+        assert!(noon > 89.0, "{}", noon);
+        assert!(quito.elevation(at(EQUINOX, 0)) < -85.0);
+
+        // 90 - 51.5 + 23.4 at midsummer noon in London, and on the horizon at sunrise.
+        let london = Location::new(51.5074, -0.1278);
+        let noon = london.elevation(at(SOLSTICE, 12 * 3600 + 2 * 60));
+        assert!((noon - 61.9).abs() < 0.5, "{}", noon);
+        let sunrise = london.sunrise(SOLSTICE).unwrap();
+        let dawn = london.elevation(UNIX_EPOCH + Duration::from_secs(sunrise as u64));
+        assert!((dawn + 0.833).abs() < 0.1, "{}", dawn);
+    }
+}
@@ -0,0 +1,434 @@
+use std::io;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use json::Json;
+
+/// Parses a TOML document into the equivalent JSON, so that config files can
+/// be written in either.
+///
+/// Tables, arrays of tables, dotted keys, inline tables, arrays, strings,
+/// integers, floats and booleans are supported. Dates and times, and multi-line
+/// strings, are not.
+pub fn parse(s: &str) -> Result<Json, io::Error> {
+    let mut parser = Parser {
+        chars: s.chars().peekable(),
+        line: 1,
+    };
+    parser.document().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid TOML: line {}: {}", parser.line, e),
+        )
+    })
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+// Nesting deeper than this is refused, rather than overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+impl<'a> Parser<'a> {
+    fn document(&mut self) -> Result<Json, String> {
+        let mut root: Vec<(String, Json)> = vec![];
+        let mut current: Vec<String> = vec![];
+        // Tables given a [header], each of which may only be given one.
+        let mut defined: Vec<Vec<String>> = vec![];
+
+        loop {
+            self.skip_blank_lines();
+            match self.chars.peek() {
+                None => return Ok(Json::Object(root)),
+                Some(&'[') => {
+                    self.chars.next();
+                    let array = self.eat('[');
+                    let path = self.key()?;
+                    if !self.eat(']') || (array && !self.eat(']')) {
+                        return Err("expected ']' after a table name".to_string());
+                    }
+                    if array {
+                        // A new element, whose subtables are yet to be defined.
+                        defined.retain(|d| !d.starts_with(&path));
+                    } else if defined.contains(&path) {
+                        return Err(format!("table {} is defined twice", path.join(".")));
+                    } else {
+                        defined.push(path.clone());
+                    }
+                    self.end_of_line()?;
+
+                    if array {
+                        let (last, parent) = path.split_last().unwrap();
+                        let parent = table(&mut root, parent)?;
+                        match find(parent, last) {
+                            Some(&mut Json::Array(ref mut items)) => {
+                                items.push(Json::Object(vec![]))
+                            }
+                            Some(_) => return Err(format!("{} is not an array of tables", last)),
+                            None => {
+                                parent.push((last.clone(), Json::Array(vec![Json::Object(vec![])])))
+                            }
+                        }
+                    } else {
+                        table(&mut root, &path)?;
+                    }
+                    current = path;
+                }
+                Some(_) => {
+                    let path = self.key()?;
+                    self.skip_spaces();
+                    if !self.eat('=') {
+                        return Err("expected '=' after a key".to_string());
+                    }
+                    let value = self.value(0)?;
+                    self.end_of_line()?;
+
+                    let (last, parent) = path.split_last().unwrap();
+                    let mut full = current.clone();
+                    full.extend_from_slice(parent);
+                    insert(table(&mut root, &full)?, last, value)?;
+                }
+            }
+        }
+    }
+
+    // A dotted key, of bare or quoted parts.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut path = vec![];
+        loop {
+            self.skip_spaces();
+            let part = match self.chars.peek().cloned() {
+                Some('"') => {
+                    self.chars.next();
+                    self.basic_string()?
+                }
+                Some('\'') => {
+                    self.chars.next();
+                    self.literal_string()?
+                }
+                _ => {
+                    let mut part = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                            break;
+                        }
+                        part.push(c);
+                        self.chars.next();
+                    }
+                    if part.is_empty() {
+                        return Err("expected a key".to_string());
+                    }
+                    part
+                }
+            };
+            path.push(part);
+            self.skip_spaces();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err("nested too deeply".to_string());
+        }
+
+        self.skip_spaces();
+        match self.chars.peek().cloned() {
+            Some('"') => {
+                self.chars.next();
+                self.basic_string().map(Json::String)
+            }
+            Some('\'') => {
+                self.chars.next();
+                self.literal_string().map(Json::String)
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut items = vec![];
+                loop {
+                    self.skip_blank_lines();
+                    if self.eat(']') {
+                        return Ok(Json::Array(items));
+                    }
+                    items.push(self.value(depth + 1)?);
+                    self.skip_blank_lines();
+                    if !self.eat(',') {
+                        self.skip_blank_lines();
+                        if self.eat(']') {
+                            return Ok(Json::Array(items));
+                        }
+                        return Err("expected ',' or ']' in an array".to_string());
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut fields = vec![];
+                self.skip_spaces();
+                if self.eat('}') {
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let path = self.key()?;
+                    self.skip_spaces();
+                    if !self.eat('=') {
+                        return Err("expected '=' after a key".to_string());
+                    }
+                    let value = self.value(depth + 1)?;
+                    let (last, parent) = path.split_last().unwrap();
+                    insert(table(&mut fields, parent)?, last, value)?;
+                    self.skip_spaces();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err("expected ',' or '}' in an inline table".to_string()),
+                    }
+                }
+            }
+            Some(c) if c == '+' || c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_digit() || "+-.eE_".contains(c)) {
+                        break;
+                    }
+                    if c != '_' {
+                        number.push(c);
+                    }
+                    self.chars.next();
+                }
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("bad number {:?}", number))
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !c.is_ascii_alphabetic() {
+                        break;
+                    }
+                    word.push(c);
+                    self.chars.next();
+                }
+                match word.as_str() {
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => Err(format!("unexpected {:?}", word)),
+                }
+            }
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    // The rest of a "string", after the opening quote.
+    fn basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some(u) if u == 'u' || u == 'U' => {
+                        let len = if u == 'u' { 4 } else { 8 };
+                        let hex: String = self.chars.by_ref().take(len).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| format!("bad escape \\{}{}", u, hex))?;
+                        s.push(c);
+                    }
+                    _ => return Err("bad escape in a string".to_string()),
+                },
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    // The rest of a 'string', after the opening quote.
+    fn literal_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        match self.chars.peek().cloned() {
+            None | Some('\n') | Some('#') => Ok(()),
+            Some('\r') => Ok(()),
+            Some(c) => Err(format!("unexpected {:?} at the end of a line", c)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    // Skips whitespace, newlines and comments.
+    fn skip_blank_lines(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.chars.next();
+                }
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.chars.peek() == Some(&c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn find<'a>(fields: &'a mut [(String, Json)], key: &str) -> Option<&'a mut Json> {
+    fields.iter_mut().find(|f| f.0 == key).map(|f| &mut f.1)
+}
+
+// The table at the path, creating any tables missing along the way. A path
+// through an array of tables leads to its last table.
+fn table<'a>(
+    mut fields: &'a mut Vec<(String, Json)>,
+    path: &[String],
+) -> Result<&'a mut Vec<(String, Json)>, String> {
+    for key in path {
+        let i = match fields.iter().position(|f| f.0 == *key) {
+            Some(i) => i,
+            None => {
+                fields.push((key.clone(), Json::Object(vec![])));
+                fields.len() - 1
+            }
+        };
+        fields = match fields[i].1 {
+            Json::Object(ref mut f) => f,
+            Json::Array(ref mut items) => match items.last_mut() {
+                Some(&mut Json::Object(ref mut f)) => f,
+                _ => return Err(format!("{} is not a table", key)),
+            },
+            _ => return Err(format!("{} is not a table", key)),
+        };
+    }
+    Ok(fields)
+}
+
+fn insert(fields: &mut Vec<(String, Json)>, key: &str, value: Json) -> Result<(), String> {
+    if fields.iter().any(|f| f.0 == key) {
+        return Err(format!("{} is defined twice", key));
+    }
+    fields.push((key.to_string(), value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use json::Json;
+
+    #[test]
+    fn test_parse_toml() {
+        let doc = r#"
+# Lights
+default_duration = 1_500
+ratio = -0.5
+"quoted key" = 'C:\path'
+
+[colours]
+sunset = "hsb(20, 90%, 60%)\tkelvin:2700"
+
+[[devices]]
+mac = "d0:73:d5:01:02:03"   # the desk lamp
+tags = [
+    "desk",
+    "office",   # trailing commas are fine
+]
+
+[[devices]]
+mac = "d0:73:d5:01:02:04"
+position = { x = 1, y.z = true }
+
+[groups.office]
+members = []
+"#;
+        let want = Json::parse(
+            r#"{"default_duration": 1500, "ratio": -0.5, "quoted key": "C:\\path",
+                "colours": {"sunset": "hsb(20, 90%, 60%)\tkelvin:2700"},
+                "devices": [
+                    {"mac": "d0:73:d5:01:02:03", "tags": ["desk", "office"]},
+                    {"mac": "d0:73:d5:01:02:04", "position": {"x": 1, "y": {"z": true}}}
+                ],
+                "groups": {"office": {"members": []}}}"#,
+        )
+        .unwrap();
+        assert_eq!(parse(doc).unwrap(), want);
+
+        // Each element of an array of tables has its own subtables.
+        assert!(parse("[[a]]\n[a.b]\n[[a]]\n[a.b]").is_ok());
+    }
+
+    #[test]
+    fn test_parse_toml_errors() {
+        let tests = vec![
+            ("a = 1\na = 2", "invalid TOML: line 2: a is defined twice"),
+            ("a = \"abc", "invalid TOML: line 1: unterminated string"),
+            (
+                "a = 1 2",
+                "invalid TOML: line 1: unexpected '2' at the end of a line",
+            ),
+            (
+                "\n\n[a\n",
+                "invalid TOML: line 3: expected ']' after a table name",
+            ),
+            ("a = 1\n[a]", "invalid TOML: line 2: a is not a table"),
+            (
+                "[a]\nb = 1\n[c]\n[a]\nd = 2",
+                "invalid TOML: line 4: table a is defined twice",
+            ),
+            (
+                "[[a]]\n[a.b]\n[a.b]",
+                "invalid TOML: line 3: table a.b is defined twice",
+            ),
+            (
+                "a = 1979-05-27",
+                "invalid TOML: line 1: bad number \"1979-05-27\"",
+            ),
+        ];
+        for (s, want) in tests {
+            assert_eq!(parse(s).unwrap_err().to_string(), want, "{}", s);
+        }
+    }
+}